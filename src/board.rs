@@ -1,3 +1,7 @@
+use crate::i18n::{t, Lang};
+use crate::notation::{coord_index_to_full_width_latin, coord_index_to_japanese_numeral};
+use crate::settings::PieceStyles;
+
 use shogi::{square::Square, Color, Piece, PieceType};
 use std::collections::{HashMap, HashSet};
 use yew::prelude::*;
@@ -5,16 +9,44 @@ use yew::prelude::*;
 mod square;
 use square::SquareView;
 
+/// The contents of every square on the board, indexed by `Square::index()`.
+pub type Pieces = [Option<Piece>; 81];
+
 pub struct Board {
     props: BoardProps,
 }
 
+/// Whose promotion zone `square` is in: Black promotes on ranks a to c and White on
+/// ranks g to i.
+fn promotion_zone_of(square: Square) -> Option<Color> {
+    match square.rank() {
+        0..=2 => Some(Color::Black),
+        6..=8 => Some(Color::White),
+        _ => None,
+    }
+}
+
 #[derive(Properties, Clone, PartialEq)]
 pub struct BoardProps {
-    pub pieces: HashMap<Square, Piece>,
+    pub pieces: Pieces,
     pub ghost_piece: Option<Piece>,
     pub move_origin_candidates: HashSet<Square>,
     pub move_destination_candidates: HashSet<Square>,
+    /// Destination candidates that get the side to move out of check, only given while
+    /// it is in check.
+    pub check_escapes: HashSet<Square>,
+    /// Pieces of the side to move that are pinned to their king.
+    pub pinned_squares: HashSet<Square>,
+    /// Where the side not to move could go if it were their turn.
+    pub opponent_destinations: HashSet<Square>,
+    /// Squares the selected king would be in check on.
+    pub danger_squares: HashSet<Square>,
+    /// Squares that loading a position just changed.
+    pub changed_squares: HashSet<Square>,
+    /// Where the piece that just moved could go next, flashed briefly after the move.
+    pub mobility_preview: HashSet<Square>,
+    /// Whose pieces to keep at full strength, dimming the other side's.
+    pub focus_color: Option<Color>,
     pub move_origin: Option<Square>,
     pub move_destination: Option<Square>,
     pub previous_move_origin: Option<Square>,
@@ -22,8 +54,190 @@ pub struct BoardProps {
     pub is_asking_promotion_with_piece: Option<Piece>,
     pub is_white_in_check: bool,
     pub is_black_in_check: bool,
+    pub attack_counts: HashMap<Square, u8>,
+    pub trail_intensities: HashMap<Square, f64>,
+    pub blindfold: bool,
+    pub reveal_on_hover: bool,
+    pub flipped: bool,
+    pub piece_styles: PieceStyles,
+    pub show_coordinates: bool,
+    /// Draw the four decorative dots marking the promotion zones.
+    pub show_dots: bool,
+    pub show_promotion_zones: bool,
+    pub high_contrast: bool,
+    /// Label each square with its index, to check the iteration order and flipping.
+    pub show_square_indices: bool,
+    /// How thick the grid lines between squares are.
+    pub border_px: u32,
+    /// Space left around each piece inside its square.
+    pub inset_px: u32,
+    pub highlighted_squares: HashSet<Square>,
+    pub hovered_square: Option<Square>,
     pub on_square_click: Callback<Square>,
     pub on_choose_promote: Callback<bool>,
+    pub on_file_click: Callback<u8>,
+    pub on_rank_click: Callback<u8>,
+    pub on_square_hover: Callback<Option<Square>>,
+    /// Takes over the context menu when set, as edit mode does to clear squares.
+    pub on_square_right_click: Option<Callback<Square>>,
+    pub lang: Lang,
+}
+
+/// Must match the size of `.square` and the padding of `.board` in the stylesheet.
+const SQUARE_SIZE_PX: u8 = 50;
+const BOARD_PADDING_PX: u8 = 20;
+
+impl Board {
+    /// How many squares from the top-left a file or rank is drawn, which depends on the
+    /// way the board faces.
+    fn screen_offset(&self, index: u8, is_file: bool) -> u16 {
+        let from_top_left = if is_file != self.props.flipped {
+            8 - index
+        } else {
+            index
+        };
+        BOARD_PADDING_PX as u16 + from_top_left as u16 * SQUARE_SIZE_PX as u16
+    }
+
+    /// The screen position of the middle of the square, relative to the board.
+    fn square_center(&self, square: Square) -> (u16, u16) {
+        let half_square = SQUARE_SIZE_PX as u16 / 2;
+        (
+            self.screen_offset(square.file(), true) + half_square,
+            self.screen_offset(square.rank(), false) + half_square,
+        )
+    }
+
+    /// Points from the chosen piece to the candidate square under the pointer.
+    fn view_ghost_arrow(&self) -> Html {
+        let (from, to) = match (self.props.move_origin, self.props.hovered_square) {
+            (Some(from), Some(to))
+                if from != to && self.props.move_destination_candidates.contains(&to) =>
+            {
+                (from, to)
+            }
+            _ => return html! {},
+        };
+        let (x1, y1) = self.square_center(from);
+        let (x2, y2) = self.square_center(to);
+        html! {
+            <svg class="ghost-arrow">
+                <defs>
+                    <marker id="ghost-arrow-head" markerWidth="4" markerHeight="4" refX="2" refY="2" orient="auto">
+                        <path d="M0,0 L4,2 L0,4 z" />
+                    </marker>
+                </defs>
+                <line
+                    x1=x1.to_string()
+                    y1=y1.to_string()
+                    x2=x2.to_string()
+                    y2=y2.to_string()
+                    marker-end="url(#ghost-arrow-head)"
+                />
+            </svg>
+        }
+    }
+
+    fn view_square(&self, square: Square) -> Html {
+        let key = square.index();
+        let is_move_origin_candidate = self.props.move_origin_candidates.contains(&square);
+        let is_move_destination_candidate =
+            self.props.move_destination_candidates.contains(&square);
+        let is_move_origin = self.props.move_origin.contains(&square);
+        let is_move_destination = self.props.move_destination.contains(&square);
+        let is_previous_move_origin = self.props.previous_move_origin.contains(&square);
+        let is_previous_move_destination = self.props.previous_move_destination.contains(&square);
+        let is_asking_promotion_with_piece = is_move_destination
+            .then_some(())
+            .and(self.props.is_asking_promotion_with_piece);
+        let piece = self.props.pieces[square.index()];
+        let is_in_check = piece
+            .filter(|piece| piece.piece_type == PieceType::King)
+            .filter(|piece| match piece.color {
+                Color::White => self.props.is_white_in_check,
+                Color::Black => self.props.is_black_in_check,
+            })
+            .is_some();
+        let on_square_right_click = self.props.on_square_right_click.clone();
+        let on_context_menu = Callback::from(move |event: MouseEvent| {
+            if let Some(on_square_right_click) = &on_square_right_click {
+                event.prevent_default();
+                on_square_right_click.emit(square);
+            }
+        });
+        html! {
+            <SquareView
+                key=key
+                square=square
+                piece=piece
+                ghost_piece=self.props.ghost_piece
+                is_move_origin_candidate=is_move_origin_candidate
+                is_move_destination_candidate=is_move_destination_candidate
+                is_check_escape=self.props.check_escapes.contains(&square)
+                is_pinned=self.props.pinned_squares.contains(&square)
+                is_opponent_destination=self.props.opponent_destinations.contains(&square)
+                is_danger=self.props.danger_squares.contains(&square)
+                is_changed=self.props.changed_squares.contains(&square)
+                is_mobility_preview=self.props.mobility_preview.contains(&square)
+                is_dimmed=self.props.focus_color.map_or(false, |color| piece.map_or(false, |piece| piece.color != color))
+                is_move_origin=is_move_origin
+                is_move_destination=is_move_destination
+                is_previous_move_origin=is_previous_move_origin
+                is_previous_move_destination=is_previous_move_destination
+                is_asking_promotion_with_piece=is_asking_promotion_with_piece
+                is_in_check=is_in_check
+                attack_count=self.props.attack_counts.get(&square).copied().unwrap_or(0)
+                trail_intensity=self.props.trail_intensities.get(&square).copied().unwrap_or(0.0)
+                blindfold=self.props.blindfold
+                flipped=self.props.flipped
+                piece_styles=self.props.piece_styles
+                is_highlighted=self.props.highlighted_squares.contains(&square)
+                show_index=self.props.show_square_indices
+                border_px=self.props.border_px
+                inset_px=self.props.inset_px
+                high_contrast=self.props.high_contrast
+                promotion_zone=promotion_zone_of(square).filter(|_| self.props.show_promotion_zones)
+                on_click=self.props.on_square_click.reform(move |_| square)
+                on_mouse_enter=self.props.on_square_hover.reform(move |_| Some(square))
+                on_mouse_leave=self.props.on_square_hover.reform(|_| None)
+                on_context_menu=on_context_menu
+                on_choose_promote=self.props.on_choose_promote.clone()
+                lang=self.props.lang
+            />
+        }
+    }
+
+    fn view_coordinates(&self) -> Html {
+        if !self.props.show_coordinates {
+            return html! {};
+        }
+        html! {
+            <>
+                {
+                    for (0..9).map(|file| html! {
+                        <div
+                            class=classes!("board-coordinate", "file")
+                            style=format!("left: {}px;", self.screen_offset(file, true))
+                            onclick=self.props.on_file_click.reform(move |_| file)
+                        >
+                            { coord_index_to_full_width_latin(file) }
+                        </div>
+                    })
+                }
+                {
+                    for (0..9).map(|rank| html! {
+                        <div
+                            class=classes!("board-coordinate", "rank")
+                            style=format!("top: {}px;", self.screen_offset(rank, false))
+                            onclick=self.props.on_rank_click.reform(move |_| rank)
+                        >
+                            { coord_index_to_japanese_numeral(rank) }
+                        </div>
+                    })
+                }
+            </>
+        }
+    }
 }
 
 impl Component for Board {
@@ -45,45 +259,45 @@ impl Component for Board {
     }
 
     fn view(&self) -> Html {
+        let mut board_classes = classes!("board");
+        if self.props.reveal_on_hover {
+            board_classes.push("reveal-on-hover");
+        }
+        // Rows run down the screen and squares across it, both turning around with the board.
+        let mut ranks: Vec<u8> = (0..9).collect();
+        let mut files: Vec<u8> = (0..9).rev().collect();
+        if self.props.flipped {
+            ranks.reverse();
+            files.reverse();
+        }
         html! {
-            <div class=classes!("board")>
-                <div class=classes!("board-dot", "top-left")></div>
-                <div class=classes!("board-dot", "top-right")></div>
-                <div class=classes!("board-dot", "bottom-left")></div>
-                <div class=classes!("board-dot", "bottom-right")></div>
+            <div class=board_classes role="grid" aria-label=t("Shogi board", self.props.lang)>
                 {
-                    for Square::iter().enumerate().map(|(key, square)| {
-                        let is_move_origin_candidate = self.props.move_origin_candidates.contains(&square);
-                        let is_move_destination_candidate = self.props.move_destination_candidates.contains(&square);
-                        let is_move_origin=self.props.move_origin.contains(&square);
-                        let is_move_destination=self.props.move_destination.contains(&square);
-                        let is_previous_move_origin=self.props.previous_move_origin.contains(&square);
-                        let is_previous_move_destination=self.props.previous_move_destination.contains(&square);
-                        let is_asking_promotion_with_piece=is_move_destination.then_some(()).and(self.props.is_asking_promotion_with_piece);
-                        let is_in_check = self.props.pieces.get(&square)
-                            .filter(|piece| piece.piece_type == PieceType::King)
-                            .filter(|piece| match piece.color {
-                                Color::White => self.props.is_white_in_check,
-                                Color::Black => self.props.is_black_in_check,
-                            })
-                            .is_some();
+                    if self.props.show_dots {
                         html! {
-                            <SquareView
-                                key=key
-                                piece=self.props.pieces.get(&square).map(|p| *p)
-                                ghost_piece=self.props.ghost_piece
-                                is_move_origin_candidate=is_move_origin_candidate
-                                is_move_destination_candidate=is_move_destination_candidate
-                                is_move_origin=is_move_origin
-                                is_move_destination=is_move_destination
-                                is_previous_move_origin=is_previous_move_origin
-                                is_previous_move_destination=is_previous_move_destination
-                                is_asking_promotion_with_piece=is_asking_promotion_with_piece
-                                is_in_check=is_in_check
-                                on_click=self.props.on_square_click.reform(move |_| square)
-                                on_choose_promote=self.props.on_choose_promote.clone()
-                            />
+                            <>
+                                <div class=classes!("board-dot", "top-left")></div>
+                                <div class=classes!("board-dot", "top-right")></div>
+                                <div class=classes!("board-dot", "bottom-left")></div>
+                                <div class=classes!("board-dot", "bottom-right")></div>
+                            </>
                         }
+                    } else {
+                        html! {}
+                    }
+                }
+                { self.view_coordinates() }
+                { self.view_ghost_arrow() }
+                {
+                    for ranks.into_iter().map(|rank| html! {
+                        <div class="board-rank" role="row" key=rank>
+                            {
+                                for files
+                                    .iter()
+                                    .filter_map(|file| Square::new(*file, rank))
+                                    .map(|square| self.view_square(square))
+                            }
+                        </div>
                     })
                 }
             </div>
@@ -1,6 +1,10 @@
+use crate::i18n::{t, t_fill_each, Lang};
+use crate::notation::piece_type_name;
 use crate::piece::PieceView;
+use crate::settings::PieceStyles;
+use shogi_board::usi::square_to_usi;
 
-use shogi::Piece;
+use shogi::{square::Square, Color, Piece, PieceType};
 use yew::prelude::*;
 
 pub struct SquareView {
@@ -9,18 +13,81 @@ pub struct SquareView {
 
 #[derive(Properties, Clone, PartialEq)]
 pub struct SquareProps {
+    pub square: Square,
     pub piece: Option<Piece>,
+    /// Previewed on hover while the square is a destination candidate. This is the
+    /// moving piece for board moves and the held piece for drops alike.
     pub ghost_piece: Option<Piece>,
     pub is_move_origin_candidate: bool,
     pub is_move_destination_candidate: bool,
+    pub is_check_escape: bool,
+    pub is_pinned: bool,
+    pub is_opponent_destination: bool,
+    pub is_danger: bool,
+    pub is_changed: bool,
+    pub is_mobility_preview: bool,
+    pub is_dimmed: bool,
     pub is_move_origin: bool,
     pub is_move_destination: bool,
     pub is_previous_move_origin: bool,
     pub is_previous_move_destination: bool,
     pub is_asking_promotion_with_piece: Option<Piece>,
     pub is_in_check: bool,
+    pub attack_count: u8,
+    /// How strongly to highlight the square as part of the recent move trail, from 0 to 1.
+    pub trail_intensity: f64,
+    pub blindfold: bool,
+    pub flipped: bool,
+    pub is_highlighted: bool,
+    pub show_index: bool,
+    pub border_px: u32,
+    pub inset_px: u32,
+    /// Mark highlights with patterns as well as color.
+    pub high_contrast: bool,
+    /// Whose promotion zone to tint the square as, if any.
+    pub promotion_zone: Option<Color>,
+    pub piece_styles: PieceStyles,
     pub on_click: Callback<()>,
+    pub on_mouse_enter: Callback<()>,
+    pub on_mouse_leave: Callback<()>,
+    pub on_context_menu: Callback<MouseEvent>,
     pub on_choose_promote: Callback<bool>,
+    pub lang: Lang,
+}
+
+/// The piece a square shows, and whether it is only the ghost of the piece being moved,
+/// which empty destination candidates show.
+fn displayed_piece(
+    piece: Option<Piece>,
+    ghost_piece: Option<Piece>,
+    is_move_destination_candidate: bool,
+) -> (Option<Piece>, bool) {
+    if piece.is_some() {
+        (piece, false)
+    } else if is_move_destination_candidate {
+        (ghost_piece, true)
+    } else {
+        (None, false)
+    }
+}
+
+impl SquareView {
+    /// Describes the square for assistive technology, e.g. "7g, Black pawn".
+    fn aria_label(&self) -> String {
+        let lang = self.props.lang;
+        let coordinate = square_to_usi(self.props.square);
+        match self.props.piece.filter(|_| !self.props.blindfold) {
+            Some(piece) => {
+                let piece_name = t(piece_type_name(piece.piece_type), lang);
+                let values: [&dyn std::fmt::Display; 2] = [&coordinate, &piece_name];
+                match piece.color {
+                    Color::Black => t_fill_each("{}, Black {}", lang, &values),
+                    Color::White => t_fill_each("{}, White {}", lang, &values),
+                }
+            }
+            None => coordinate,
+        }
+    }
 }
 
 impl Component for SquareView {
@@ -43,9 +110,33 @@ impl Component for SquareView {
 
     fn view(&self) -> Html {
         let mut square_classes = classes!("square");
+        if self.props.high_contrast {
+            square_classes.push("high-contrast");
+        }
         if self.props.is_move_destination_candidate {
             square_classes.push("move-destination-candidate");
         }
+        if self.props.is_check_escape {
+            square_classes.push("check-escape");
+        }
+        if self.props.is_pinned {
+            square_classes.push("pinned");
+        }
+        if self.props.is_opponent_destination {
+            square_classes.push("opponent-destination");
+        }
+        if self.props.is_danger {
+            square_classes.push("danger");
+        }
+        if self.props.is_changed {
+            square_classes.push("changed");
+        }
+        if self.props.is_mobility_preview {
+            square_classes.push("mobility-preview");
+        }
+        if self.props.is_dimmed {
+            square_classes.push("dimmed");
+        }
         if self.props.is_move_origin_candidate {
             square_classes.push("move-origin-candidate");
         }
@@ -64,22 +155,75 @@ impl Component for SquareView {
         if self.props.is_in_check {
             square_classes.push("in-check");
         }
+        if self.props.is_highlighted {
+            square_classes.push("highlighted");
+        }
+        match self.props.promotion_zone {
+            Some(Color::Black) => square_classes.push("black-promotion-zone"),
+            Some(Color::White) => square_classes.push("white-promotion-zone"),
+            None => {}
+        }
+        let mut square_style = format!(
+            "--square-border: {}px; --piece-inset: {}px;",
+            self.props.border_px, self.props.inset_px
+        );
+        if self.props.attack_count > 0 {
+            square_classes.push("attacked");
+            square_style.push_str(&format!("--attack-count: {};", self.props.attack_count));
+        }
+        if self.props.trail_intensity > 0.0 {
+            square_classes.push("trail");
+            square_style.push_str(&format!(
+                "--trail-intensity: {};",
+                self.props.trail_intensity
+            ));
+        }
 
-        let displayed_piece = if let Some(piece) = self.props.piece {
-            Some(piece)
-        } else if self.props.is_move_destination_candidate {
+        let (displayed_piece, is_ghost) = displayed_piece(
+            self.props.piece,
+            self.props.ghost_piece,
+            self.props.is_move_destination_candidate,
+        );
+        if is_ghost {
             square_classes.push("ghost");
-            self.props.ghost_piece
-        } else {
-            None
-        };
+        }
 
         html! {
             <div
                 class=square_classes
+                style=square_style
+                role="gridcell"
+                aria-label=self.aria_label()
                 onclick=self.props.on_click.reform(|_| ())
+                onmouseenter=self.props.on_mouse_enter.reform(|_| ())
+                onmouseleave=self.props.on_mouse_leave.reform(|_| ())
+                oncontextmenu=self.props.on_context_menu.clone()
             >
-                <PieceView piece=displayed_piece />
+                <PieceView piece=displayed_piece blindfold=self.props.blindfold flipped=self.props.flipped piece_styles=self.props.piece_styles />
+                {
+                    if self.props.is_move_destination_candidate {
+                        html! {
+                            <>
+                                <div class="hit-padding" />
+                                <div class="hit-area" />
+                            </>
+                        }
+                    } else {
+                        html! {}
+                    }
+                }
+                {
+                    if self.props.show_index {
+                        let square = self.props.square;
+                        html! {
+                            <div class="square-index">
+                                { format!("{} f{} r{}", square.index(), square.file(), square.rank()) }
+                            </div>
+                        }
+                    } else {
+                        html! {}
+                    }
+                }
                 {
                     if let Some(piece) = self.props.is_asking_promotion_with_piece {
                         html!{
@@ -89,7 +233,7 @@ impl Component for SquareView {
                                     onclick=self.props.on_choose_promote.reform(|_| true)
                                 >
                                     <div>
-                                        <PieceView piece=piece.promote().expect("Piece can be promoted") />
+                                        <PieceView piece=piece.promote().expect("Piece can be promoted") flipped=self.props.flipped piece_styles=self.props.piece_styles />
                                     </div>
                                 </div>
                                 <div
@@ -97,7 +241,7 @@ impl Component for SquareView {
                                     onclick=self.props.on_choose_promote.reform(|_| false)
                                 >
                                     <div>
-                                        <PieceView piece=piece />
+                                        <PieceView piece=piece flipped=self.props.flipped piece_styles=self.props.piece_styles />
                                     </div>
                                 </div>
                             </div>
@@ -110,3 +254,43 @@ impl Component for SquareView {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use shogi::bitboard::Factory as BBFactory;
+    use shogi::Position;
+    use shogi_board::move_intent::{MoveIntentBuilder, Origin};
+    use shogi_board::usi::parse_square;
+
+    #[test]
+    fn held_piece_ghosts_on_drop_candidates() {
+        BBFactory::init();
+        let mut position = Position::new();
+        position.set_sfen("4k4/9/9/9/9/9/9/9/4K4 b G 1").unwrap();
+        let intent = MoveIntentBuilder::WithOrigin {
+            from: Origin::HeldPiece(PieceType::Gold),
+        };
+        let ghost_piece = intent.move_origin_piece(&position);
+        assert_eq!(
+            ghost_piece,
+            Some(Piece {
+                piece_type: PieceType::Gold,
+                color: Color::Black,
+            })
+        );
+
+        let empty = parse_square("5e").unwrap();
+        assert!(intent
+            .move_destination_candidates(&position)
+            .contains(&empty));
+        assert_eq!(
+            displayed_piece(None, ghost_piece, true),
+            (ghost_piece, true)
+        );
+
+        let king = *position.piece_at(parse_square("5a").unwrap());
+        assert_eq!(displayed_piece(king, ghost_piece, false), (king, false));
+        assert_eq!(displayed_piece(None, ghost_piece, false), (None, false));
+    }
+}
@@ -0,0 +1,126 @@
+use crate::board::Pieces;
+
+use shogi::{square::Square, Color, Move, Piece, PieceType};
+
+fn sfen_letter(piece: Piece) -> String {
+    let letter = match piece.piece_type {
+        PieceType::King => "K",
+        PieceType::Rook => "R",
+        PieceType::Bishop => "B",
+        PieceType::Gold => "G",
+        PieceType::Silver => "S",
+        PieceType::Knight => "N",
+        PieceType::Lance => "L",
+        PieceType::Pawn => "P",
+        PieceType::ProRook => "+R",
+        PieceType::ProBishop => "+B",
+        PieceType::ProSilver => "+S",
+        PieceType::ProKnight => "+N",
+        PieceType::ProLance => "+L",
+        PieceType::ProPawn => "+P",
+    };
+    match piece.color {
+        Color::Black => letter.to_owned(),
+        Color::White => letter.to_lowercase(),
+    }
+}
+
+/// Writes the board field of an SFEN, from the top rank down and from file 9 to file 1
+/// along each rank.
+fn board_sfen(pieces: &Pieces) -> String {
+    (0..9)
+        .map(|rank| {
+            let mut row = String::new();
+            let mut empty_squares = 0;
+            for file in (0..9).rev() {
+                let square = Square::new(file, rank).unwrap();
+                match pieces[square.index()] {
+                    Some(piece) => {
+                        if empty_squares > 0 {
+                            row.push_str(&empty_squares.to_string());
+                            empty_squares = 0;
+                        }
+                        row.push_str(&sfen_letter(piece));
+                    }
+                    None => empty_squares += 1,
+                }
+            }
+            if empty_squares > 0 {
+                row.push_str(&empty_squares.to_string());
+            }
+            row
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Replaces the piece on `square`, keeping the rest of the SFEN as it is.
+pub fn sfen_with_square(
+    sfen: &str,
+    pieces: &Pieces,
+    square: Square,
+    piece: Option<Piece>,
+) -> String {
+    let mut pieces = *pieces;
+    pieces[square.index()] = piece;
+    let board = board_sfen(&pieces);
+    sfen.split(' ')
+        .enumerate()
+        .map(|(index, field)| if index == 0 { board.as_str() } else { field })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// The square in the same place when the board is turned around.
+pub fn rotated_square(square: Square) -> Square {
+    Square::new(8 - square.file(), 8 - square.rank()).unwrap()
+}
+
+/// The same move as seen after `swapped_colors_sfen`.
+pub fn rotated_move(next_move: Move) -> Move {
+    match next_move {
+        Move::Normal { from, to, promote } => Move::Normal {
+            from: rotated_square(from),
+            to: rotated_square(to),
+            promote,
+        },
+        Move::Drop { to, piece_type } => Move::Drop {
+            to: rotated_square(to),
+            piece_type,
+        },
+    }
+}
+
+/// The position with Black and White swapped: every piece changes side and the board
+/// is turned around so each side still plays up the board, the hands trade places, and
+/// the other side is to move.
+pub fn swapped_colors_sfen(sfen: &str, pieces: &Pieces) -> String {
+    let mut swapped_pieces = [None; 81];
+    for square in Square::iter() {
+        swapped_pieces[rotated_square(square).index()] =
+            pieces[square.index()].map(|piece| Piece {
+                piece_type: piece.piece_type,
+                color: piece.color.flip(),
+            });
+    }
+    let board = board_sfen(&swapped_pieces);
+    sfen.split(' ')
+        .enumerate()
+        .map(|(index, field)| match index {
+            0 => board.clone(),
+            1 => if field == "b" { "w" } else { "b" }.to_owned(),
+            2 => field
+                .chars()
+                .map(|c| {
+                    if c.is_ascii_uppercase() {
+                        c.to_ascii_lowercase()
+                    } else {
+                        c.to_ascii_uppercase()
+                    }
+                })
+                .collect(),
+            _ => field.to_owned(),
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
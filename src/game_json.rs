@@ -0,0 +1,108 @@
+use crate::settings::{AutoPromotePolicy, DropStyle, Notation, Settings};
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Bumped whenever a field changes meaning. Newer fields are added with defaults so
+/// older exports keep loading.
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// Everything the app knows about a game, for round-tripping through other tools.
+#[derive(Serialize, Deserialize)]
+pub struct GameJson {
+    pub schema: u32,
+    /// The position the game started from.
+    pub sfen: String,
+    /// Every move of the game in USI, including any after the viewed ply.
+    pub moves: Vec<String>,
+    /// How many of `moves` have been played in the position being viewed.
+    #[serde(default)]
+    pub ply: usize,
+    /// Comments keyed by the ply they follow, where 0 is the starting position.
+    #[serde(default)]
+    pub comments: BTreeMap<usize, String>,
+    #[serde(default)]
+    pub result: Option<GameResultJson>,
+    #[serde(default)]
+    pub settings: Option<SettingsJson>,
+    #[serde(default)]
+    pub meta: GameMeta,
+}
+
+/// Who played the game, and where and when. Empty fields are unknown. The result isn't
+/// kept here, as it follows from the moves: it was decided by the rules or a declared
+/// impasse and is exported as [`GameJson::result`], so there is nothing to edit.
+#[derive(Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GameMeta {
+    pub black: String,
+    pub white: String,
+    /// When the game started, written as KIF writes it, e.g. "2021/10/05 10:00:00".
+    pub date: String,
+    pub event: String,
+}
+
+/// One of the editable fields of [`GameMeta`].
+#[derive(Clone, Copy)]
+pub enum MetaField {
+    Black,
+    White,
+    Date,
+    Event,
+}
+
+impl GameMeta {
+    pub fn field(&self, field: MetaField) -> &str {
+        match field {
+            MetaField::Black => &self.black,
+            MetaField::White => &self.white,
+            MetaField::Date => &self.date,
+            MetaField::Event => &self.event,
+        }
+    }
+
+    pub fn field_mut(&mut self, field: MetaField) -> &mut String {
+        match field {
+            MetaField::Black => &mut self.black,
+            MetaField::White => &mut self.white,
+            MetaField::Date => &mut self.date,
+            MetaField::Event => &mut self.event,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct GameResultJson {
+    /// As reported in the `shogi-gameover` event, e.g. "checkmate".
+    pub result: String,
+    /// "black" or "white", or missing for a draw.
+    #[serde(default)]
+    pub winner: Option<String>,
+}
+
+/// The settings that affect how the game reads and plays.
+#[derive(Serialize, Deserialize)]
+pub struct SettingsJson {
+    pub notation: Notation,
+    pub drop_style: DropStyle,
+    pub auto_promote: AutoPromotePolicy,
+    pub flipped: bool,
+}
+
+impl SettingsJson {
+    pub fn from_settings(settings: &Settings) -> Self {
+        Self {
+            notation: settings.notation,
+            drop_style: settings.drop_style,
+            auto_promote: settings.auto_promote,
+            flipped: settings.flipped,
+        }
+    }
+
+    pub fn apply_to(&self, settings: &mut Settings) {
+        settings.notation = self.notation;
+        settings.drop_style = self.drop_style;
+        settings.auto_promote = self.auto_promote;
+        settings.flipped = self.flipped;
+    }
+}
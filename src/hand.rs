@@ -1,6 +1,10 @@
+use crate::i18n::{t, t_fill_each, Lang};
+use crate::notation::piece_type_name;
 use crate::piece::PieceView;
+use crate::settings::PieceStyles;
 
 use shogi::{Color, Piece, PieceType};
+use std::collections::HashMap;
 use yew::prelude::*;
 
 pub struct Hand {
@@ -13,7 +17,18 @@ pub struct HandProps {
     pub pieces: Vec<HandPiece>,
     pub selection: Option<PieceType>,
     pub can_select: bool,
+    /// Whether the board is viewed from White's side, which decides where the hand sits
+    /// and which way up its pieces are drawn.
+    pub flipped: bool,
+    pub piece_styles: PieceStyles,
+    /// The piece type a capture just added, to draw the eye to it.
+    #[prop_or_default]
+    pub just_gained: Option<PieceType>,
+    /// How many legal drops each held piece type has, when they are shown.
+    #[prop_or_default]
+    pub drop_counts: HashMap<PieceType, usize>,
     pub on_piece_click: Callback<PieceType>,
+    pub lang: Lang,
 }
 
 #[derive(Clone, PartialEq)]
@@ -45,6 +60,9 @@ impl Component for Hand {
         if self.props.can_select {
             hand_classes.push("selectable");
         }
+        if self.props.flipped {
+            hand_classes.push("flipped");
+        }
         html! {
             <div class=hand_classes>
                 {
@@ -57,21 +75,52 @@ impl Component for Hand {
                         if hand_piece.count == 0 {
                             hand_piece_classes.push("none");
                         }
+                        if self.props.just_gained == Some(hand_piece.piece_type) {
+                            hand_piece_classes.push("just-gained");
+                        }
                         if let Some(selected_piece_type) = self.props.selection {
                             if hand_piece.piece_type == selected_piece_type {
                                 hand_piece_classes.push("selected");
                             }
                         }
+                        // Empty slots are kept out of the tab order, as is a hand that can't be chosen from.
+                        let is_focusable = hand_piece.count > 0 && self.props.can_select;
+                        let aria_label = t_fill_each(
+                            "{} in hand, {}",
+                            self.props.lang,
+                            &[&t(piece_type_name(hand_piece.piece_type), self.props.lang), &hand_piece.count],
+                        );
+                        let on_piece_click = self.props.on_piece_click.clone();
+                        let on_key_down = Callback::from(move |event: KeyboardEvent| {
+                            if event.key() == "Enter" || event.key() == " " {
+                                event.prevent_default();
+                                on_piece_click.emit(piece.piece_type);
+                            }
+                        });
                         html! {
                             <div
                                 class=hand_piece_classes
                                 key=key
+                                role="button"
+                                aria-label=aria_label
+                                tabindex=if is_focusable { "0" } else { "-1" }
                                 onclick=self.props.on_piece_click.reform(move |_| piece.piece_type)
+                                onkeydown=on_key_down
                             >
-                                <PieceView piece=Some(piece) />
+                                <PieceView piece=Some(piece) flipped=self.props.flipped piece_styles=self.props.piece_styles />
                                 <div class="count">
                                     {hand_piece.count}
                                 </div>
+                                {
+                                    match self.props.drop_counts.get(&hand_piece.piece_type) {
+                                        Some(drop_count) if hand_piece.count > 0 => html! {
+                                            <div class="drop-count" title=t("Legal drops", self.props.lang)>
+                                                { drop_count }
+                                            </div>
+                                        },
+                                        _ => html! {},
+                                    }
+                                }
                             </div>
                         }
                     })
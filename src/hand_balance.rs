@@ -0,0 +1,75 @@
+use crate::hand::HandPiece;
+use crate::i18n::{t, Lang};
+use crate::piece::PieceView;
+use crate::settings::PieceStyles;
+
+use shogi::{Color, Piece};
+use yew::prelude::*;
+
+/// A read-only summary of how many more of each piece Black holds than White.
+pub struct HandBalance {
+    props: HandBalanceProps,
+}
+
+#[derive(Properties, Clone, PartialEq)]
+pub struct HandBalanceProps {
+    /// Both hands list the same piece types in the same order.
+    pub black_pieces: Vec<HandPiece>,
+    pub white_pieces: Vec<HandPiece>,
+    pub piece_styles: PieceStyles,
+    pub lang: Lang,
+}
+
+impl Component for HandBalance {
+    type Message = ();
+    type Properties = HandBalanceProps;
+
+    fn create(props: Self::Properties, _link: ComponentLink<Self>) -> Self {
+        Self { props }
+    }
+
+    fn update(&mut self, _msg: Self::Message) -> ShouldRender {
+        false
+    }
+
+    fn change(&mut self, props: Self::Properties) -> ShouldRender {
+        let changed = self.props != props;
+        self.props = props;
+        changed
+    }
+
+    fn view(&self) -> Html {
+        let differences: Vec<_> = self
+            .props
+            .black_pieces
+            .iter()
+            .zip(self.props.white_pieces.iter())
+            .map(|(black, white)| (black.piece_type, black.count as i16 - white.count as i16))
+            .filter(|(_, difference)| *difference != 0)
+            .collect();
+        html! {
+            <div class="hand-balance" aria-label=t("Pieces in hand, Black minus White", self.props.lang)>
+                {
+                    if differences.is_empty() {
+                        html! { <span>{ t("Hands even", self.props.lang) }</span> }
+                    } else {
+                        html! {
+                            for differences.into_iter().map(|(piece_type, difference)| {
+                                let piece = Piece {
+                                    piece_type,
+                                    color: Color::Black,
+                                };
+                                html! {
+                                    <div class="hand-balance-piece" key=format!("{:?}", piece_type)>
+                                        <PieceView piece=Some(piece) piece_styles=self.props.piece_styles />
+                                        <span>{ format!("{:+}", difference) }</span>
+                                    </div>
+                                }
+                            })
+                        }
+                    }
+                }
+            </div>
+        }
+    }
+}
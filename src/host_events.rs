@@ -0,0 +1,22 @@
+use js_sys::{Object, Reflect};
+use wasm_bindgen::JsValue;
+use yew::utils::document;
+use yew::web_sys::{CustomEvent, CustomEventInit};
+
+/// Dispatches a `CustomEvent` on the document so that host pages embedding the
+/// board can follow the game, with `detail` set to an object of string fields.
+pub fn dispatch(name: &str, detail: &[(&str, &str)]) {
+    let detail_object = Object::new();
+    for (key, value) in detail {
+        let _ = Reflect::set(
+            &detail_object,
+            &JsValue::from_str(key),
+            &JsValue::from_str(value),
+        );
+    }
+    let mut event_init = CustomEventInit::new();
+    event_init.detail(&detail_object);
+    if let Ok(event) = CustomEvent::new_with_event_init_dict(name, &event_init) {
+        let _ = document().dispatch_event(&event);
+    }
+}
@@ -0,0 +1,419 @@
+/// The language the interface is shown in.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Lang {
+    English,
+    Japanese,
+}
+
+impl Lang {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::English => "en",
+            Self::Japanese => "ja",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "en" => Some(Self::English),
+            "ja" => Some(Self::Japanese),
+            _ => None,
+        }
+    }
+
+    /// How the language calls itself, for the language selector.
+    pub fn native_name(self) -> &'static str {
+        match self {
+            Self::English => "English",
+            Self::Japanese => "日本語",
+        }
+    }
+}
+
+impl Default for Lang {
+    fn default() -> Self {
+        Self::English
+    }
+}
+
+/// Japanese translations, keyed by the English text.
+const JAPANESE: &[(&str, &str)] = &[
+    (
+        " {} random moves undone cleanly",
+        " ランダムな{}手をきれいに戻せました",
+    ),
+    (", annotated", "、コメントあり"),
+    ("24-point rule", "24点法"),
+    ("27-point rule", "27点法"),
+    (
+        "A different move here overwrites the {} later moves; ask first",
+        "ここで別の手を指すと以降の{}手が消えるので確認する",
+    ),
+    ("Always promote", "常に成る"),
+    ("Arrows for every piece", "すべての駒の矢印"),
+    ("Arrows for the selected piece", "選んだ駒の矢印"),
+    ("Arrows in the SVG diagram", "SVG図の矢印"),
+    ("As the notation counts them", "表記に合わせる"),
+    ("Ask", "確認する"),
+    ("BOD diagram", "BOD図"),
+    ("Back to my view", "自分の視点に戻る"),
+    ("Black ", "先手 "),
+    (
+        "Black has {} kings instead of one",
+        "先手の玉が1枚ではなく{}枚あります",
+    ),
+    (
+        "Black has {} pawns on file {} (nifu)",
+        "先手の歩が{}枚、{}筋にあります（二歩）",
+    ),
+    (
+        "Black is in check while it's White's turn",
+        "後手番なのに先手に王手がかかっています",
+    ),
+    ("Black pieces ", "先手の駒 "),
+    ("Black to move", "先手番"),
+    (
+        "Black {} on {} can never move",
+        "先手の{}（{}）は動けません",
+    ),
+    (
+        "Black's material minus White's",
+        "先手の駒の価値から後手の駒の価値を引いた値",
+    ),
+    ("Blindfold", "目隠し"),
+    ("Board dots", "星"),
+    ("Byoyomi ", "秒読み "),
+    ("CPU plays Black", "CPUが先手"),
+    ("CPU plays White", "CPUが後手"),
+    ("CPU seed", "CPUの乱数シード"),
+    ("CPU thinking time", "CPUの思考時間"),
+    ("Can't read the SFEN: {}", "SFENを読み込めません：{}"),
+    ("Cancel move", "指し手をやめる"),
+    ("Capture log", "駒取りの記録"),
+    ("Captures", "駒取り"),
+    ("Check hovered square only", "ポインタのマスだけ調べる"),
+    ("Check undo symmetry", "待ったの対称性を確認"),
+    ("Checkmate", "詰み"),
+    ("Clear", "消去"),
+    ("Comment on this position", "この局面へのコメント"),
+    (
+        "Compare with an SFEN or shared link",
+        "SFENか共有リンクと比べる",
+    ),
+    ("Confirm restart", "やり直す前に確認"),
+    ("Confirm restart?", "本当にやり直しますか？"),
+    ("Coordinates", "座標"),
+    ("Copied!", "コピーしました！"),
+    ("Copy", "コピー"),
+    ("Copy the link after every move", "毎手リンクをコピー"),
+    ("Count legal drops", "打てるマスの数を表示"),
+    ("Custom start", "任意の開始局面"),
+    ("Date ", "開始日時 "),
+    ("Debug", "デバッグ"),
+    ("Declare impasse", "入玉宣言"),
+    ("Destination, e.g. 7f", "移動先（例：7f）"),
+    ("Destinations ", "移動先 "),
+    ("Dim the opponent's pieces", "相手の駒を暗くする"),
+    ("Done editing", "編集を終える"),
+    ("Drops ", "駒打ち "),
+    ("Edit position", "局面を編集"),
+    ("Even game", "手合割：平手"),
+    ("Event ", "棋戦 "),
+    ("Every ply (KIF)", "一手ごと（KIF）"),
+    ("Explain refused pawn-drop mates", "打ち歩詰めを説明する"),
+    ("Find mate", "詰みを探す"),
+    ("Find the best move.", "最善手を探してください。"),
+    ("Fit to width", "幅に合わせる"),
+    (
+        "Flash where a moved piece can go next",
+        "動いた駒の次の利きを一瞬表示",
+    ),
+    ("Flip board", "盤を反転"),
+    ("From {}", "移動元 {}"),
+    ("Grid lines ", "罫線の太さ "),
+    ("Hand balance", "持ち駒の損得"),
+    ("Hands even", "持ち駒は互角"),
+    ("High contrast highlights", "高コントラスト表示"),
+    ("History columns ", "棋譜の列数 "),
+    ("Impasse", "持将棋"),
+    ("Japanese", "日本式"),
+    ("Kanji", "漢字"),
+    ("Language ", "言語 "),
+    ("Legal drops", "打てるマスの数"),
+    ("Lesson complete!", "レッスン完了！"),
+    ("Letters", "アルファベット"),
+    ("Link copied!", "リンクをコピーしました！"),
+    ("Lishogi analysis", "Lishogiで解析"),
+    (
+        "Mate! The defence couldn't hold out.",
+        "詰み！受けきれませんでした。",
+    ),
+    (
+        "Mark squares a king can't step to",
+        "玉が行けない危険なマスを表示",
+    ),
+    ("Mate in {}", "{}手詰め"),
+    ("Material balance over the game", "対局中の駒の損得"),
+    ("Material {}", "駒の損得 {}"),
+    (
+        "Missed: every move of a tsume must give check.",
+        "失敗：詰将棋では毎手王手をかけます。",
+    ),
+    (
+        "Missed: no mate found within {} plies.",
+        "失敗：{}手以内の詰みが見つかりませんでした。",
+    ),
+    ("Move in USI", "USI形式の指し手"),
+    ("Move numbers ", "手数の数え方 "),
+    ("Move pairs (Western)", "二手ごと（西洋式）"),
+    ("Move {}", "{}手目"),
+    ("Move, e.g. 7g7f", "指し手（例：7g7f）"),
+    ("Mute", "消音"),
+    ("Never promote", "成らない"),
+    ("New game from preset…", "プリセットから新規対局…"),
+    ("No arrows", "矢印なし"),
+    ("No legal moves", "指し手なし"),
+    (
+        "No mate found within {} plies",
+        "{}手以内の詰みは見つかりませんでした",
+    ),
+    ("Not quite. {}", "惜しい！{}"),
+    ("Notation ", "表記 "),
+    ("Off", "なし"),
+    (
+        "Only load playable positions from links",
+        "リンクからは指せる局面だけ読み込む",
+    ),
+    ("Opponent", "対戦相手"),
+    ("Paste JSON", "JSONを貼り付け"),
+    ("Pause", "一時停止"),
+    ("Piece inset ", "駒の余白 "),
+    (
+        "Pieces in hand, Black minus White",
+        "持ち駒の差（先手－後手）",
+    ),
+    ("Play", "再生"),
+    (
+        "Play the lesson's next move.",
+        "レッスンの次の手を指してください。",
+    ),
+    (
+        "Playing this move replaces the move after it. Continue?",
+        "この手を指すと後の1手が置き換わります。続けますか？",
+    ),
+    (
+        "Playing this move replaces the {} moves after it. Continue?",
+        "この手を指すと後の{}手が置き換わります。続けますか？",
+    ),
+    (
+        "Promoted pieces can't be held in hand",
+        "成駒は持ち駒にできません",
+    ),
+    ("Promotion ", "成り "),
+    ("Promotion zones", "敵陣"),
+    ("Puzzle of the position", "この局面の問題"),
+    ("Random moves to play", "ランダムに指す手数"),
+    ("Random position", "ランダムな局面"),
+    ("Random seed", "乱数シード"),
+    ("Redo", "進む"),
+    ("Reference SFEN", "比較用SFEN"),
+    ("Repair", "修正"),
+    ("Restart", "最初から"),
+    ("Reveal", "表示"),
+    ("Reveal on hover", "ポインタで表示"),
+    (
+        "Save current settings as preset",
+        "現在の設定をプリセットに保存",
+    ),
+    (
+        "Scroll the history to new moves",
+        "棋譜を最新の手に合わせる",
+    ),
+    ("See it as opponent", "相手の視点で見る"),
+    ("Share full game", "対局全体を共有"),
+    ("Share position only", "局面だけ共有"),
+    ("Shareable link", "共有リンク"),
+    ("Shareable link contents", "共有リンクの内容"),
+    ("Shogi board", "将棋盤"),
+    ("Show all", "すべて表示"),
+    ("Show move hints", "指し手のヒントを表示"),
+    ("Show pinned pieces", "ピンされた駒を表示"),
+    ("Show the opponent's moves", "相手の指し手を表示"),
+    ("Show threats", "脅威を表示"),
+    ("Side to move", "手番"),
+    (
+        "Sorry, link wasn’t copied",
+        "リンクをコピーできませんでした",
+    ),
+    ("Sorry, that wasn’t copied", "コピーできませんでした"),
+    ("SVG diagram", "SVG図"),
+    ("Square indices", "マスの番号"),
+    ("Starting position", "開始局面"),
+    (
+        "That's not the move for this lesson. Try again.",
+        "このレッスンの手ではありません。もう一度どうぞ。",
+    ),
+    (
+        "The position in the link was not loaded because it breaks the rules:",
+        "リンクの局面は反則があるため読み込みませんでした：",
+    ),
+    ("Thinking…", "考慮中…"),
+    ("Thinks {}s", "考慮 {}秒"),
+    ("Tick for the last ", "秒読み音を鳴らす秒数 "),
+    ("To {}", "移動先 {}"),
+    ("Trail ", "軌跡 "),
+    ("Try", "トライ"),
+    ("Try a piece from your hand.", "持ち駒を使ってみましょう。"),
+    ("Try rule", "トライルール"),
+    ("Try the piece on {}.", "{}の駒を動かしてみましょう。"),
+    (
+        "Tsume practice against the best defence",
+        "最善の受けで詰将棋を練習",
+    ),
+    ("Two players", "二人対局"),
+    ("USI moves", "USI形式の棋譜"),
+    ("Undo", "待った"),
+    ("Undo my move", "自分の手を待った"),
+    ("Well done, that's the move!", "正解です！"),
+    ("Western", "西洋式"),
+    ("White ", "後手 "),
+    (
+        "White has {} kings instead of one",
+        "後手の玉が1枚ではなく{}枚あります",
+    ),
+    (
+        "White has {} pawns on file {} (nifu)",
+        "後手の歩が{}枚、{}筋にあります（二歩）",
+    ),
+    (
+        "White is in check while it's Black's turn",
+        "先手番なのに後手に王手がかかっています",
+    ),
+    ("White pieces ", "後手の駒 "),
+    ("White to move", "後手番"),
+    (
+        "White {} on {} can never move",
+        "後手の{}（{}）は動けません",
+    ),
+    ("bishop", "角"),
+    ("gold", "金"),
+    ("hand", "持ち駒"),
+    ("king", "玉"),
+    ("king not entered", "未入玉"),
+    ("knight", "桂"),
+    ("lance", "香"),
+    ("nowhere", "なし"),
+    ("pawn", "歩"),
+    ("promoted bishop", "馬"),
+    ("promoted knight", "成桂"),
+    ("promoted lance", "成香"),
+    ("promoted pawn", "と"),
+    ("promoted rook", "龍"),
+    ("promoted silver", "成銀"),
+    ("rook", "飛車"),
+    ("silver", "銀"),
+    (
+        "{} destination squares checked this render",
+        "この描画で移動先を{}マス調べました",
+    ),
+    ("{} in hand, {}", "持ち駒の{}、{}枚"),
+    ("{} legal moves", "合法手{}通り"),
+    ("{} moves", "{}手"),
+    ("{} points", "{}点"),
+    ("{}, Black wins", "{}で先手の勝ち"),
+    ("{}, Black {}", "{}、先手の{}"),
+    ("{}, White wins", "{}で後手の勝ち"),
+    ("{}, White {}", "{}、後手の{}"),
+    ("{}, draw", "{}で引き分け"),
+    (
+        "打ち歩詰めは反則: a pawn drop may not give checkmate.",
+        "打ち歩詰めは反則です。",
+    ),
+];
+
+/// The text for `key` in `lang`. Keys are the English text, which is also shown for any
+/// key that hasn't been translated yet.
+pub fn t(key: &'static str, lang: Lang) -> &'static str {
+    match lang {
+        Lang::English => key,
+        Lang::Japanese => JAPANESE
+            .iter()
+            .find(|(english, _)| *english == key)
+            .map_or(key, |(_, japanese)| japanese),
+    }
+}
+
+/// The text for `key` in `lang`, with `value` written in place of its `{}`.
+pub fn t_fill(key: &'static str, lang: Lang, value: impl std::fmt::Display) -> String {
+    t(key, lang).replacen("{}", &value.to_string(), 1)
+}
+
+/// The text for `key` in `lang`, with `values` written in place of its `{}`s in order.
+pub fn t_fill_each(key: &'static str, lang: Lang, values: &[&dyn std::fmt::Display]) -> String {
+    values.iter().fold(t(key, lang).to_owned(), |text, value| {
+        text.replacen("{}", &value.to_string(), 1)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fills_in_the_translated_text() {
+        assert_eq!(t_fill("Move {}", Lang::English, 12), "Move 12");
+        assert_eq!(t_fill("Move {}", Lang::Japanese, 12), "12手目");
+    }
+
+    #[test]
+    fn fills_in_each_value_in_order() {
+        assert_eq!(
+            t_fill_each("{} in hand, {}", Lang::English, &[&"pawn", &2]),
+            "pawn in hand, 2"
+        );
+        assert_eq!(
+            t_fill_each(
+                "Black has {} pawns on file {} (nifu)",
+                Lang::Japanese,
+                &[&2, &5]
+            ),
+            "先手の歩が2枚、5筋にあります（二歩）"
+        );
+    }
+
+    #[test]
+    fn untranslated_keys_fall_back_to_english() {
+        assert_eq!(t("Not a key", Lang::Japanese), "Not a key");
+    }
+
+    /// The source files that look text up, checked for keys missing from the table.
+    const SOURCES: &[&str] = &[
+        include_str!("board.rs"),
+        include_str!("board/square.rs"),
+        include_str!("hand.rs"),
+        include_str!("hand_balance.rs"),
+        include_str!("main.rs"),
+        include_str!("shareable_link.rs"),
+        include_str!("status_bar.rs"),
+    ];
+
+    #[test]
+    fn every_key_looked_up_has_a_japanese_translation() {
+        for source in SOURCES {
+            for lookup in ["t(\"", "t_fill(\"", "t_fill_each(\""] {
+                for (start, _) in source.match_indices(lookup) {
+                    if source[..start].ends_with(|c: char| c.is_alphanumeric() || c == '_') {
+                        continue;
+                    }
+                    let key = &source[start + lookup.len()..];
+                    let key = &key[..key.find('"').unwrap()];
+                    assert!(
+                        JAPANESE.iter().any(|(english, _)| *english == key),
+                        "{:?} isn't translated",
+                        key
+                    );
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,62 @@
+//! How pieces and sides are written in Japanese, shared by the notation and diagrams.
+
+use shogi::{Color, PieceType};
+
+pub const BLACK_MARK: &str = "☗";
+pub const WHITE_MARK: &str = "☖";
+
+pub fn side_mark(color: Color) -> &'static str {
+    match color {
+        Color::Black => BLACK_MARK,
+        Color::White => WHITE_MARK,
+    }
+}
+
+/// The piece's name, one or two kanji wide.
+pub fn piece_kanji(piece_type: PieceType) -> &'static str {
+    match piece_type {
+        PieceType::King => "玉",
+        PieceType::Rook => "飛",
+        PieceType::Bishop => "角",
+        PieceType::Gold => "金",
+        PieceType::Silver => "銀",
+        PieceType::Knight => "桂",
+        PieceType::Lance => "香",
+        PieceType::Pawn => "歩",
+        PieceType::ProRook => "龍",
+        PieceType::ProBishop => "馬",
+        PieceType::ProSilver => "成銀",
+        PieceType::ProKnight => "成桂",
+        PieceType::ProLance => "成香",
+        PieceType::ProPawn => "と",
+    }
+}
+
+/// The single kanji BOD uses for each piece, where promoted minor pieces get their own
+/// characters.
+pub fn bod_piece_kanji(piece_type: PieceType) -> &'static str {
+    match piece_type {
+        PieceType::ProSilver => "全",
+        PieceType::ProKnight => "圭",
+        PieceType::ProLance => "杏",
+        _ => piece_kanji(piece_type),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn marks_each_side() {
+        assert_eq!(side_mark(Color::Black), "☗");
+        assert_eq!(side_mark(Color::White), "☖");
+    }
+
+    #[test]
+    fn bod_gives_promoted_minor_pieces_one_kanji() {
+        assert_eq!(piece_kanji(PieceType::ProSilver), "成銀");
+        assert_eq!(bod_piece_kanji(PieceType::ProSilver), "全");
+        assert_eq!(bod_piece_kanji(PieceType::ProPawn), "と");
+    }
+}
@@ -0,0 +1,429 @@
+use shogi::{square::Square, Color, Move, PieceType};
+
+/// Reads a digit written in either half or full width, or as a kanji numeral.
+fn parse_coordinate(character: char) -> Option<u8> {
+    let index = match character {
+        '1'..='9' => character as u32 - '1' as u32,
+        '１'..='９' => character as u32 - '１' as u32,
+        '一' => 0,
+        '二' => 1,
+        '三' => 2,
+        '四' => 3,
+        '五' => 4,
+        '六' => 5,
+        '七' => 6,
+        '八' => 7,
+        '九' => 8,
+        _ => return None,
+    };
+    Some(index as u8)
+}
+
+fn parse_piece_type(name: &str) -> Option<PieceType> {
+    match name {
+        "玉" | "王" => Some(PieceType::King),
+        "飛" => Some(PieceType::Rook),
+        "角" => Some(PieceType::Bishop),
+        "金" => Some(PieceType::Gold),
+        "銀" => Some(PieceType::Silver),
+        "桂" => Some(PieceType::Knight),
+        "香" => Some(PieceType::Lance),
+        "歩" => Some(PieceType::Pawn),
+        "龍" | "竜" => Some(PieceType::ProRook),
+        "馬" => Some(PieceType::ProBishop),
+        "成銀" | "全" => Some(PieceType::ProSilver),
+        "成桂" | "圭" => Some(PieceType::ProKnight),
+        "成香" | "杏" => Some(PieceType::ProLance),
+        "と" => Some(PieceType::ProPawn),
+        _ => None,
+    }
+}
+
+/// How SFEN writes a piece of Black's, which White's pieces give in lower case.
+fn sfen_letter(piece_type: PieceType) -> &'static str {
+    match piece_type {
+        PieceType::King => "K",
+        PieceType::Rook => "R",
+        PieceType::Bishop => "B",
+        PieceType::Gold => "G",
+        PieceType::Silver => "S",
+        PieceType::Knight => "N",
+        PieceType::Lance => "L",
+        PieceType::Pawn => "P",
+        PieceType::ProRook => "+R",
+        PieceType::ProBishop => "+B",
+        PieceType::ProSilver => "+S",
+        PieceType::ProKnight => "+N",
+        PieceType::ProLance => "+L",
+        PieceType::ProPawn => "+P",
+    }
+}
+
+fn sfen_piece(piece_type: PieceType, color: Color) -> String {
+    match color {
+        Color::Black => sfen_letter(piece_type).to_owned(),
+        Color::White => sfen_letter(piece_type).to_lowercase(),
+    }
+}
+
+/// Reads a hand count written as BOD does, where no numeral means one.
+fn parse_count(text: &str) -> Option<u8> {
+    let mut characters = text.chars();
+    let count = match (characters.next(), characters.next()) {
+        (None, _) => 1,
+        (Some('十'), None) => 10,
+        (Some('十'), Some(units)) => 11 + parse_coordinate(units)?,
+        (Some(units), None) => 1 + parse_coordinate(units)?,
+        _ => return None,
+    };
+    characters.next().is_none().then(|| count)
+}
+
+/// Reads a BOD hand line such as "先手の持駒：金二　歩十" into its SFEN pieces.
+fn parse_bod_hand(line: &str, color: Color) -> Result<String, String> {
+    let error = || format!("Can't read the pieces in hand {}", line);
+    let pieces = line
+        .split(|c: char| c == '：' || c == ':')
+        .nth(1)
+        .ok_or_else(error)?
+        .trim();
+    let mut hand = String::new();
+    for piece in pieces.split(|c: char| c == '　' || c == ' ') {
+        if piece.is_empty() || piece == "なし" {
+            continue;
+        }
+        let mut characters = piece.chars();
+        let name = characters.next().ok_or_else(error)?.to_string();
+        let piece_type = parse_piece_type(&name).ok_or_else(error)?;
+        let count = parse_count(characters.as_str()).ok_or_else(error)?;
+        if count > 1 {
+            hand.push_str(&count.to_string());
+        }
+        hand.push_str(&sfen_piece(piece_type, color));
+    }
+    Ok(hand)
+}
+
+/// Reads one rank of a BOD board, such as "|v香v桂 ・ ・ ・ ・ ・ 桂 香|一", into SFEN.
+fn parse_bod_rank(line: &str) -> Result<String, String> {
+    let error = || format!("Can't read the board row {}", line);
+    let squares = line
+        .strip_prefix('|')
+        .and_then(|rest| rest.split('|').next())
+        .ok_or_else(error)?;
+    let mut rank = String::new();
+    let mut empty_squares = 0;
+    let mut characters = squares.chars();
+    while let Some(owner) = characters.next() {
+        let name = characters.next().ok_or_else(error)?;
+        if name == '・' {
+            empty_squares += 1;
+            continue;
+        }
+        let color = match owner {
+            'v' => Color::White,
+            ' ' | '^' => Color::Black,
+            _ => return Err(error()),
+        };
+        let piece_type = parse_piece_type(&name.to_string()).ok_or_else(error)?;
+        if empty_squares > 0 {
+            rank.push_str(&empty_squares.to_string());
+            empty_squares = 0;
+        }
+        rank.push_str(&sfen_piece(piece_type, color));
+    }
+    if empty_squares > 0 {
+        rank.push_str(&empty_squares.to_string());
+    }
+    Ok(rank)
+}
+
+/// The number of the first move listed, which is also the SFEN move count of the start.
+fn first_move_number(text: &str) -> usize {
+    numbered_lines(text).next().map_or(1, |(number, _)| number)
+}
+
+/// Reads the start of a game given as a BOD diagram or a handicap in the header, as an
+/// SFEN. Gives `None` for a game from the even starting position.
+pub fn parse_start_sfen(text: &str) -> Result<Option<String>, String> {
+    let ranks: Vec<&str> = text
+        .lines()
+        .map(str::trim_end)
+        .filter(|line| line.starts_with('|'))
+        .collect();
+    if ranks.is_empty() {
+        return match header_value(text, "手合割") {
+            None | Some("平手") => Ok(None),
+            Some(handicap) => HANDICAPS
+                .iter()
+                .find(|(name, _)| *name == handicap)
+                .map(|(_, sfen)| Some((*sfen).to_owned()))
+                .ok_or_else(|| format!("Unknown handicap {}", handicap)),
+        };
+    }
+    if ranks.len() != 9 {
+        return Err("The BOD board should have nine rows".to_owned());
+    }
+    let board = ranks
+        .iter()
+        .map(|line| parse_bod_rank(line))
+        .collect::<Result<Vec<_>, _>>()?
+        .join("/");
+    let hand_line = |owner: &str| {
+        text.lines()
+            .map(str::trim)
+            .find(|line| line.starts_with(owner) && line.contains("持駒"))
+    };
+    let mut hands = String::new();
+    for (owner, color) in [("先手", Color::Black), ("後手", Color::White)] {
+        if let Some(line) = hand_line(owner) {
+            hands.push_str(&parse_bod_hand(line, color)?);
+        }
+    }
+    if hands.is_empty() {
+        hands.push('-');
+    }
+    let side_to_move = if text.lines().any(|line| line.trim() == "後手番") {
+        'w'
+    } else {
+        'b'
+    };
+    Ok(Some(format!(
+        "{} {} {} {}",
+        board,
+        side_to_move,
+        hands,
+        first_move_number(text)
+    )))
+}
+
+/// The handicaps a 手合割 line may name, and the position each starts from. White gives
+/// the handicap and moves first.
+const HANDICAPS: &[(&str, &str)] = &[
+    (
+        "香落ち",
+        "lnsgkgsn1/1r5b1/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL w - 1",
+    ),
+    (
+        "右香落ち",
+        "1nsgkgsnl/1r5b1/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL w - 1",
+    ),
+    (
+        "角落ち",
+        "lnsgkgsnl/1r7/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL w - 1",
+    ),
+    (
+        "飛車落ち",
+        "lnsgkgsnl/7b1/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL w - 1",
+    ),
+    (
+        "飛香落ち",
+        "lnsgkgsn1/7b1/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL w - 1",
+    ),
+    (
+        "二枚落ち",
+        "lnsgkgsnl/9/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL w - 1",
+    ),
+    (
+        "四枚落ち",
+        "1nsgkgsn1/9/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL w - 1",
+    ),
+    (
+        "六枚落ち",
+        "2sgkgs2/9/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL w - 1",
+    ),
+    (
+        "八枚落ち",
+        "3gkg3/9/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL w - 1",
+    ),
+    (
+        "十枚落ち",
+        "4k4/9/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL w - 1",
+    ),
+];
+
+/// The name of the handicap that starts from `sfen`, if it is one.
+pub fn handicap_name(sfen: &str) -> Option<&'static str> {
+    HANDICAPS
+        .iter()
+        .find(|(_, handicap_sfen)| *handicap_sfen == sfen)
+        .map(|(name, _)| *name)
+}
+
+/// The value of a header line such as "先手：羽生善治", trimmed, if the record has one.
+pub fn header_value<'a>(text: &'a str, key: &str) -> Option<&'a str> {
+    text.lines().find_map(|line| {
+        line.trim()
+            .strip_prefix(key)
+            .and_then(|rest| rest.strip_prefix('：').or_else(|| rest.strip_prefix(':')))
+            .map(str::trim)
+    })
+}
+
+/// Whether the text looks like a KIF game record rather than an SFEN.
+pub fn is_kif(text: &str) -> bool {
+    text.contains("手数") || text.contains("手合割")
+}
+
+/// Parses one move such as "７六歩(77)", "同　角成（88）" or "５五角打".
+fn parse_move(text: &str, previous_destination: Option<Square>) -> Result<Move, String> {
+    let error = || format!("Can't read the move {}", text);
+    let (destination, rest) = if let Some(rest) = text.strip_prefix('同') {
+        (previous_destination.ok_or_else(error)?, rest)
+    } else {
+        let mut characters = text.chars();
+        let file = characters
+            .next()
+            .and_then(parse_coordinate)
+            .ok_or_else(error)?;
+        let rank = characters
+            .next()
+            .and_then(parse_coordinate)
+            .ok_or_else(error)?;
+        (
+            Square::new(file, rank).ok_or_else(error)?,
+            characters.as_str(),
+        )
+    };
+    let rest = rest.trim_start_matches(|c: char| c == '　' || c == ' ');
+    let (piece_and_modifiers, origin) = match rest.find(|c: char| c == '(' || c == '（') {
+        Some(index) => (&rest[..index], Some(&rest[index..])),
+        None => (rest, None),
+    };
+    let piece_and_modifiers = piece_and_modifiers.trim_end_matches(|c: char| c == '　' || c == ' ');
+    if let Some(piece) = piece_and_modifiers.strip_suffix('打') {
+        let piece = piece.trim_end_matches('　');
+        let piece_type = parse_piece_type(piece).ok_or_else(error)?;
+        return Ok(Move::Drop {
+            piece_type,
+            to: destination,
+        });
+    }
+    let (piece, promote) = if let Some(piece) = piece_and_modifiers.strip_suffix("不成") {
+        (piece, false)
+    } else if parse_piece_type(piece_and_modifiers).is_some() {
+        (piece_and_modifiers, false)
+    } else if let Some(piece) = piece_and_modifiers.strip_suffix('成') {
+        (piece, true)
+    } else {
+        (piece_and_modifiers, false)
+    };
+    parse_piece_type(piece.trim_end_matches('　')).ok_or_else(error)?;
+    let mut origin_coordinates = origin
+        .ok_or_else(error)?
+        .chars()
+        .filter_map(parse_coordinate);
+    let from_file = origin_coordinates.next().ok_or_else(error)?;
+    let from_rank = origin_coordinates.next().ok_or_else(error)?;
+    Ok(Move::Normal {
+        from: Square::new(from_file, from_rank).ok_or_else(error)?,
+        to: destination,
+        promote,
+    })
+}
+
+/// The lines that start with a move number, along with that number.
+fn numbered_lines(text: &str) -> impl Iterator<Item = (usize, &str)> {
+    text.lines().filter_map(|line| {
+        let line = line.trim();
+        match line.find(|c: char| !c.is_ascii_digit()) {
+            Some(index) if index > 0 => Some((line[..index].parse().ok()?, &line[index..])),
+            _ => None,
+        }
+    })
+}
+
+/// Reads the moves of a KIF game record, which are numbered on from the first one, so a
+/// game from a BOD start may begin past move 1. Comments and header lines are skipped.
+pub fn parse_moves(text: &str) -> Result<Vec<Move>, String> {
+    let first_move_number = first_move_number(text);
+    let mut moves = Vec::new();
+    for (number, rest) in numbered_lines(text) {
+        if number != first_move_number + moves.len() {
+            continue;
+        }
+        let notation = rest
+            .trim_start()
+            .split(|c: char| c == ' ' || c == '\t')
+            .next()
+            .unwrap_or_default();
+        // Results like "投了" end the game without being a move.
+        if notation.starts_with("投了")
+            || notation.starts_with("中断")
+            || notation.starts_with("詰")
+        {
+            break;
+        }
+        let previous_destination = moves.last().map(|previous_move| match previous_move {
+            Move::Normal { to, .. } => *to,
+            Move::Drop { to, .. } => *to,
+        });
+        moves.push(parse_move(notation, previous_destination)?);
+    }
+    Ok(moves)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn even_games_have_no_start_sfen() {
+        let kif = "手合割：平手\n手数----指手---------消費時間--\n   1 ７六歩(77)\n";
+        assert!(parse_start_sfen(kif).unwrap().is_none());
+    }
+
+    #[test]
+    fn reads_a_handicap_start() {
+        let sfen = parse_start_sfen("手合割：角落ち\n").unwrap().unwrap();
+        assert_eq!(
+            sfen,
+            "lnsgkgsnl/1r7/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL w - 1"
+        );
+        assert_eq!(handicap_name(&sfen), Some("角落ち"));
+        assert!(parse_start_sfen("手合割：その他\n").is_err());
+    }
+
+    #[test]
+    fn reads_a_bod_start() {
+        let kif = "後手の持駒：飛　
+  ９ ８ ７ ６ ５ ４ ３ ２ １
++---------------------------+
+| ・ ・ ・ ・v玉 ・ ・ ・ ・|一
+| ・ ・ ・ ・ ・ ・ ・ ・ ・|二
+| ・ ・ ・ ・ 金 ・ ・ ・ ・|三
+| ・ ・ ・ ・ ・ ・ ・ ・ ・|四
+| ・ ・ ・ ・ ・ ・ ・ ・ ・|五
+| ・ ・ ・ ・ ・ ・ ・ ・ ・|六
+| ・ ・ ・ ・ ・ ・ ・ ・ ・|七
+| ・ ・ ・ ・ 全 ・ ・ ・ ・|八
+| ・ ・ ・ ・ 玉 ・ ・ ・ ・|九
++---------------------------+
+先手の持駒：金二　歩十　
+後手番
+手数----指手---------消費時間--
+  12 ４一玉(51)
+";
+        assert_eq!(
+            parse_start_sfen(kif).unwrap().as_deref(),
+            Some("4k4/9/4G4/9/9/9/9/4+S4/4K4 w 2G10Pr 12")
+        );
+        assert!(
+            parse_moves(kif).unwrap()
+                == vec![Move::Normal {
+                    from: Square::new(4, 0).unwrap(),
+                    to: Square::new(3, 0).unwrap(),
+                    promote: false,
+                }]
+        );
+    }
+
+    #[test]
+    fn counts_bod_hands() {
+        assert_eq!(parse_count(""), Some(1));
+        assert_eq!(parse_count("九"), Some(9));
+        assert_eq!(parse_count("十"), Some(10));
+        assert_eq!(parse_count("十八"), Some(18));
+        assert_eq!(parse_count("百"), None);
+    }
+}
@@ -0,0 +1,10 @@
+//! The rules and notation logic behind the board, usable without the Yew app.
+
+#![feature(option_result_contains)]
+
+pub mod kanji;
+pub mod move_intent;
+pub mod rng;
+pub mod rules;
+pub mod svg;
+pub mod usi;
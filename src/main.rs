@@ -3,275 +3,807 @@
 
 use base64::{decode, encode};
 use gloo::events::EventListener;
-use gloo::timers::callback::Timeout;
+use gloo::storage::{LocalStorage, Storage};
+use gloo::timers::callback::{Interval, Timeout};
+use js_sys::{Date, Reflect};
+use rand::seq::SliceRandom;
 use shogi::{
     bitboard::Factory as BBFactory, square::Square, Color, Move, MoveRecord, Piece, PieceType,
     Position,
 };
 use std::collections::{HashMap, HashSet};
-use wasm_bindgen::JsValue;
-use yew::web_sys::{Element, HtmlAudioElement};
+use wasm_bindgen::{JsCast, JsValue};
+use yew::web_sys::{Element, File, FileReader, HtmlAudioElement, HtmlInputElement};
 use yew::{prelude::*, utils::window};
 
 mod board;
+mod editor;
+mod game_json;
 mod hand;
+mod hand_balance;
+mod host_events;
+mod i18n;
+mod kif;
+mod notation;
+mod palette;
 mod piece;
+mod preset;
+mod settings;
 mod shareable_link;
+mod status_bar;
+mod tsume;
 
-use board::Board;
+use board::{Board, Pieces};
+use game_json::{GameJson, GameMeta, GameResultJson, MetaField, SettingsJson, SCHEMA_VERSION};
 use hand::{Hand, HandPiece};
+use hand_balance::HandBalance;
+use i18n::{t, t_fill, t_fill_each, Lang};
+use notation::{
+    format_move, format_move_western, piece_type_name, position_to_bod, relative_movement,
+    spoken_move,
+};
+use palette::Palette;
+use piece::PieceView;
+use preset::{load_presets, save_presets, Preset};
+use settings::{
+    color_from_name, color_name, AutoPromotePolicy, CandidateStrategy, DiagramArrows, DropStyle,
+    HistoryLayout, MoveNumbering, Notation, PieceStyle, PieceStyles, Settings, ShareScope,
+};
 use shareable_link::ShareableLink;
+use shogi_board::kanji::{piece_kanji, side_mark};
+use shogi_board::move_intent::{MoveIntentBuilder, Origin};
+use shogi_board::rng::XorShift64;
+use shogi_board::rules::{
+    can_move_between, check_undo_symmetry, declare_impasse, diagnose, diagnose_sfen, game_status,
+    is_legal, king_danger_squares, legal_drop_count, legal_moves, material_balance, pinned_squares,
+    random_position, repair_sfen, sfen_with_side_to_move, try_rule_status, Diagnosis, GameStatus,
+    ImpasseRule,
+};
+use shogi_board::svg::position_to_svg;
+use shogi_board::usi::{move_to_usi, parse_move, parse_square, square_to_usi};
+use status_bar::StatusBar;
 
-fn coord_index_to_full_width_latin(index: u8) -> &'static str {
-    match index {
-        0 => "１",
-        1 => "２",
-        2 => "３",
-        3 => "４",
-        4 => "５",
-        5 => "６",
-        6 => "７",
-        7 => "８",
-        8 => "９",
-        _ => unreachable!(),
-    }
+/// Percent-encodes everything except unreserved characters and path separators.
+fn percent_encode(text: &str) -> String {
+    text.bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => {
+                (byte as char).to_string()
+            }
+            _ => format!("%{:02X}", byte),
+        })
+        .collect()
 }
 
-fn coord_index_to_japanese_numeral(index: u8) -> &'static str {
-    match index {
-        0 => "一",
-        1 => "二",
-        2 => "三",
-        3 => "四",
-        4 => "五",
-        5 => "六",
-        6 => "七",
-        7 => "八",
-        8 => "九",
-        _ => unreachable!(),
+const STARTING_SFEN: &str = "lnsgkgsnl/1r5b1/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL b - 1";
+
+/// Remembers the side the computer plays, or that there is no computer opponent.
+fn store_cpu_color(cpu_color: Option<Color>) {
+    match cpu_color {
+        Some(cpu_color) => {
+            let _ = LocalStorage::set(CPU_COLOR_STORAGE_KEY, color_name(cpu_color));
+        }
+        None => LocalStorage::delete(CPU_COLOR_STORAGE_KEY),
     }
 }
 
-#[derive(Clone, Copy)]
-enum Origin {
-    SquarePiece(Square),
-    HeldPiece(PieceType),
+/// Reads a saved piece style, falling back to kanji.
+fn stored_piece_style(key: &str) -> PieceStyle {
+    LocalStorage::get(key)
+        .ok()
+        .and_then(|name: String| PieceStyle::from_name(&name))
+        .unwrap_or(PieceStyle::Kanji)
 }
 
-impl Origin {
-    pub fn square(self) -> Option<Square> {
-        match self {
-            Self::SquarePiece(square) => Some(square),
-            Self::HeldPiece(..) => None,
+/// Undoes percent-encoding, which some apps apply to the `+`, `/` and `=` of base64 when
+/// passing a link along. Malformed escapes are left as they are.
+fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+    while index < bytes.len() {
+        let escaped = bytes
+            .get(index + 1..index + 3)
+            .filter(|_| bytes[index] == b'%')
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                index += 3;
+            }
+            None => {
+                decoded.push(bytes[index]);
+                index += 1;
+            }
         }
     }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
 
-    pub fn hand_piece_type(self) -> Option<PieceType> {
-        match self {
-            Self::SquarePiece(..) => None,
-            Self::HeldPiece(piece_type) => Some(piece_type),
-        }
+/// A short duration such as "12s" or "3m05s".
+fn format_seconds(seconds: u32) -> String {
+    if seconds < 60 {
+        format!("{}s", seconds)
+    } else {
+        format!("{}m{:02}s", seconds / 60, seconds % 60)
     }
+}
 
-    pub fn piece(self, position: &Position) -> Option<Piece> {
-        match self {
-            Origin::SquarePiece(from_square) => *position.piece_at(from_square),
-            Origin::HeldPiece(piece_type) => Some(Piece {
-                piece_type,
-                color: position.side_to_move(),
-            }),
-        }
+/// Reads the SFEN out of the first part of a shared link's fragment.
+fn decode_hash_sfen(encoded_sfen: &str) -> Result<String, String> {
+    // A `+` turned into a space by a form encoder would otherwise fail to decode.
+    let encoded_sfen = percent_decode(encoded_sfen).replace(' ', "+");
+    let decoded = decode(encoded_sfen.trim()).map_err(|err| err.to_string())?;
+    String::from_utf8(decoded).map_err(|err| err.to_string())
+}
+
+/// Splits a URL fragment into its SFEN and the `name=value` parts that follow it.
+fn split_url_hash(hash: &str) -> Result<(String, Vec<&str>), String> {
+    let hash = hash.strip_prefix('#').unwrap_or(hash);
+    if hash.is_empty() {
+        return Err("No hash".to_string());
     }
+    let mut parts = hash.split('&');
+    let sfen = decode_hash_sfen(parts.next().unwrap_or_default())?;
+    Ok((sfen, parts.collect()))
 }
 
-enum Msg {
-    ClickSquare(Square),
-    ClickHeldPiece(PieceType, Color),
-    ChoosePromote(bool),
-    Restart,
-    Undo,
-    LoadFromUrl,
+/// The value of the query parameter `name` as it appears in `search`, still encoded.
+fn raw_query_param<'a>(search: &'a str, name: &str) -> Option<&'a str> {
+    search.trim_start_matches('?').split('&').find_map(|pair| {
+        let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+        (key == name).then(|| value)
+    })
 }
 
-#[derive(Clone, Copy)]
-enum MoveIntentBuilder {
-    NoIntent,
-    WithOrigin { from: Origin },
-    WithDestination { from: Origin, to: Square },
+/// The value of the query parameter `name`, decoded the way a form encodes it.
+fn query_param(search: &str, name: &str) -> Option<String> {
+    raw_query_param(search, name).map(|value| percent_decode(&value.replace('+', " ")))
 }
 
-impl MoveIntentBuilder {
-    fn create_sandbox(position: &Position) -> Position {
-        let mut sandbox_position = Position::new();
-        sandbox_position.set_sfen(&position.to_sfen()).unwrap();
-        sandbox_position
-    }
+/// A game given in the query string.
+struct QueryGame {
+    start_sfen: String,
+    moves: Vec<MoveRecord>,
+    /// The ply to show, which is the last one unless the link gives another.
+    ply: usize,
+}
 
-    pub fn can_move_to(self, square: Square, position: &Position) -> bool {
-        let mut sandbox_position = Self::create_sandbox(&position);
-        match self {
-            MoveIntentBuilder::WithOrigin { from } => {
-                let try_moves = match from {
-                    Origin::SquarePiece(from_square) => {
-                        vec![
-                            Move::Normal {
-                                from: from_square,
-                                to: square,
-                                promote: true,
-                            },
-                            Move::Normal {
-                                from: from_square,
-                                to: square,
-                                promote: false,
-                            },
-                        ]
-                    }
-                    Origin::HeldPiece(piece_type) => {
-                        vec![Move::Drop {
-                            piece_type,
-                            to: square,
-                        }]
-                    }
-                };
-                for try_move in try_moves {
-                    if sandbox_position.make_move(try_move).is_ok() {
-                        return true;
-                    }
-                }
-                false
-            }
-            _ => panic!(),
+/// Reads `?sfen=` for a position, and `?usi=` for USI moves played from it, or from the
+/// starting position if there is no SFEN. The moves are separated by spaces written as
+/// `%20`. A `+` in `?usi=` is refused rather than read as a space, as a hand-typed link
+/// most likely means a promotion by it, which has to be written `%2B`.
+fn parse_query_game(search: &str) -> Result<QueryGame, String> {
+    let start_sfen = query_param(search, "sfen").map(|sfen| sfen.trim().to_owned());
+    let usi = match (query_param(search, "usi"), start_sfen.as_deref()) {
+        (Some(usi), _) => usi,
+        (None, Some(sfen)) => {
+            return Ok(QueryGame {
+                start_sfen: sfen.to_owned(),
+                moves: Vec::new(),
+                ply: 0,
+            })
         }
+        (None, None) => return Err("No query".to_owned()),
+    };
+    if raw_query_param(search, "usi").map_or(false, |raw_usi| raw_usi.contains('+')) {
+        return Err("Write the + of a promotion as %2B in ?usi=".to_owned());
+    }
+    let start_sfen = start_sfen.unwrap_or_else(|| STARTING_SFEN.to_owned());
+    let mut position = parse_sfen(&start_sfen)?;
+    for text in usi.split_whitespace() {
+        let next_move = parse_move(text).ok_or_else(|| format!("Unreadable move {}", text))?;
+        position
+            .make_move(next_move)
+            .map_err(|err| format!("{}: {}", text, err))?;
     }
+    let moves = position.move_history().to_vec();
+    let ply = query_param(search, "ply")
+        .and_then(|ply| ply.parse().ok())
+        .unwrap_or_else(|| moves.len());
+    Ok(QueryGame {
+        start_sfen,
+        moves,
+        ply,
+    })
+}
 
-    pub fn must_promote(self, position: &Position) -> bool {
-        match self {
-            MoveIntentBuilder::WithDestination {
-                from: Origin::SquarePiece(from),
-                to,
-            } => {
-                let mut sandbox_position = Self::create_sandbox(&position);
-                sandbox_position
-                    .make_move(Move::Normal {
-                        from,
-                        to,
-                        promote: false,
-                    })
-                    .is_err()
+/// Whether a key event is going to a text box, where keys are the user's typing.
+fn is_typing_into(event: &Event) -> bool {
+    event
+        .target()
+        .and_then(|target| target.dyn_into::<Element>().ok())
+        .map_or(false, |element| {
+            matches!(element.tag_name().as_str(), "INPUT" | "TEXTAREA")
+        })
+}
+
+/// The `result` and `winner` of a `shogi-gameover` event, with "draw" for the winner of
+/// a drawn game, or nothing while the game goes on.
+fn game_over_detail(status: GameStatus) -> Option<(&'static str, &'static str)> {
+    let result = status.result_name()?;
+    Some((result, status.winner().map_or("draw", color_name)))
+}
+
+/// Explains a rule of play that a position breaks.
+fn describe_diagnosis(diagnosis: &Diagnosis, lang: Lang) -> String {
+    match diagnosis {
+        Diagnosis::Unparseable(error) => t_fill("Can't read the SFEN: {}", lang, error),
+        Diagnosis::DeadPiece { piece, square } => {
+            let piece_name = t(piece_type_name(piece.piece_type), lang);
+            let values: [&dyn std::fmt::Display; 2] = [&piece_name, &square_to_usi(*square)];
+            match piece.color {
+                Color::Black => t_fill_each("Black {} on {} can never move", lang, &values),
+                Color::White => t_fill_each("White {} on {} can never move", lang, &values),
             }
-            MoveIntentBuilder::WithDestination {
-                from: Origin::HeldPiece(_),
-                to: _,
-            } => false,
-            _ => panic!(),
         }
-    }
-
-    pub fn cant_promote(self, position: &Position) -> bool {
-        match self {
-            MoveIntentBuilder::WithDestination {
-                from: Origin::SquarePiece(from),
-                to,
-            } => {
-                let mut sandbox_position = Self::create_sandbox(&position);
-                sandbox_position
-                    .make_move(Move::Normal {
-                        from,
-                        to,
-                        promote: true,
-                    })
-                    .is_err()
+        Diagnosis::Nifu { color, file, count } => {
+            let values: [&dyn std::fmt::Display; 2] = [count, &(file + 1)];
+            match color {
+                Color::Black => t_fill_each("Black has {} pawns on file {} (nifu)", lang, &values),
+                Color::White => t_fill_each("White has {} pawns on file {} (nifu)", lang, &values),
             }
-            MoveIntentBuilder::WithDestination {
-                from: Origin::HeldPiece(_),
-                to: _,
-            } => true,
-            _ => panic!(),
+        }
+        Diagnosis::WrongKingCount { color, count } => match color {
+            Color::Black => t_fill("Black has {} kings instead of one", lang, count),
+            Color::White => t_fill("White has {} kings instead of one", lang, count),
+        },
+        Diagnosis::WaitingSideInCheck { color } => match color {
+            Color::Black => t("Black is in check while it's White's turn", lang).to_owned(),
+            Color::White => t("White is in check while it's Black's turn", lang).to_owned(),
+        },
+        Diagnosis::PromotedPieceInHand => {
+            t("Promoted pieces can't be held in hand", lang).to_owned()
         }
     }
+}
 
-    pub fn move_origin_candidates(self, position: &Position) -> HashSet<Square> {
-        match self {
-            Self::NoIntent => Square::iter()
-                .filter(|square| {
-                    position
-                        .piece_at(*square)
-                        .filter(|piece| piece.color == position.side_to_move())
-                        .is_some()
-                })
-                .collect(),
-            Self::WithOrigin { .. } => Default::default(),
-            Self::WithDestination { .. } => Default::default(),
+/// Drops the query string from the address, keeping the page and its fragment.
+fn strip_query() {
+    let location = window().location();
+    if let (Ok(history), Ok(pathname), Ok(hash)) =
+        (window().history(), location.pathname(), location.hash())
+    {
+        let _ = history.replace_state_with_url(
+            &JsValue::NULL,
+            "",
+            Some(&format!("{}{}", pathname, hash)),
+        );
+    }
+}
+
+fn parse_sfen(sfen: &str) -> Result<Position, String> {
+    let mut position = Position::new();
+    position.set_sfen(sfen).map_err(|err| err.to_string())?;
+    Ok(position)
+}
+
+fn pieces_of(position: &Position) -> Pieces {
+    let mut pieces = [None; 81];
+    for square in Square::iter() {
+        pieces[square.index()] = *position.piece_at(square);
+    }
+    pieces
+}
+
+/// The KIF header lines for where the game started: the handicap line for the usual
+/// start and the standard handicaps, otherwise the whole position as a BOD diagram,
+/// which KIF readers take in place of a handicap.
+fn kif_starting_position(start_sfen: &str) -> String {
+    if let Some(handicap) = kif::handicap_name(start_sfen) {
+        return format!("手合割：{}\n", handicap);
+    }
+    let mut start_position = Position::new();
+    if start_sfen == STARTING_SFEN || start_position.set_sfen(start_sfen).is_err() {
+        return "手合割：平手\n".to_owned();
+    }
+    position_to_bod(&start_position)
+}
+
+/// Where the move before `turn` landed, or nothing for the first move in the history.
+fn previous_move_destination(game_records: &[MoveRecord], turn: usize) -> Option<&Square> {
+    turn.checked_sub(1)
+        .and_then(|previous_turn| game_records.get(previous_turn))
+        .map(|previous_move| match previous_move {
+            MoveRecord::Normal { to, .. } => to,
+            MoveRecord::Drop { to, .. } => to,
+        })
+}
+
+/// The squares whose contents differ between the game and the reference position.
+fn reference_differences(position: &Position, reference: &Position) -> HashSet<Square> {
+    Square::iter()
+        .filter(|square| position.piece_at(*square) != reference.piece_at(*square))
+        .collect()
+}
+
+/// Must match the height of `.history-item` in the stylesheet.
+const HISTORY_ROW_HEIGHT_PX: i32 = 18;
+/// Shorter games render every history row.
+const HISTORY_VIRTUALIZATION_THRESHOLD: usize = 200;
+/// Rows rendered beyond each edge of the visible part of the history.
+const HISTORY_OVERSCAN_ROWS: usize = 20;
+/// Must match the width of `.history` in the stylesheet.
+const DEFAULT_HISTORY_WIDTH_PX: f64 = 200.0;
+const MIN_HISTORY_WIDTH_PX: f64 = 160.0;
+const MAX_HISTORY_WIDTH_PX: f64 = 600.0;
+/// The narrowest a column of the vertical history gets when fitting columns to the
+/// panel, which keeps the default width to a single column.
+const HISTORY_COLUMN_MIN_WIDTH_PX: f64 = 180.0;
+const HISTORY_WIDTH_STORAGE_KEY: &str = "history-width";
+const BLACK_PIECE_STYLE_STORAGE_KEY: &str = "black-piece-style";
+const WHITE_PIECE_STYLE_STORAGE_KEY: &str = "white-piece-style";
+const SHOW_DOTS_STORAGE_KEY: &str = "show-board-dots";
+const HIGH_CONTRAST_STORAGE_KEY: &str = "high-contrast";
+const STRICT_URL_POSITIONS_STORAGE_KEY: &str = "strict-url-positions";
+const LAST_SFEN_STORAGE_KEY: &str = "last-sfen";
+const LANG_STORAGE_KEY: &str = "lang";
+const CPU_COLOR_STORAGE_KEY: &str = "cpu-color";
+/// Deeper searches take too long to run in the browser.
+const MATE_SEARCH_MAX_PLIES: u8 = 7;
+/// How many random moves the undo check plays before unwinding them.
+const UNDO_CHECK_PLIES: usize = 40;
+/// Each random move tries every move on the board, so long walks stall the page.
+const MAX_RANDOM_POSITION_PLIES: usize = 200;
+const SPARKLINE_HEIGHT_PX: f64 = 40.0;
+/// Must match the duration of the `just-gained` animation in the stylesheet.
+const JUST_GAINED_PULSE_MS: u32 = 600;
+/// Must match the duration of the `changed` animation in the stylesheet.
+const CHANGED_SQUARES_FLASH_MS: u32 = 1500;
+/// Must match the duration of the `mobility-preview` animation in the stylesheet.
+const MOBILITY_PREVIEW_MS: u32 = 1000;
+/// How long a lesson pauses before playing the opponent's reply.
+const LESSON_REPLY_DELAY_MS: u32 = 500;
+/// How long the explanation of a refused pawn-drop mate stays up.
+const UCHIFUZUME_FEEDBACK_MS: u32 = 2000;
+/// How often the byoyomi is checked for a tick, which is once a second.
+const BYOYOMI_TICK_INTERVAL_MS: u32 = 1000;
+
+fn move_from_record(move_record: &MoveRecord) -> Move {
+    match *move_record {
+        MoveRecord::Normal {
+            from, to, promoted, ..
+        } => Move::Normal {
+            from,
+            to,
+            promote: promoted,
+        },
+        MoveRecord::Drop { to, piece } => Move::Drop {
+            to,
+            piece_type: piece.piece_type,
+        },
+    }
+}
+
+/// Takes back the last move, keeping its record at the end of `future_moves` to be
+/// redone. Returns `false` if there was no move to take back.
+fn undo_into(position: &mut Position, future_moves: &mut Vec<MoveRecord>) -> bool {
+    match position.move_history().last().cloned() {
+        Some(last_move) => {
+            position.unmake_move().unwrap();
+            future_moves.push(last_move);
+            true
         }
+        None => false,
     }
+}
 
-    pub fn move_destination_candidates(self, position: &Position) -> HashSet<Square> {
-        match self {
-            Self::NoIntent => Default::default(),
-            Self::WithOrigin { .. } => Square::iter()
-                .filter(|square| self.can_move_to(*square, position))
-                .collect(),
-            Self::WithDestination { .. } => Default::default(),
+/// Replays the last record of `future_moves` exactly as it was played, including
+/// whether it promoted. Returns `false` if there was nothing to redo.
+fn redo_from(position: &mut Position, future_moves: &mut Vec<MoveRecord>) -> bool {
+    match future_moves.pop() {
+        Some(next_move) => {
+            position.make_move(move_from_record(&next_move)).unwrap();
+            true
         }
+        None => false,
     }
+}
 
-    pub fn move_origin_square(self) -> Option<Square> {
-        match self {
-            Self::NoIntent => None,
-            Self::WithOrigin { from } => from.square(),
-            Self::WithDestination { from, .. } => from.square(),
+#[derive(Clone, Copy, PartialEq)]
+enum PuzzleStatus {
+    Unsolved,
+    Solved,
+    /// The last attempt was wrong and has been taken back.
+    Missed,
+}
+
+/// How the last attempt at tsume practice ended.
+#[derive(Clone, Copy, PartialEq)]
+enum TsumeResult {
+    Mated,
+    /// Tsume moves must all check, and the last one didn't.
+    NotCheck,
+    /// The search found no mate within `MATE_SEARCH_MAX_PLIES` of the attacker's move,
+    /// counting it, or gave up before it could.
+    NoMateFound,
+}
+
+/// A "find the best move" challenge for the position at `ply`, loaded from a link.
+struct Puzzle {
+    solution: Move,
+    ply: usize,
+    status: PuzzleStatus,
+}
+
+impl Puzzle {
+    fn hint(&self, lang: Lang) -> String {
+        match self.solution {
+            Move::Normal { from, .. } => t_fill("Try the piece on {}.", lang, square_to_usi(from)),
+            Move::Drop { .. } => t("Try a piece from your hand.", lang).to_owned(),
         }
     }
+}
+
+/// A whole file or rank picked out on the board as a teaching aid.
+#[derive(Clone, Copy, PartialEq)]
+enum BoardLine {
+    File(u8),
+    Rank(u8),
+}
 
-    pub fn move_origin_hand_piece_type(self) -> Option<PieceType> {
+impl BoardLine {
+    fn contains(self, square: Square) -> bool {
         match self {
-            Self::NoIntent => None,
-            Self::WithOrigin { from } => from.hand_piece_type(),
-            Self::WithDestination { from, .. } => from.hand_piece_type(),
+            Self::File(file) => square.file() == file,
+            Self::Rank(rank) => square.rank() == rank,
         }
     }
+}
+
+/// A text export of the game, which is only built when asked for.
+#[derive(Clone, Copy, PartialEq)]
+enum ExportFormat {
+    Kif,
+    Bod,
+    Svg,
+    Usi,
+    Json,
+}
 
-    pub fn move_origin_piece(self, position: &Position) -> Option<Piece> {
+impl ExportFormat {
+    /// The id of the box the export is shown in.
+    fn id(self) -> &'static str {
         match self {
-            Self::NoIntent => None,
-            Self::WithOrigin { from } => from.piece(position),
-            Self::WithDestination { from, .. } => from.piece(position),
+            Self::Kif => "kif-export",
+            Self::Bod => "bod-export",
+            Self::Svg => "svg-export",
+            Self::Usi => "usi-export",
+            Self::Json => "json-export",
         }
     }
 
-    pub fn move_destination(self) -> Option<Square> {
+    fn label(self) -> &'static str {
         match self {
-            Self::NoIntent => None,
-            Self::WithOrigin { .. } => None,
-            Self::WithDestination { to, .. } => Some(to),
+            Self::Kif => "KIF",
+            Self::Bod => "BOD diagram",
+            Self::Svg => "SVG diagram",
+            Self::Usi => "USI moves",
+            Self::Json => "JSON",
         }
     }
+}
 
-    pub fn is_asking_promotion_with_piece(self, position: &Position) -> Option<Piece> {
-        match self {
-            Self::NoIntent => None,
-            Self::WithOrigin { .. } => None,
-            Self::WithDestination { from, .. } => from.piece(position),
+/// An export that was asked for, kept until the game or the viewed position changes.
+struct Export {
+    format: ExportFormat,
+    text: String,
+    /// The game and viewed position the export was built from.
+    source: String,
+}
+
+/// A drag of the divider beside the history panel that is under way.
+struct HistoryResize {
+    start_x: i32,
+    start_width: f64,
+    _mouse_move_listener: EventListener,
+    _mouse_up_listener: EventListener,
+}
+
+#[derive(Clone, Copy)]
+enum MoveSoundKind {
+    Quiet,
+    Capture,
+    MajorCapture,
+}
+
+impl MoveSoundKind {
+    fn for_captured(captured: Option<Piece>) -> Self {
+        match captured.map(|piece| piece.piece_type) {
+            None => Self::Quiet,
+            Some(PieceType::Rook)
+            | Some(PieceType::Bishop)
+            | Some(PieceType::ProRook)
+            | Some(PieceType::ProBishop) => Self::MajorCapture,
+            Some(_) => Self::Capture,
         }
     }
 }
 
+enum Msg {
+    ClickSquare(Square),
+    ClickHeldPiece(PieceType, Color),
+    ChoosePromote(bool),
+    CancelMove,
+    Restart,
+    ImportJson,
+    DisarmRestart,
+    ToggleConfirmRestart,
+    ToggleConfirmOverwrite,
+    StartPreset(usize),
+    SavePreset,
+    RandomPosition,
+    SetRandomPositionPlies(usize),
+    ToggleEditing,
+    SelectPalettePiece(Piece),
+    ClearSquare(Square),
+    Undo,
+    UndoMyMove,
+    LessonReply,
+    SetCpuColor(Option<Color>),
+    SetCpuThinkMs(u32),
+    SetCpuSeed(Option<u64>),
+    SetByoyomi(Option<u32>),
+    SetByoyomiTickSeconds(u32),
+    ByoyomiTick,
+    SetShareScope(ShareScope),
+    SetDiagramArrows(DiagramArrows),
+    Export(ExportFormat),
+    CpuMove,
+    Redo,
+    LoadFromUrl,
+    ToggleHeatmap,
+    ToggleHints,
+    ToggleMute,
+    SetNotation(Notation),
+    SetDropStyle(DropStyle),
+    SetPieceStyle(Color, PieceStyle),
+    SetLang(Lang),
+    SetAutoPromote(AutoPromotePolicy),
+    SetCandidateStrategy(CandidateStrategy),
+    SetImpasseRule(ImpasseRule),
+    ToggleTryRule,
+    DeclareImpasse,
+    ToggleBlindfold,
+    ToggleFlip,
+    OpponentView,
+    ToggleCoordinates,
+    ToggleDots,
+    ToggleHighContrast,
+    ToggleStrictUrlPositions,
+    ToggleAutoCopyLink,
+    StopJustGained,
+    StopChangedSquares,
+    StopMobilityPreview,
+    ToggleMobilityPreview,
+    StopUchifuzumeFeedback,
+    TsumeReply,
+    ToggleTsumeDefence,
+    ToggleExplainUchifuzume,
+    TogglePromotionZones,
+    TogglePins,
+    ToggleOpponentMoves,
+    ToggleDanger,
+    ToggleDropCounts,
+    ToggleFocusSide,
+    ToggleFollowGame,
+    ToggleDebug,
+    ToggleSquareIndices,
+    SetSquareBorder(u32),
+    SetPieceInset(u32),
+    CheckUndoSymmetry,
+    ToggleHandBalance,
+    ToggleCaptureLog,
+    HoverSquare(Option<Square>),
+    HighlightFile(u8),
+    HighlightRank(u8),
+    ToggleBlindfoldRevealOnHover,
+    RevealPieces,
+    HidePieces,
+    SearchMate,
+    TypeDestination(String),
+    SubmitUsi(String),
+    StopUsiInputFlash,
+    StopDestinationInputShake,
+    GotoPly(usize),
+    ToggleReplay,
+    ReplayStep,
+    SetReplaySpeed(u32),
+    SetTrailLength(usize),
+    SetHistoryColumns(Option<usize>),
+    SetMoveNumbering(Option<MoveNumbering>),
+    SetSfen(String),
+    SetReferenceSfen(String),
+    ClearReference,
+    DropFile(File),
+    LoadDroppedText(String),
+    SetSideToMove(Color),
+    StopSfenInputFlash,
+    ScrollHistory { scroll_top: i32, height: i32 },
+    StartHistoryResize(i32),
+    ResizeHistory(i32),
+    StopHistoryResize,
+    SetComment(String),
+    SetMetaField(MetaField, String),
+}
+
 struct Model {
     link: ComponentLink<Self>,
     position: Position,
+    /// Moves that were stepped back over, with the next move to replay last.
+    future_moves: Vec<MoveRecord>,
+    /// Notes on the position after the given number of plies.
+    comments: HashMap<usize, String>,
+    /// The players, date and event, which carry over from one game to the next.
+    meta: GameMeta,
+    /// Seconds spent thinking over the move that reached the given number of plies, for
+    /// moves played here rather than loaded.
+    move_times: HashMap<usize, u32>,
+    /// When the current position was first shown, in milliseconds since the epoch.
+    last_move_instant: f64,
     move_intent: MoveIntentBuilder,
+    settings: Settings,
+    replay: Option<Interval>,
+    replay_speed_ms: u32,
+    /// Runs while a byoyomi is set, checking each second whether to tick.
+    byoyomi_ticker: Option<Interval>,
     move_audio_ref: NodeRef,
+    capture_audio_ref: NodeRef,
+    major_capture_audio_ref: NodeRef,
+    tick_audio_ref: NodeRef,
     history_bottom_ref: NodeRef,
+    history_scroll_top: i32,
+    history_height: i32,
+    history_width: f64,
+    history_resize: Option<HistoryResize>,
+    destination_input_ref: NodeRef,
+    usi_input_ref: NodeRef,
+    /// Set while the USI move input shows that its move couldn't be played.
+    usi_input_flash: Option<Timeout>,
+    should_focus_destination_input: bool,
+    destination_input_shake: Option<Timeout>,
+    sfen_input_ref: NodeRef,
+    sfen_input_flash: Option<Timeout>,
+    /// The last SFEN typed in that couldn't be loaded, kept to explain what's wrong.
+    rejected_sfen: Option<String>,
+    /// Why the position in the link was refused, when only playable positions are allowed.
+    refused_url_violations: Vec<Diagnosis>,
+    /// Pieces are shown despite the blindfold until this fires.
+    reveal_pieces: Option<Timeout>,
+    /// The hand piece a capture just added, which pulses until this fires.
+    just_gained: Option<(Piece, Timeout)>,
+    /// The squares a newly loaded position changed, highlighted until this fires.
+    changed_squares: Option<(HashSet<Square>, Timeout)>,
+    /// Where the piece just moved could go next, flashed until this fires.
+    mobility_preview: Option<(HashSet<Square>, Timeout)>,
+    /// Explains that the pawn drop just tried gives checkmate, until this fires.
+    uchifuzume_feedback: Option<Timeout>,
+    /// A first click on Restart arms it until this fires; a second click restarts.
+    restart_armed: Option<Timeout>,
+    /// The computer's reply, waiting to be played.
+    cpu_move: Option<Timeout>,
+    /// Makes the computer's choices when it is seeded, restarting with each game.
+    cpu_rng: Option<XorShift64>,
+    /// How many random moves "Random position" plays from the start.
+    random_position_plies: usize,
+    /// A scripted line of USI moves to follow from `lesson_start_ply`. The player's moves
+    /// alternate with replies that are played for them.
+    lesson: Option<Vec<String>>,
+    lesson_start_ply: usize,
+    /// Whether the last move tried in the lesson wasn't the expected one.
+    lesson_mistake: bool,
+    lesson_reply: Option<Timeout>,
+    /// The defender's reply in tsume practice, played when this fires.
+    tsume_reply: Option<Timeout>,
+    tsume_result: Option<TsumeResult>,
+    /// The SFEN that was last searched for a mate, and the mate length found.
+    mate_search: Option<(String, Option<u8>)>,
+    puzzle: Option<Puzzle>,
+    highlighted_line: Option<BoardLine>,
+    /// In edit mode, clicking a square places `palette_piece` there instead of moving.
+    editing: bool,
+    /// Whether the colors are swapped by the opponent view.
+    opponent_view: bool,
+    palette_piece: Option<Piece>,
+    hovered_square: Option<Square>,
     _hash_change_listener: EventListener,
+    /// Escape anywhere on the page but a text box gives up the move being chosen.
+    _escape_listener: EventListener,
+    /// The URL fragment last written, so it is only rewritten when it changes.
+    synced_url_hash: String,
+    /// Reads a file dropped onto the game, until it has loaded.
+    file_reader: Option<(FileReader, EventListener)>,
+    presets: Vec<Preset>,
+    /// A second, read-only position shown beside the game for comparison.
+    reference_position: Option<Position>,
+    /// The ply at which an impasse was declared, and how it was settled.
+    declared_impasse: Option<(usize, GameStatus)>,
+    /// The position the game started from, before any of its moves.
+    start_sfen: String,
+    /// The outcome of the last random undo check: how many moves were undone, or what
+    /// went wrong.
+    undo_check: Option<Result<usize, String>>,
+    /// The start and moves of the game last replayed, so it is only replayed again when
+    /// they change.
+    analysed_game: String,
+    /// Which of several identical pieces made each move of the game.
+    move_disambiguations: Vec<String>,
+    /// The material balance before the game's first move and after each of its moves.
+    material_balances: Vec<i32>,
+    export: Option<Export>,
 }
 
 impl Model {
     fn reset(&mut self) {
         self.position = Position::new();
         self.position
-            .set_sfen("lnsgkgsnl/1r5b1/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL b - 1")
+            .set_sfen(STARTING_SFEN)
             .expect("Starting position should be valid");
-        self.play_move_sound();
+        self.start_sfen = STARTING_SFEN.to_owned();
+        self.cpu_move = None;
+        self.reseed_cpu();
+        self.future_moves.clear();
+        self.comments.clear();
+        self.move_times.clear();
+        self.last_move_instant = Date::now();
+        self.declared_impasse = None;
+        self.play_move_sound(MoveSoundKind::Quiet);
+    }
+
+    /// Loads the first position found in, in order: the query string, for embedders that
+    /// strip fragments; the URL fragment; the position last shown in this browser; and
+    /// finally the usual starting position. A loaded query string is then taken out of the
+    /// address, as the fragment is kept up to date from here on and a reload should
+    /// return to the game as played rather than as linked.
+    fn load_initial_position(&mut self) {
+        let loaded = if self.try_load_from_query().is_ok() {
+            strip_query();
+            true
+        } else {
+            self.try_load_from_url().is_ok() || self.try_load_from_storage().is_ok()
+        };
+        if !loaded {
+            self.reset();
+        }
+    }
+
+    /// Loads the game in the query string, if any, keeping its moves in the history, which
+    /// is shown at `?ply=` if given and at the last move otherwise.
+    fn try_load_from_query(&mut self) -> Result<(), String> {
+        let search = window()
+            .location()
+            .search()
+            .map_err(|err| err.as_string().unwrap_or_default())?;
+        let game = parse_query_game(&search)?;
+        self.load_url_sfen(&game.start_sfen)?;
+        if !game.moves.is_empty() {
+            self.future_moves = game.moves.into_iter().rev().collect();
+            self.goto_ply(game.ply);
+        }
+        Ok(())
+    }
+
+    /// Loads a position given in the URL, first checking that it is playable if the
+    /// settings require it.
+    fn load_url_sfen(&mut self, sfen: &str) -> Result<(), String> {
+        if self.settings.strict_url_positions {
+            let mut position = Position::new();
+            position.set_sfen(sfen).map_err(|err| err.to_string())?;
+            let violations = diagnose(&position);
+            if !violations.is_empty() {
+                self.refused_url_violations = violations;
+                return Err("The position breaks the rules".to_owned());
+            }
+        }
+        self.refused_url_violations.clear();
+        self.load_sfen(sfen)
+    }
+
+    fn try_load_from_storage(&mut self) -> Result<(), String> {
+        let sfen: String =
+            LocalStorage::get(LAST_SFEN_STORAGE_KEY).map_err(|err| err.to_string())?;
+        self.load_sfen(&sfen)
     }
 
     fn try_load_from_url(&mut self) -> Result<(), String> {
@@ -279,123 +811,1891 @@ impl Model {
             .location()
             .hash()
             .map_err(|err| err.as_string().unwrap_or_default())?;
-        if hash.is_empty() {
-            return Err("No hash".to_string());
+        let (sfen, parts) = split_url_hash(&hash)?;
+        self.load_url_sfen(&sfen)?;
+        let solution = parts
+            .iter()
+            .filter_map(|part| part.strip_prefix("solution="))
+            .find_map(parse_move)
+            .filter(|solution| is_legal(&mut self.position, *solution));
+        self.puzzle = solution.map(|solution| Puzzle {
+            solution,
+            ply: self.position.move_history().len(),
+            status: PuzzleStatus::Unsolved,
+        });
+        if let Some(lesson) = parts
+            .iter()
+            .find_map(|part| part.strip_prefix("lesson="))
+            .and_then(|lesson| self.parse_lesson(&percent_decode(lesson)))
+        {
+            self.lesson = Some(lesson);
+            self.lesson_start_ply = self.position.move_history().len();
         }
-        let hash_without_prefix = &hash[1..];
-        let decoded = decode(hash_without_prefix).map_err(|err| err.to_string())?;
-        let sfen = std::str::from_utf8(&decoded).map_err(|err| err.to_string())?;
-        self.position = Position::new();
-        self.position
-            .set_sfen(sfen)
-            .map_err(|err| err.to_string())?;
-        self.play_move_sound();
         Ok(())
     }
 
-    fn undo(&mut self) {
-        self.position.unmake_move().unwrap();
-        self.play_move_sound();
+    /// Reads a comma-separated list of USI moves, keeping it only if every move can be
+    /// played in turn from the current position.
+    fn parse_lesson(&self, text: &str) -> Option<Vec<String>> {
+        let mut sandbox_position = Position::new();
+        sandbox_position.set_sfen(&self.position.to_sfen()).ok()?;
+        let moves: Vec<String> = text.split(',').map(|usi| usi.trim().to_owned()).collect();
+        for usi in &moves {
+            sandbox_position.make_move(parse_move(usi)?).ok()?;
+        }
+        Some(moves)
     }
 
-    fn pieces(&self) -> HashMap<Square, Piece> {
-        Square::iter()
-            .filter_map(|square| Some(square).zip(*self.position.piece_at(square)))
-            .collect()
+    /// How far into the lesson the viewed position is.
+    fn lesson_step(&self) -> Option<usize> {
+        self.lesson.as_ref()?;
+        self.position
+            .move_history()
+            .len()
+            .checked_sub(self.lesson_start_ply)
     }
 
-    fn clear_choice(&mut self) {
-        self.move_intent = MoveIntentBuilder::NoIntent;
+    /// The move the player has to find next, if it is their turn in the lesson.
+    fn expected_lesson_move(&self) -> Option<Move> {
+        let step = self.lesson_step()?;
+        if step % 2 == 1 {
+            return None;
+        }
+        parse_move(self.lesson.as_ref()?.get(step)?)
     }
 
-    fn choose_origin(&mut self, from: Origin) {
-        self.move_intent = match self.move_intent {
-            MoveIntentBuilder::NoIntent => MoveIntentBuilder::WithOrigin { from },
-            MoveIntentBuilder::WithOrigin { from: _ } => MoveIntentBuilder::WithOrigin { from },
-            _ => panic!(),
+    fn is_lesson_complete(&self) -> bool {
+        matches!(
+            (self.lesson_step(), &self.lesson),
+            (Some(step), Some(lesson)) if step >= lesson.len()
+        )
+    }
+
+    fn play_lesson_reply(&mut self) {
+        self.lesson_reply = None;
+        let reply = match (self.lesson_step(), &self.lesson) {
+            (Some(step), Some(lesson)) if step % 2 == 1 => {
+                lesson.get(step).and_then(|usi| parse_move(usi))
+            }
+            _ => None,
         };
+        if let Some(reply) = reply {
+            self.play_move(reply);
+        }
     }
 
-    fn choose_destination(&mut self, to: Square) {
-        self.move_intent = match self.move_intent {
-            MoveIntentBuilder::WithOrigin { from } => {
-                MoveIntentBuilder::WithDestination { from, to }
+    /// The URL fragment for the current position, keeping the puzzle's solution while its
+    /// position is shown.
+    fn url_hash(&self) -> String {
+        let encoded_sfen = encode(self.position.to_sfen());
+        match &self.puzzle {
+            Some(puzzle) if puzzle.ply == self.position.move_history().len() => {
+                format!(
+                    "#{}&solution={}",
+                    encoded_sfen,
+                    move_to_usi(puzzle.solution)
+                )
             }
-            MoveIntentBuilder::WithDestination { from, to: _ } => {
-                MoveIntentBuilder::WithDestination { from, to }
+            _ => match &self.lesson {
+                Some(lesson) if self.lesson_step() == Some(0) => {
+                    format!("#{}&lesson={}", encoded_sfen, lesson.join(","))
+                }
+                _ => format!("#{}", encoded_sfen),
+            },
+        }
+    }
+
+    /// Writes the position to the URL fragment and browser storage, but only when it has
+    /// changed since the last write, so unrelated updates don't touch the history state.
+    fn sync_url_hash(&mut self) {
+        let url_hash = self.url_hash();
+        if url_hash == self.synced_url_hash {
+            return;
+        }
+        if let Ok(history) = window().history() {
+            let _ = history.replace_state_with_url(&JsValue::NULL, "", Some(&url_hash));
+        }
+        let _ = LocalStorage::set(LAST_SFEN_STORAGE_KEY, self.position.to_sfen());
+        self.synced_url_hash = url_hash;
+    }
+
+    /// Links to the whole game through the query string, opening at the viewed ply.
+    fn game_url(&self) -> String {
+        let location = window().location();
+        let page = format!(
+            "{}{}",
+            location.origin().unwrap_or_default(),
+            location.pathname().unwrap_or_default()
+        );
+        let mut query = format!("?usi={}", percent_encode(&self.game_usi()));
+        if self.start_sfen != STARTING_SFEN {
+            query = format!("?sfen={}&{}", percent_encode(&self.start_sfen), &query[1..]);
+        }
+        let viewed_ply = self.position.move_history().len();
+        if viewed_ply < self.game_records().len() {
+            query.push_str(&format!("&ply={}", viewed_ply));
+        }
+        format!("{}{}", page, query)
+    }
+
+    fn shareable_url(&self) -> String {
+        match self.settings.share_scope {
+            ShareScope::Position => window().location().href().unwrap_or_default(),
+            ShareScope::Game => self.game_url(),
+        }
+    }
+
+    /// Links to the current position as a puzzle whose answer is the move played next.
+    fn puzzle_url(&self) -> Option<String> {
+        let solution = move_from_record(self.future_moves.last()?);
+        let href = window().location().href().unwrap_or_default();
+        let page = href.split('#').next().unwrap_or_default();
+        Some(format!(
+            "{}#{}&solution={}",
+            page,
+            encode(self.position.to_sfen()),
+            move_to_usi(solution)
+        ))
+    }
+
+    /// Candidate squares are hidden while a puzzle waits for its answer so they don't give
+    /// it away.
+    fn show_hints(&self) -> bool {
+        self.settings.show_hints
+            && !self.editing
+            && !matches!(&self.puzzle, Some(puzzle) if puzzle.status != PuzzleStatus::Solved)
+    }
+
+    /// Checks the move just played against the puzzle, taking it back if it isn't the
+    /// answer. Returns whether the move is still on the board.
+    fn check_puzzle_answer(&mut self, played: Move) -> bool {
+        let ply = self.position.move_history().len();
+        let puzzle = match &mut self.puzzle {
+            Some(puzzle) if puzzle.ply + 1 == ply && puzzle.status != PuzzleStatus::Solved => {
+                puzzle
             }
-            _ => panic!(),
+            _ => return true,
         };
+        if played == puzzle.solution {
+            puzzle.status = PuzzleStatus::Solved;
+            true
+        } else {
+            puzzle.status = PuzzleStatus::Missed;
+            self.step_back();
+            self.future_moves.pop();
+            false
+        }
+    }
 
-        // Skip asking whether to promote if there's only one legal option.
-        if self.move_intent.cant_promote(&self.position) {
-            self.choose_promote(false);
-        } else if self.move_intent.must_promote(&self.position) {
-            self.choose_promote(true);
+    fn load_sfen(&mut self, sfen: &str) -> Result<(), String> {
+        let position = parse_sfen(sfen)?;
+        self.start_sfen = position.to_sfen();
+        self.position = position;
+        self.cpu_move = None;
+        self.reseed_cpu();
+        self.rejected_sfen = None;
+        self.future_moves.clear();
+        self.comments.clear();
+        self.move_times.clear();
+        self.last_move_instant = Date::now();
+        self.puzzle = None;
+        self.lesson = None;
+        self.lesson_mistake = false;
+        self.lesson_reply = None;
+        self.tsume_reply = None;
+        self.tsume_result = None;
+        self.declared_impasse = None;
+        self.clear_choice();
+        self.play_move_sound(MoveSoundKind::Quiet);
+        Ok(())
+    }
+
+    fn set_sfen(&mut self, sfen: &str) {
+        let sfen = sfen.trim();
+        if sfen == self.position.to_sfen() || self.load_sfen(sfen).is_ok() {
+            return;
         }
+        if let Some(input) = self.sfen_input_ref.cast::<HtmlInputElement>() {
+            input.set_value(&self.position.to_sfen());
+        }
+        self.rejected_sfen = Some(sfen.to_owned());
+        self.flash_sfen_input();
     }
 
-    fn choose_promote(&mut self, promote: bool) {
-        match self.move_intent {
-            MoveIntentBuilder::WithDestination { from, to } => {
-                self.play_move_sound();
-                let next_move = match from {
-                    Origin::SquarePiece(from_square) => Move::Normal {
-                        from: from_square,
-                        to,
-                        promote,
-                    },
-                    Origin::HeldPiece(piece_type) => Move::Drop { piece_type, to },
-                };
+    /// Draws attention to the SFEN bar to show that loading a position failed.
+    fn flash_sfen_input(&mut self) {
+        let link = self.link.clone();
+        self.sfen_input_flash = Some(Timeout::new(1000, move || {
+            link.send_message(Msg::StopSfenInputFlash);
+        }));
+    }
 
-                // Scroll after update.
-                let history_bottom_ref = self.history_bottom_ref.clone();
-                Timeout::new(0, move || {
-                    if let Some(history_bottom) = history_bottom_ref.cast::<Element>() {
-                        let _ = history_bottom.scroll_into_view();
-                    }
-                })
-                .forget();
+    fn read_dropped_file(&mut self, file: File) {
+        let reader = match FileReader::new() {
+            Ok(reader) => reader,
+            Err(_) => return,
+        };
+        let link = self.link.clone();
+        let reader_clone = reader.clone();
+        let listener = EventListener::new(&reader, "load", move |_| {
+            let text = reader_clone
+                .result()
+                .ok()
+                .and_then(|result| result.as_string())
+                .unwrap_or_default();
+            link.send_message(Msg::LoadDroppedText(text));
+        });
+        if reader.read_as_text(&file).is_ok() {
+            self.file_reader = Some((reader, listener));
+        }
+    }
+
+    /// Loads a dropped SFEN, KIF or JSON file, replaying a KIF's moves from the position
+    /// or handicap its header starts from. A file that can't be loaded says why.
+    fn load_dropped_text(&mut self, text: &str) {
+        self.file_reader = None;
+        let result = if kif::is_kif(text) {
+            self.load_kif(text)
+        } else if text.trim_start().starts_with('{') {
+            self.from_json(text)
+        } else {
+            self.load_sfen(text.trim())
+        };
+        if let Err(error) = result {
+            self.flash_sfen_input();
+            gloo::dialogs::alert(&format!("Couldn't load the file: {}", error));
+        }
+    }
+
+    /// The whole game, its comments, result and settings as versioned JSON.
+    fn to_json(&self) -> String {
+        let status = self.status();
+        let game_json = GameJson {
+            schema: SCHEMA_VERSION,
+            sfen: self.start_sfen.clone(),
+            moves: self
+                .game_records()
+                .iter()
+                .map(|move_record| move_to_usi(move_from_record(move_record)))
+                .collect(),
+            ply: self.position.move_history().len(),
+            comments: self.comments.clone().into_iter().collect(),
+            result: status.result_name().map(|result| GameResultJson {
+                result: result.to_owned(),
+                winner: status
+                    .winner()
+                    .map(|winner| winner.to_string().to_lowercase()),
+            }),
+            settings: Some(SettingsJson::from_settings(&self.settings)),
+            meta: self.meta.clone(),
+        };
+        serde_json::to_string_pretty(&game_json).unwrap_or_default()
+    }
+
+    /// Loads a game written by `to_json`. The result isn't read back since it follows
+    /// from the moves, apart from a declared impasse which has to be declared again.
+    fn from_json(&mut self, text: &str) -> Result<(), String> {
+        let game_json: GameJson = serde_json::from_str(text).map_err(|err| err.to_string())?;
+        if game_json.schema > SCHEMA_VERSION {
+            return Err(format!(
+                "Made by a newer version (schema {}, this reads up to {})",
+                game_json.schema, SCHEMA_VERSION
+            ));
+        }
+        let mut position = Position::new();
+        position
+            .set_sfen(&game_json.sfen)
+            .map_err(|err| err.to_string())?;
+        for usi in &game_json.moves {
+            let next_move = parse_move(usi).ok_or_else(|| format!("Unreadable move {}", usi))?;
+            position
+                .make_move(next_move)
+                .map_err(|err| format!("{}: {}", usi, err))?;
+        }
+        self.load_sfen(&game_json.sfen)?;
+        self.future_moves = position.move_history().iter().rev().cloned().collect();
+        self.comments = game_json.comments.into_iter().collect();
+        if let Some(settings_json) = &game_json.settings {
+            settings_json.apply_to(&mut self.settings);
+        }
+        self.meta = game_json.meta;
+        self.goto_ply(game_json.ply);
+        Ok(())
+    }
 
-                self.position.make_move(next_move).unwrap();
-                self.move_intent = MoveIntentBuilder::NoIntent;
+    fn import_json(&mut self) {
+        if let Some(text) = gloo::dialogs::prompt("Paste the game's JSON", None) {
+            if let Err(error) = self.from_json(&text) {
+                gloo::dialogs::alert(&format!("Couldn't load the game: {}", error));
             }
-            _ => panic!(),
         }
     }
 
-    fn play_move_sound(&self) {
-        if let Some(audio) = self.move_audio_ref.cast::<HtmlAudioElement>() {
-            let _ = audio.play();
+    fn load_kif(&mut self, text: &str) -> Result<(), String> {
+        let start_sfen = kif::parse_start_sfen(text)?.unwrap_or_else(|| STARTING_SFEN.to_owned());
+        let moves = kif::parse_moves(text)?;
+        let mut position = parse_sfen(&start_sfen)?;
+        for next_move in moves {
+            position
+                .make_move(next_move)
+                .map_err(|err| err.to_string())?;
         }
+        self.load_sfen(&start_sfen)?;
+        // Queue the moves up so the game can be stepped through from the start.
+        self.future_moves = position.move_history().iter().rev().cloned().collect();
+        let header = |key: &str| kif::header_value(text, key).unwrap_or_default().to_owned();
+        self.meta = GameMeta {
+            black: header("先手"),
+            white: header("後手"),
+            date: header("開始日時"),
+            event: header("棋戦"),
+        };
+        Ok(())
     }
-}
 
-impl Component for Model {
-    type Message = Msg;
-    type Properties = ();
+    /// Puts `piece` on `square`, or empties it, starting over from the edited position.
+    fn stamp_square(&mut self, square: Square, piece: Option<Piece>) {
+        let sfen =
+            editor::sfen_with_square(&self.position.to_sfen(), &self.pieces(), square, piece);
+        let _ = self.load_sfen(&sfen);
+    }
 
-    fn create(_props: Self::Properties, link: ComponentLink<Self>) -> Self {
-        BBFactory::init();
-        let link_clone = link.clone();
-        let mut model = Self {
-            link,
-            position: Position::new(),
-            move_intent: MoveIntentBuilder::NoIntent,
-            move_audio_ref: Default::default(),
-            history_bottom_ref: Default::default(),
-            _hash_change_listener: EventListener::new(&window(), "hashchange", move |_| {
-                link_clone.send_message(Msg::LoadFromUrl);
-            }),
+    /// Whether dropping a pawn on `to` is illegal only because it gives checkmate
+    /// (打ち歩詰め), rather than for breaking one of the ordinary drop rules.
+    fn is_uchifuzume(&self, to: Square) -> bool {
+        let color = self.position.side_to_move();
+        let pawn = Piece {
+            piece_type: PieceType::Pawn,
+            color,
         };
-        if let Err(_error) = model.try_load_from_url() {
-            model.reset();
+        if self.position.piece_at(to).is_some() || self.position.hand(pawn) == 0 {
+            return false;
         }
-        model
+        // The crate refuses to play the drop, so set up the position after it instead.
+        // The hand isn't reduced, but that can't change whether the opponent can reply.
+        let dropped_sfen =
+            editor::sfen_with_square(&self.position.to_sfen(), &self.pieces(), to, Some(pawn));
+        let mut dropped = Position::new();
+        if dropped
+            .set_sfen(&sfen_with_side_to_move(&dropped_sfen, color.flip()))
+            .is_err()
+        {
+            return false;
+        }
+        // A dead pawn, a second pawn on the file or leaving the king in check are the
+        // ordinary reasons a drop is refused.
+        diagnose(&dropped).is_empty()
+            && matches!(game_status(&dropped), GameStatus::Checkmate { .. })
+    }
+
+    /// Starts over from the current position with `color` to move.
+    fn set_side_to_move(&mut self, color: Color) {
+        if color == self.position.side_to_move() {
+            return;
+        }
+        let sfen = sfen_with_side_to_move(&self.position.to_sfen(), color);
+        self.load_sfen(&sfen)
+            .expect("Changing the side to move keeps the SFEN well formed");
+    }
+
+    fn undo(&mut self) {
+        self.cpu_move = None;
+        self.tsume_reply = None;
+        if self.step_back() {
+            self.clear_choice();
+            self.last_move_instant = Date::now();
+            self.play_move_sound(MoveSoundKind::Quiet);
+        }
+        self.schedule_cpu_move();
+    }
+
+    /// Takes back moves until it is the human's turn before their previous move, which
+    /// usually means the computer's reply and the human's move.
+    fn undo_my_move(&mut self) {
+        let human_color = match self.settings.cpu_color {
+            Some(cpu_color) => cpu_color.flip(),
+            None => return,
+        };
+        if self.position.move_history().is_empty() {
+            return;
+        }
+        self.undo();
+        while self.position.side_to_move() != human_color && self.step_back() {}
+        self.cpu_move = None;
+        self.schedule_cpu_move();
+    }
+
+    /// Has the computer reply after a pause, if it is its turn.
+    fn schedule_cpu_move(&mut self) {
+        if self.settings.cpu_color != Some(self.position.side_to_move())
+            || self.editing
+            || self.status().is_over()
+        {
+            return;
+        }
+        let link = self.link.clone();
+        self.cpu_move = Some(Timeout::new(self.settings.cpu_think_ms, move || {
+            link.send_message(Msg::CpuMove);
+        }));
+    }
+
+    fn play_cpu_move(&mut self) {
+        self.cpu_move = None;
+        if self.settings.cpu_color != Some(self.position.side_to_move())
+            || self.editing
+            || self.status().is_over()
+        {
+            return;
+        }
+        let mut sandbox_position = Position::new();
+        sandbox_position.set_sfen(&self.position.to_sfen()).unwrap();
+        let moves = legal_moves(&mut sandbox_position);
+        let next_move = match &mut self.cpu_rng {
+            Some(rng) => moves.choose(rng),
+            None => moves.choose(&mut rand::thread_rng()),
+        };
+        if let Some(next_move) = next_move.copied() {
+            self.clear_choice();
+            self.play_move(next_move);
+        }
+    }
+
+    /// Starts a fresh game from a random but legal position part way into a game.
+    fn load_random_position(&mut self) {
+        let mut start = Position::new();
+        start
+            .set_sfen(STARTING_SFEN)
+            .expect("Starting position should be valid");
+        let plies = self.random_position_plies;
+        let position = match &mut self.cpu_rng {
+            Some(rng) => random_position(&start, plies, rng),
+            None => random_position(&start, plies, &mut rand::thread_rng()),
+        };
+        let _ = self.load_sfen(&position.to_sfen());
+    }
+
+    /// Starts the seeded computer's choices over, so each game with a seed goes the same.
+    fn reseed_cpu(&mut self) {
+        self.cpu_rng = self.settings.cpu_seed.map(XorShift64::new);
+    }
+
+    /// Replays the move that was last undone exactly as it was played, including whether
+    /// it promoted, rather than going through the promotion prompt again.
+    fn redo(&mut self) {
+        self.cpu_move = None;
+        if let Some(next_move) = self.future_moves.last() {
+            let kind = MoveSoundKind::for_captured(match next_move {
+                MoveRecord::Normal { captured, .. } => *captured,
+                MoveRecord::Drop { .. } => None,
+            });
+            if self.step_forward() {
+                self.clear_choice();
+                self.play_move_sound(kind);
+            }
+        }
+        self.schedule_cpu_move();
+    }
+
+    /// Takes back the last move, along with the time it took and any impasse declared
+    /// after it, which no longer stand once the game can go another way.
+    fn step_back(&mut self) -> bool {
+        let ply = self.position.move_history().len();
+        let stepped = undo_into(&mut self.position, &mut self.future_moves);
+        if stepped {
+            self.move_times.remove(&ply);
+            self.declared_impasse = None;
+        }
+        stepped
+    }
+
+    fn step_forward(&mut self) -> bool {
+        redo_from(&mut self.position, &mut self.future_moves)
+    }
+
+    fn goto_ply(&mut self, ply: usize) {
+        self.cpu_move = None;
+        self.tsume_reply = None;
+        // Jumping through the history only changes what is viewed, so the times stay.
+        let move_times = std::mem::take(&mut self.move_times);
+        while self.position.move_history().len() > ply && self.step_back() {}
+        while self.position.move_history().len() < ply && self.step_forward() {}
+        self.move_times = move_times;
+        self.last_move_instant = Date::now();
+        self.clear_choice();
+        self.play_move_sound(MoveSoundKind::Quiet);
+        self.schedule_cpu_move();
+    }
+
+    /// The side that made the first move in the history. Positions loaded from SFEN can
+    /// start part way through a game, with either side to move.
+    fn first_mover(&self) -> Color {
+        if self.position.move_history().len() % 2 == 0 {
+            self.position.side_to_move()
+        } else {
+            self.position.side_to_move().flip()
+        }
+    }
+
+    /// What the history calls the position the game started from.
+    fn starting_position_label(&self) -> String {
+        let lang = self.settings.lang;
+        if self.start_sfen == STARTING_SFEN {
+            t("Even game", lang).to_owned()
+        } else if let Some(handicap) = kif::handicap_name(&self.start_sfen) {
+            format!("手合割：{}", handicap)
+        } else {
+            t("Custom start", lang).to_owned()
+        }
+    }
+
+    /// The SFEN move count of the first move in the history.
+    fn first_move_number(&self) -> usize {
+        (self.position.ply() as usize).saturating_sub(self.position.move_history().len())
+    }
+
+    /// How many moves share each row of the vertical history.
+    fn history_columns(&self) -> usize {
+        self.settings
+            .history_columns
+            .unwrap_or((self.history_width / HISTORY_COLUMN_MIN_WIDTH_PX) as usize)
+            .max(1)
+    }
+
+    fn move_numbering(&self) -> MoveNumbering {
+        self.settings
+            .move_numbering
+            .unwrap_or_else(|| MoveNumbering::for_notation(self.settings.notation))
+    }
+
+    fn history_row_count(&self) -> usize {
+        let move_count = self.game_records().len();
+        match HistoryLayout::for_numbering(self.move_numbering()) {
+            HistoryLayout::Vertical => {
+                let columns = self.history_columns();
+                (move_count + columns - 1) / columns
+            }
+            HistoryLayout::TwoColumn => {
+                let first_slot = if self.first_mover() == Color::White {
+                    1
+                } else {
+                    0
+                };
+                (move_count + first_slot + 1) / 2
+            }
+        }
+    }
+
+    /// Replays the game for the history and the material sparkline, but only when its
+    /// start or moves have changed since the last replay.
+    fn refresh_game_analysis(&mut self) {
+        let analysed_game = format!("{} {}", self.start_sfen, self.game_usi());
+        if analysed_game == self.analysed_game {
+            return;
+        }
+        let game_records = self.game_records();
+        self.move_disambiguations = self.replay_disambiguations(&game_records);
+        self.material_balances = self.replay_material_balances(&game_records);
+        self.analysed_game = analysed_game;
+    }
+
+    /// Replays the game to find, for each move, which of several identical pieces made it.
+    fn replay_disambiguations(&self, game_records: &[MoveRecord]) -> Vec<String> {
+        let mut sandbox_position = Position::new();
+        if sandbox_position.set_sfen(&self.start_sfen).is_err() {
+            return vec![String::new(); game_records.len()];
+        }
+        game_records
+            .iter()
+            .map(|move_record| {
+                let disambiguation = relative_movement(&mut sandbox_position, move_record);
+                let _ = sandbox_position.make_move(move_from_record(move_record));
+                disambiguation
+            })
+            .collect()
+    }
+
+    /// The material balance after each ply of the game, starting with the initial
+    /// position, found by replaying the game.
+    fn replay_material_balances(&self, game_records: &[MoveRecord]) -> Vec<i32> {
+        let mut sandbox_position = Position::new();
+        if sandbox_position.set_sfen(&self.start_sfen).is_err() {
+            return Vec::new();
+        }
+        let mut balances = vec![material_balance(&sandbox_position)];
+        for move_record in game_records {
+            if sandbox_position
+                .make_move(move_from_record(move_record))
+                .is_err()
+            {
+                break;
+            }
+            balances.push(material_balance(&sandbox_position));
+        }
+        balances
+    }
+
+    /// The captures played to reach the viewed position, in order, each written as the side
+    /// that captured and the piece it gained in hand.
+    fn view_capture_log(&self) -> Html {
+        let lang = self.settings.lang;
+        let captures: Vec<(usize, Piece)> = self
+            .position
+            .move_history()
+            .iter()
+            .enumerate()
+            .filter_map(|(turn, move_record)| match move_record {
+                MoveRecord::Normal {
+                    captured: Some(captured),
+                    ..
+                } => Some((turn + 1, *captured)),
+                _ => None,
+            })
+            .collect();
+        html! {
+            <ol class="capture-log" aria-label=t("Captures", lang)>
+                {
+                    for captures.into_iter().map(|(ply, captured)| {
+                        let gained = captured.piece_type.unpromote().unwrap_or(captured.piece_type);
+                        html! {
+                            <li>
+                                <button onclick=self.link.callback(move |_| Msg::GotoPly(ply))>
+                                    { format!("{}×{}", side_mark(captured.color.flip()), piece_kanji(gained)) }
+                                </button>
+                            </li>
+                        }
+                    })
+                }
+            </ol>
+        }
+    }
+
+    fn view_meta_input(&self, field: MetaField, label: &'static str, placeholder: &str) -> Html {
+        let lang = self.settings.lang;
+        html! {
+            <label>
+                { t(label, lang) }
+                <input
+                    type="text"
+                    placeholder=placeholder.to_owned()
+                    value=self.meta.field(field).to_owned()
+                    oninput=self.link.callback(move |input: InputData| Msg::SetMetaField(field, input.value))
+                />
+            </label>
+        }
+    }
+
+    /// A sparkline of the material balance over the game, with a point per ply that
+    /// jumps to it.
+    fn view_material_sparkline(&self, balances: &[i32]) -> Html {
+        if balances.len() < 2 {
+            return html! {};
+        }
+        let lang = self.settings.lang;
+        let width = self.history_width;
+        let height = SPARKLINE_HEIGHT_PX;
+        let largest = balances
+            .iter()
+            .map(|balance| balance.abs())
+            .max()
+            .unwrap_or(0)
+            .max(1) as f64;
+        let point = |ply: usize, balance: i32| {
+            let x = ply as f64 / (balances.len() - 1) as f64 * width;
+            // Black's advantage goes up.
+            let y = height / 2.0 - balance as f64 / largest * (height / 2.0 - 2.0);
+            (x, y)
+        };
+        let points = balances
+            .iter()
+            .enumerate()
+            .map(|(ply, balance)| {
+                let (x, y) = point(ply, *balance);
+                format!("{:.1},{:.1}", x, y)
+            })
+            .collect::<Vec<_>>()
+            .join(" ");
+        let viewed_ply = self.position.move_history().len();
+        html! {
+            <svg
+                class="material-sparkline"
+                width=width.to_string()
+                height=height.to_string()
+                role="img"
+                aria-label=t("Material balance over the game", lang)
+            >
+                <line
+                    class="zero"
+                    x1="0"
+                    y1=(height / 2.0).to_string()
+                    x2=width.to_string()
+                    y2=(height / 2.0).to_string()
+                />
+                <polyline points=points />
+                {
+                    for balances.iter().enumerate().map(|(ply, balance)| {
+                        let (x, y) = point(ply, *balance);
+                        html! {
+                            <circle
+                                class=classes!((ply == viewed_ply).then(|| "current"))
+                                cx=x.to_string()
+                                cy=y.to_string()
+                                r="3"
+                                onclick=self.link.callback(move |_| Msg::GotoPly(ply))
+                            >
+                                <title>{ format!("Ply {}: {:+}", ply, balance) }</title>
+                            </circle>
+                        }
+                    })
+                }
+            </svg>
+        }
+    }
+
+    fn view_history_move(
+        &self,
+        game_records: &[MoveRecord],
+        turn: usize,
+        first_mover: Color,
+    ) -> Html {
+        let move_record = &game_records[turn];
+        let viewed_ply = self.position.move_history().len();
+        let previous_move_destination = previous_move_destination(game_records, turn);
+        let color = if turn % 2 == 0 {
+            first_mover
+        } else {
+            first_mover.flip()
+        };
+        let text = match self.settings.notation {
+            Notation::Japanese => format!(
+                "{}{}",
+                side_mark(color),
+                format_move(
+                    move_record,
+                    previous_move_destination,
+                    self.move_disambiguations
+                        .get(turn)
+                        .map_or("", String::as_str),
+                    self.settings.drop_style
+                )
+            ),
+            Notation::Western => format_move_western(move_record),
+        };
+        let mut history_item_classes = classes!("history-item");
+        if turn + 1 == viewed_ply {
+            history_item_classes.push("current");
+        } else if turn >= viewed_ply {
+            history_item_classes.push("future");
+        }
+        let comment = self.comments.get(&(turn + 1));
+        let move_number = self.move_numbering().number(self.first_move_number(), turn);
+        let lang = self.settings.lang;
+        let aria_label = format!(
+            "{}, {}{}",
+            t_fill("Move {}", lang, move_number),
+            spoken_move(move_record),
+            if comment.is_some() {
+                t(", annotated", lang)
+            } else {
+                ""
+            }
+        );
+        html! {
+            <button
+                class=history_item_classes
+                aria-label=aria_label
+                aria-current=if turn + 1 == viewed_ply { "step" } else { "false" }
+                onclick=self.link.callback(move |_| Msg::GotoPly(turn + 1))
+            >
+                { text }
+                {
+                    match self.move_times.get(&(turn + 1)) {
+                        Some(seconds) => html! {
+                            <span class="move-time">{ format!(" ({})", format_seconds(*seconds)) }</span>
+                        },
+                        None => html! {},
+                    }
+                }
+                {
+                    if let Some(comment) = comment {
+                        html! { <span class="annotated" title=comment.clone()>{ "＊" }</span> }
+                    } else {
+                        html! {}
+                    }
+                }
+            </button>
+        }
+    }
+
+    /// The range of history rows worth rendering at the current scroll position.
+    fn history_window(&self, row_count: usize) -> std::ops::Range<usize> {
+        if row_count <= HISTORY_VIRTUALIZATION_THRESHOLD {
+            return 0..row_count;
+        }
+        let first_visible = (self.history_scroll_top / HISTORY_ROW_HEIGHT_PX).max(0) as usize;
+        let visible_count = (self.history_height / HISTORY_ROW_HEIGHT_PX).max(0) as usize + 1;
+        let start = first_visible
+            .saturating_sub(HISTORY_OVERSCAN_ROWS)
+            .min(row_count);
+        let end = (first_visible + visible_count + HISTORY_OVERSCAN_ROWS).min(row_count);
+        start..end
+    }
+
+    /// Every move of the game, including the ones after the currently viewed ply.
+    fn game_records(&self) -> Vec<MoveRecord> {
+        self.position
+            .move_history()
+            .iter()
+            .chain(self.future_moves.iter().rev())
+            .cloned()
+            .collect()
+    }
+
+    /// Swaps Black and White throughout the game, keeping its moves, comments and the
+    /// position being viewed.
+    fn swap_colors(&mut self) {
+        let viewed_ply = self.position.move_history().len();
+        let game_records = self.game_records();
+        let comments = std::mem::take(&mut self.comments);
+        let move_times = std::mem::take(&mut self.move_times);
+        while self.step_back() {}
+        let swapped_sfen = editor::swapped_colors_sfen(&self.position.to_sfen(), &self.pieces());
+        let mut swapped_position = Position::new();
+        if swapped_position.set_sfen(&swapped_sfen).is_err() {
+            self.comments = comments;
+            self.move_times = move_times;
+            self.goto_ply(viewed_ply);
+            return;
+        }
+        for move_record in &game_records {
+            let swapped_move = editor::rotated_move(move_from_record(move_record));
+            if swapped_position.make_move(swapped_move).is_err() {
+                break;
+            }
+        }
+        let _ = self.load_sfen(&swapped_sfen);
+        self.future_moves = swapped_position
+            .move_history()
+            .iter()
+            .rev()
+            .cloned()
+            .collect();
+        self.comments = comments;
+        self.move_times = move_times;
+        self.goto_ply(viewed_ply);
+    }
+
+    /// Saves the starting position and current settings under a name the user gives.
+    fn save_preset(&mut self) {
+        let name = match gloo::dialogs::prompt("Name this preset", None) {
+            Some(name) if !name.trim().is_empty() => name.trim().to_owned(),
+            _ => return,
+        };
+        let preset = Preset::from_settings(name, self.start_sfen.clone(), &self.settings);
+        // Saving under an existing name replaces that preset, so check it's meant to.
+        match self
+            .presets
+            .iter_mut()
+            .find(|saved| saved.name == preset.name)
+        {
+            Some(saved) => {
+                if !gloo::dialogs::confirm(&format!(
+                    "A preset named \"{}\" already exists. Replace it?",
+                    preset.name
+                )) {
+                    return;
+                }
+                *saved = preset;
+            }
+            None => self.presets.push(preset),
+        }
+        save_presets(&self.presets);
+    }
+
+    /// Starts a new game from the preset's position with all of its settings applied.
+    fn start_preset(&mut self, preset: &Preset) {
+        if self.load_sfen(&preset.starting_sfen).is_err() {
+            return;
+        }
+        self.replay = None;
+        self.opponent_view = false;
+        self.editing = false;
+        preset.apply_to(&mut self.settings);
+        store_cpu_color(self.settings.cpu_color);
+        self.schedule_cpu_move();
+    }
+
+    /// Shows the game as the opponent saw it by swapping the colors and flipping the
+    /// board together. Each half undoes itself, so pressing it again restores the view;
+    /// flipping the board by hand in the meantime leaves the colors swapped.
+    fn toggle_opponent_view(&mut self) {
+        self.swap_colors();
+        self.settings.flipped = !self.settings.flipped;
+        self.opponent_view = !self.opponent_view;
+    }
+
+    fn start_replay(&mut self) {
+        let link = self.link.clone();
+        self.replay = Some(Interval::new(self.replay_speed_ms, move || {
+            link.send_message(Msg::ReplayStep);
+        }));
+    }
+
+    fn toggle_replay(&mut self) {
+        if self.replay.take().is_none() {
+            if self.future_moves.is_empty() {
+                self.goto_ply(0);
+            }
+            self.start_replay();
+        }
+    }
+
+    fn replay_step(&mut self) {
+        let captured = match self.future_moves.last() {
+            Some(MoveRecord::Normal { captured, .. }) => *captured,
+            _ => None,
+        };
+        if self.step_forward() {
+            self.play_move_sound(MoveSoundKind::for_captured(captured));
+        }
+        if self.future_moves.is_empty() {
+            self.replay = None;
+        }
+    }
+
+    fn pieces(&self) -> Pieces {
+        pieces_of(&self.position)
+    }
+
+    /// Flashes where the piece now on `from` could go if its side were to move again.
+    fn flash_mobility(&mut self, from: Square) {
+        let color = match self.position.piece_at(from) {
+            Some(piece) => piece.color,
+            None => return,
+        };
+        let mut sandbox_position = Position::new();
+        if sandbox_position
+            .set_sfen(&sfen_with_side_to_move(&self.position.to_sfen(), color))
+            .is_err()
+        {
+            return;
+        }
+        let destinations: HashSet<Square> = Square::iter()
+            .filter(|&to| can_move_between(&mut sandbox_position, from, to))
+            .collect();
+        if destinations.is_empty() {
+            self.mobility_preview = None;
+            return;
+        }
+        let link = self.link.clone();
+        let timeout = Timeout::new(MOBILITY_PREVIEW_MS, move || {
+            link.send_message(Msg::StopMobilityPreview);
+        });
+        self.mobility_preview = Some((destinations, timeout));
+    }
+
+    /// Briefly highlights the squares that differ from `before`, to show what loading a
+    /// position changed.
+    fn flash_changed_squares(&mut self, before: Pieces) {
+        let after = self.pieces();
+        let changed: HashSet<Square> = Square::iter()
+            .filter(|square| before[square.index()] != after[square.index()])
+            .collect();
+        if changed.is_empty() {
+            return;
+        }
+        let link = self.link.clone();
+        let timeout = Timeout::new(CHANGED_SQUARES_FLASH_MS, move || {
+            link.send_message(Msg::StopChangedSquares);
+        });
+        self.changed_squares = Some((changed, timeout));
+    }
+
+    /// Loads the reference board from an SFEN, or from the position in a shared link.
+    fn set_reference_sfen(&mut self, text: &str) {
+        let text = text.trim();
+        if text.is_empty() {
+            self.reference_position = None;
+            return;
+        }
+        let sfen = match text.split_once('#') {
+            Some((_, hash)) => match decode_hash_sfen(hash.split('&').next().unwrap_or_default()) {
+                Ok(sfen) => sfen,
+                Err(_) => return,
+            },
+            None => text.to_owned(),
+        };
+        let mut position = Position::new();
+        if position.set_sfen(&sfen).is_ok() {
+            self.reference_position = Some(position);
+        }
+    }
+
+    fn set_comment(&mut self, comment: String) {
+        let ply = self.position.move_history().len();
+        if comment.trim().is_empty() {
+            self.comments.remove(&ply);
+        } else {
+            self.comments.insert(ply, comment);
+        }
+    }
+
+    fn to_kif(&self) -> String {
+        let mut kif = String::new();
+        for (key, value) in [("開始日時", &self.meta.date), ("棋戦", &self.meta.event)] {
+            if !value.is_empty() {
+                kif.push_str(&format!("{}：{}\n", key, value));
+            }
+        }
+        kif.push_str(&kif_starting_position(&self.start_sfen));
+        for (key, value) in [("先手", &self.meta.black), ("後手", &self.meta.white)] {
+            if !value.is_empty() {
+                kif.push_str(&format!("{}：{}\n", key, value));
+            }
+        }
+        kif.push_str("手数----指手---------消費時間--\n");
+        let write_comment = |kif: &mut String, ply: usize| {
+            if let Some(comment) = self.comments.get(&ply) {
+                for line in comment.lines() {
+                    kif.push_str(&format!("*{}\n", line));
+                }
+            }
+        };
+        write_comment(&mut kif, 0);
+        let game_records = self.game_records();
+        let first_move_number = self.first_move_number();
+        // Each side's running total, as the 消費時間 column lists it after the move's own time.
+        let mut total_seconds = [0; 2];
+        for (turn, move_record) in game_records.iter().enumerate() {
+            let previous_move_destination = previous_move_destination(&game_records, turn);
+            let time = match self.move_times.get(&(turn + 1)) {
+                Some(seconds) => {
+                    total_seconds[turn % 2] += seconds;
+                    let total = total_seconds[turn % 2];
+                    format!(
+                        "   ({:>2}:{:02}/{:02}:{:02}:{:02})",
+                        seconds / 60,
+                        seconds % 60,
+                        total / 3600,
+                        total / 60 % 60,
+                        total % 60
+                    )
+                }
+                None => String::new(),
+            };
+            kif.push_str(&format!(
+                "{:>4} {}{}\n",
+                MoveNumbering::PerPly.number(first_move_number, turn),
+                // KIF readers expect the fixed-width columns regardless of the display setting.
+                format_move(
+                    move_record,
+                    previous_move_destination,
+                    "",
+                    DropStyle::Spaced
+                ),
+                time
+            ));
+            write_comment(&mut kif, turn + 1);
+        }
+        kif
+    }
+
+    fn to_bod(&self) -> String {
+        position_to_bod(&self.position)
+    }
+
+    /// What an export depends on: the whole game and the position being viewed.
+    fn export_source(&self) -> String {
+        format!("{} {}", self.analysed_game, self.position.to_sfen())
+    }
+
+    fn build_export(&self, format: ExportFormat) -> Export {
+        let text = match format {
+            ExportFormat::Kif => self.to_kif(),
+            ExportFormat::Bod => self.to_bod(),
+            ExportFormat::Svg => self.to_svg(),
+            ExportFormat::Usi => self.game_usi(),
+            ExportFormat::Json => self.to_json(),
+        };
+        Export {
+            format,
+            text,
+            source: self.export_source(),
+        }
+    }
+
+    /// The board as an SVG diagram, with arrows for the moves the settings ask for.
+    fn to_svg(&self) -> String {
+        let (arrows, drops) = match self.settings.diagram_arrows {
+            DiagramArrows::None => (Vec::new(), Vec::new()),
+            DiagramArrows::SelectedPiece => {
+                let mut destinations: Vec<Square> = self
+                    .move_intent
+                    .move_destination_candidates(&self.position)
+                    .into_iter()
+                    .collect();
+                destinations.sort_by_key(|square| square.index());
+                match self.move_intent.move_origin_square() {
+                    Some(from) => (
+                        destinations.into_iter().map(|to| (from, to)).collect(),
+                        Vec::new(),
+                    ),
+                    None => (Vec::new(), destinations),
+                }
+            }
+            DiagramArrows::AllPieces => {
+                let mut sandbox_position = Position::new();
+                sandbox_position.set_sfen(&self.position.to_sfen()).unwrap();
+                let mut arrows: Vec<(Square, Square)> = legal_moves(&mut sandbox_position)
+                    .into_iter()
+                    .filter_map(|next_move| match next_move {
+                        Move::Normal { from, to, .. } => Some((from, to)),
+                        Move::Drop { .. } => None,
+                    })
+                    .collect();
+                // Moves that may or may not promote share one arrow.
+                arrows.dedup();
+                (arrows, Vec::new())
+            }
+        };
+        position_to_svg(&self.position, self.settings.flipped, &arrows, &drops)
+    }
+
+    /// The whole game as a space-separated USI move list, for pasting into engines.
+    fn game_usi(&self) -> String {
+        self.game_records()
+            .iter()
+            .map(|move_record| move_to_usi(move_from_record(move_record)))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    fn toggle_highlighted_line(&mut self, line: BoardLine) {
+        self.highlighted_line = if self.highlighted_line == Some(line) {
+            None
+        } else {
+            Some(line)
+        };
+    }
+
+    fn highlighted_squares(&self) -> HashSet<Square> {
+        match self.highlighted_line {
+            Some(line) => Square::iter()
+                .filter(|square| line.contains(*square))
+                .collect(),
+            None => HashSet::new(),
+        }
+    }
+
+    /// The destinations of the moves leading up to the last one, mapped to how brightly
+    /// to highlight them: the most recent is brightest.
+    fn trail_intensities(&self) -> HashMap<Square, f64> {
+        let trail_length = self.settings.trail_length;
+        let mut intensities = HashMap::new();
+        for (age, move_record) in self
+            .position
+            .move_history()
+            .iter()
+            .rev()
+            .skip(1)
+            .take(trail_length)
+            .enumerate()
+        {
+            let destination = match move_record {
+                MoveRecord::Normal { to, .. } => *to,
+                MoveRecord::Drop { to, .. } => *to,
+            };
+            intensities
+                .entry(destination)
+                .or_insert((trail_length - age) as f64 / (trail_length + 1) as f64);
+        }
+        intensities
+    }
+
+    /// Every legal move for the side to move, in USI.
+    fn legal_moves_usi(&self) -> Vec<String> {
+        let mut sandbox_position = Position::new();
+        sandbox_position.set_sfen(&self.position.to_sfen()).unwrap();
+        legal_moves(&mut sandbox_position)
+            .into_iter()
+            .map(move_to_usi)
+            .collect()
+    }
+
+    fn start_history_resize(&mut self, start_x: i32) {
+        let move_link = self.link.clone();
+        let up_link = self.link.clone();
+        self.history_resize = Some(HistoryResize {
+            start_x,
+            start_width: self.history_width,
+            _mouse_move_listener: EventListener::new(&window(), "mousemove", move |event| {
+                if let Some(event) = event.dyn_ref::<MouseEvent>() {
+                    move_link.send_message(Msg::ResizeHistory(event.client_x()));
+                }
+            }),
+            _mouse_up_listener: EventListener::new(&window(), "mouseup", move |_| {
+                up_link.send_message(Msg::StopHistoryResize);
+            }),
+        });
+    }
+
+    /// The divider sits to the left of the history, so dragging it left widens the panel.
+    fn resize_history(&mut self, x: i32) {
+        if let Some(resize) = &self.history_resize {
+            self.history_width = (resize.start_width - (x - resize.start_x) as f64)
+                .clamp(MIN_HISTORY_WIDTH_PX, MAX_HISTORY_WIDTH_PX);
+        }
+    }
+
+    fn stop_history_resize(&mut self) {
+        if self.history_resize.take().is_some() {
+            let _ = LocalStorage::set(HISTORY_WIDTH_STORAGE_KEY, self.history_width);
+        }
+    }
+
+    fn view_piece_style_select(&self, color: Color) -> Html {
+        let lang = self.settings.lang;
+        let (label, current) = match color {
+            Color::Black => (t("Black pieces ", lang), self.settings.piece_styles.black),
+            Color::White => (t("White pieces ", lang), self.settings.piece_styles.white),
+        };
+        html! {
+            <label>
+                {label}
+                <select
+                    onchange=self.link.batch_callback(move |change: ChangeData| match change {
+                        ChangeData::Select(select) => PieceStyle::from_name(&select.value())
+                            .map(|style| Msg::SetPieceStyle(color, style)),
+                        _ => None,
+                    })
+                >
+                    <option value="kanji" selected=current == PieceStyle::Kanji>{ t("Kanji", lang) }</option>
+                    <option value="letters" selected=current == PieceStyle::Letters>{ t("Letters", lang) }</option>
+                </select>
+            </label>
+        }
+    }
+
+    /// Where the viewed position stands, including an impasse declared there.
+    fn status(&self) -> GameStatus {
+        match self.declared_impasse {
+            Some((ply, status)) if ply == self.position.move_history().len() => status,
+            _ => self
+                .settings
+                .try_rule
+                .then(|| try_rule_status(&self.position))
+                .flatten()
+                .unwrap_or_else(|| game_status(&self.position)),
+        }
+    }
+
+    fn declare_impasse(&mut self) {
+        if self.status().is_over() {
+            return;
+        }
+        let status = declare_impasse(&self.position, self.settings.impasse_rule);
+        self.declared_impasse = Some((self.position.move_history().len(), status));
+        self.clear_choice();
+        self.report_game_over(status);
+    }
+
+    /// Tells the host page how the game ended, if it has.
+    fn report_game_over(&self, status: GameStatus) {
+        if let Some((result, winner)) = game_over_detail(status) {
+            host_events::dispatch(
+                "shogi-gameover",
+                &[
+                    ("result", result),
+                    ("winner", winner),
+                    ("sfen", &self.position.to_sfen()),
+                ],
+            );
+        }
+    }
+
+    fn impasse_points(&self, color: Color) -> Option<u32> {
+        shogi_board::rules::impasse_points(&self.position, color)
+    }
+
+    fn mate_in(&self, max_depth: u8) -> Option<u8> {
+        tsume::mate_in(&self.position, max_depth)
+    }
+
+    /// Links to the current position on lishogi's analysis board.
+    fn lishogi_url(&self) -> String {
+        format!(
+            "https://lishogi.org/analysis/{}",
+            percent_encode(&self.position.to_sfen())
+        )
+    }
+
+    fn hand_pieces(&self, color: Color) -> Vec<HandPiece> {
+        PieceType::iter()
+            .filter(|piece_type| piece_type.is_hand_piece())
+            .map(|piece_type| HandPiece {
+                piece_type,
+                count: self.position.hand(Piece { piece_type, color }),
+            })
+            .collect()
+    }
+
+    /// How many of each piece both sides hold, in a fixed order so that two snapshots
+    /// can be compared.
+    fn hand_counts(&self) -> Vec<(Piece, u8)> {
+        [Color::Black, Color::White]
+            .iter()
+            .flat_map(|&color| {
+                self.hand_pieces(color).into_iter().map(move |hand_piece| {
+                    let piece = Piece {
+                        piece_type: hand_piece.piece_type,
+                        color,
+                    };
+                    (piece, hand_piece.count)
+                })
+            })
+            .collect()
+    }
+
+    /// How many squares each held piece type could be dropped on. Only the side to move
+    /// can drop, so the other side has none.
+    fn drop_counts(&self, color: Color) -> HashMap<PieceType, usize> {
+        if color != self.position.side_to_move() {
+            return HashMap::new();
+        }
+        let mut sandbox_position = Position::new();
+        sandbox_position.set_sfen(&self.position.to_sfen()).unwrap();
+        self.hand_pieces(color)
+            .iter()
+            .filter(|hand_piece| hand_piece.count > 0)
+            .map(|hand_piece| {
+                let piece_type = hand_piece.piece_type;
+                (
+                    piece_type,
+                    legal_drop_count(&mut sandbox_position, piece_type),
+                )
+            })
+            .collect()
+    }
+
+    fn view_hand(&self, color: Color) -> Html {
+        let pieces = self.hand_pieces(color);
+        let is_side_to_move = self.position.side_to_move() == color;
+        let selection = if is_side_to_move {
+            self.move_intent.move_origin_hand_piece_type()
+        } else {
+            None
+        };
+        let can_select = is_side_to_move && matches!(self.move_intent, MoveIntentBuilder::NoIntent);
+        html! {
+            <Hand
+                color=color
+                pieces=pieces
+                selection=selection
+                can_select=can_select
+                flipped=self.settings.flipped
+                piece_styles=self.settings.piece_styles
+                just_gained=self.just_gained
+                    .as_ref()
+                    .filter(|(piece, _)| piece.color == color)
+                    .map(|(piece, _)| piece.piece_type)
+                drop_counts=if self.settings.show_drop_counts { self.drop_counts(color) } else { HashMap::new() }
+                on_piece_click=self.link.callback(move |piece_type| Msg::ClickHeldPiece(piece_type, color))
+                lang=self.settings.lang
+            />
+        }
+    }
+
+    /// A larger view of the chosen piece with its destinations written out, for boards
+    /// too small to read comfortably.
+    fn view_intent_preview(&self, move_destination_candidates: &HashSet<Square>) -> Html {
+        let lang = self.settings.lang;
+        let from = match self.move_intent {
+            MoveIntentBuilder::WithOrigin { from } => from,
+            _ => return html! {},
+        };
+        let origin = match from {
+            Origin::SquarePiece(square) => square_to_usi(square),
+            Origin::HeldPiece(..) => t("hand", lang).to_owned(),
+        };
+        let mut destinations: Vec<Square> = move_destination_candidates.iter().copied().collect();
+        destinations.sort_by_key(|square| square.index());
+        let destinations = if destinations.is_empty() {
+            t("nowhere", lang).to_owned()
+        } else {
+            destinations
+                .into_iter()
+                .map(square_to_usi)
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        html! {
+            <div class="intent-preview">
+                <div class="intent-preview-piece">
+                    <PieceView
+                        piece=from.piece(&self.position)
+                        flipped=self.settings.flipped
+                        piece_styles=self.settings.piece_styles
+                    />
+                </div>
+                <div>
+                    <div>{ t_fill("From {}", lang, origin) }</div>
+                    <div>{ t_fill("To {}", lang, destinations) }</div>
+                </div>
+            </div>
+        }
+    }
+
+    /// Counts, for each square, how many pieces of `color` could move there if it
+    /// were their turn.
+    fn attack_counts(&self, color: Color) -> HashMap<Square, u8> {
+        let mut sandbox_position = Position::new();
+        sandbox_position
+            .set_sfen(&sfen_with_side_to_move(&self.position.to_sfen(), color))
+            .unwrap();
+        let mut counts = HashMap::new();
+        for from in Square::iter() {
+            if self
+                .position
+                .piece_at(from)
+                .filter(|piece| piece.color == color)
+                .is_none()
+            {
+                continue;
+            }
+            for to in Square::iter() {
+                if can_move_between(&mut sandbox_position, from, to) {
+                    *counts.entry(to).or_insert(0) += 1;
+                }
+            }
+        }
+        counts
+    }
+
+    fn clear_choice(&mut self) {
+        self.move_intent.cancel();
+        self.hovered_square = None;
+    }
+
+    fn choose_origin(&mut self, from: Origin) {
+        self.move_intent = match self.move_intent {
+            MoveIntentBuilder::NoIntent => MoveIntentBuilder::WithOrigin { from },
+            MoveIntentBuilder::WithOrigin { from: _ } => MoveIntentBuilder::WithOrigin { from },
+            _ => panic!(),
+        };
+        self.should_focus_destination_input = true;
+    }
+
+    fn type_destination(&mut self, text: &str) {
+        let destination = parse_square(text).filter(|square| {
+            matches!(self.move_intent, MoveIntentBuilder::WithOrigin { .. })
+                && self.move_intent.can_move_to(*square, &self.position)
+        });
+        if let Some(square) = destination {
+            self.choose_destination(square);
+        } else {
+            let link = self.link.clone();
+            self.destination_input_shake = Some(Timeout::new(400, move || {
+                link.send_message(Msg::StopDestinationInputShake);
+            }));
+        }
+    }
+
+    fn choose_destination(&mut self, to: Square) {
+        if self.move_intent.is_stale(&self.position) {
+            self.clear_choice();
+            return;
+        }
+        self.move_intent = match self.move_intent {
+            MoveIntentBuilder::WithOrigin { from } => {
+                MoveIntentBuilder::WithDestination { from, to }
+            }
+            MoveIntentBuilder::WithDestination { from, to: _ } => {
+                MoveIntentBuilder::WithDestination { from, to }
+            }
+            _ => panic!(),
+        };
+
+        // Skip asking whether to promote if there's only one legal option.
+        if self.move_intent.cant_promote(&self.position) {
+            self.choose_promote(false);
+        } else if self.move_intent.must_promote(&self.position) {
+            self.choose_promote(true);
+        } else {
+            match self.settings.auto_promote {
+                AutoPromotePolicy::Ask => {}
+                AutoPromotePolicy::AlwaysWhenOptional => self.choose_promote(true),
+                AutoPromotePolicy::NeverWhenOptional => self.choose_promote(false),
+            }
+        }
+    }
+
+    fn choose_promote(&mut self, promote: bool) {
+        match self.move_intent {
+            MoveIntentBuilder::WithDestination { from, to } => {
+                let next_move = match from {
+                    Origin::SquarePiece(from_square) => Move::Normal {
+                        from: from_square,
+                        to,
+                        promote,
+                    },
+                    Origin::HeldPiece(piece_type) => Move::Drop { piece_type, to },
+                };
+                if !self.play_user_move(next_move) {
+                    self.clear_choice();
+                }
+            }
+            _ => panic!(),
+        }
+    }
+
+    /// Plays a legal move chosen by the user once the lesson and the overwrite check
+    /// allow it, then lets the lesson or the computer reply. Returns whether it was played.
+    fn play_user_move(&mut self, next_move: Move) -> bool {
+        if let Some(expected) = self.expected_lesson_move() {
+            self.lesson_mistake = next_move != expected;
+            if self.lesson_mistake {
+                return false;
+            }
+        }
+        if !self.confirm_overwrite(next_move) {
+            return false;
+        }
+        self.play_move(next_move);
+        self.tsume_result = None;
+        if self.settings.tsume_defence {
+            self.check_tsume_attempt();
+        }
+        if self.lesson_step().map_or(false, |step| step % 2 == 1) && !self.is_lesson_complete() {
+            let link = self.link.clone();
+            self.lesson_reply = Some(Timeout::new(LESSON_REPLY_DELAY_MS, move || {
+                link.send_message(Msg::LessonReply);
+            }));
+        }
+        self.schedule_cpu_move();
+        true
+    }
+
+    /// Judges the attacker's move just played in tsume practice, queueing the defender's
+    /// reply if the mate is still on.
+    fn check_tsume_attempt(&mut self) {
+        let status = self.status();
+        if matches!(status, GameStatus::Checkmate { .. }) {
+            self.tsume_result = Some(TsumeResult::Mated);
+        } else if !status.is_over() {
+            if self.position.in_check(self.position.side_to_move()) {
+                let link = self.link.clone();
+                self.tsume_reply = Some(Timeout::new(LESSON_REPLY_DELAY_MS, move || {
+                    link.send_message(Msg::TsumeReply);
+                }));
+            } else {
+                self.tsume_result = Some(TsumeResult::NotCheck);
+            }
+        }
+    }
+
+    fn play_tsume_reply(&mut self) {
+        self.tsume_reply = None;
+        if let Some((reply, mate)) = tsume::best_defence(&self.position, MATE_SEARCH_MAX_PLIES) {
+            self.play_move(reply);
+            if mate.is_none() {
+                self.tsume_result = Some(TsumeResult::NoMateFound);
+            }
+        }
+    }
+
+    /// Plays a complete move typed in USI, such as "7g7f", "8h2b+" or "P*5e". Moves that
+    /// can't be played leave the text in place to be corrected.
+    fn submit_usi(&mut self, text: &str) {
+        let can_move = !self.editing
+            && !self.status().is_over()
+            && self.cpu_move.is_none()
+            && self.lesson_reply.is_none()
+            && self.tsume_reply.is_none();
+        let next_move = parse_move(text.trim()).filter(|next_move| {
+            let mut sandbox_position = Position::new();
+            sandbox_position.set_sfen(&self.position.to_sfen()).is_ok()
+                && is_legal(&mut sandbox_position, *next_move)
+        });
+        match next_move {
+            Some(next_move) if can_move => {
+                if self.play_user_move(next_move) {
+                    self.clear_choice();
+                    if let Some(input) = self.usi_input_ref.cast::<HtmlInputElement>() {
+                        input.set_value("");
+                    }
+                    return;
+                }
+            }
+            _ => {}
+        }
+        let link = self.link.clone();
+        self.usi_input_flash = Some(Timeout::new(1000, move || {
+            link.send_message(Msg::StopUsiInputFlash);
+        }));
+    }
+
+    /// Whether to go ahead with a move that may replace the rest of the game, asking
+    /// first if it would and the setting is on.
+    fn confirm_overwrite(&self, next_move: Move) -> bool {
+        let overwritten = self.future_moves.len();
+        if !self.settings.confirm_overwrite
+            || overwritten == 0
+            || self.future_moves.last().map(move_from_record) == Some(next_move)
+        {
+            return true;
+        }
+        let lang = self.settings.lang;
+        gloo::dialogs::confirm(&if overwritten == 1 {
+            t(
+                "Playing this move replaces the move after it. Continue?",
+                lang,
+            )
+            .to_owned()
+        } else {
+            t_fill(
+                "Playing this move replaces the {} moves after it. Continue?",
+                lang,
+                overwritten,
+            )
+        })
+    }
+
+    /// Plays a legal move, keeping the history, comments, puzzle and host page in step.
+    fn play_move(&mut self, next_move: Move) {
+        let to = match next_move {
+            Move::Normal { to, .. } => to,
+            Move::Drop { to, .. } => to,
+        };
+        self.play_move_sound(MoveSoundKind::for_captured(*self.position.piece_at(to)));
+
+        let hands_before = self.hand_counts();
+        self.position.make_move(next_move).unwrap();
+        self.declared_impasse = None;
+        self.move_intent = MoveIntentBuilder::NoIntent;
+        let gained = self
+            .hand_counts()
+            .into_iter()
+            .zip(hands_before)
+            .find(|((_, after), (_, before))| after > before)
+            .map(|((piece, _), _)| piece);
+        if let Some(piece) = gained {
+            let link = self.link.clone();
+            let timeout = Timeout::new(JUST_GAINED_PULSE_MS, move || {
+                link.send_message(Msg::StopJustGained);
+            });
+            self.just_gained = Some((piece, timeout));
+        }
+        if self.settings.preview_mobility {
+            self.flash_mobility(to);
+        }
+        let now = Date::now();
+        let seconds = ((now - self.last_move_instant) / 1000.0).max(0.0).round() as u32;
+        self.last_move_instant = now;
+
+        // Keep the rest of the game if the move repeats it, otherwise the game now
+        // continues differently.
+        if self.future_moves.last().map(move_from_record) == Some(next_move) {
+            self.future_moves.pop();
+        } else {
+            self.future_moves.clear();
+            let ply = self.position.move_history().len();
+            self.comments
+                .retain(|commented_ply, _| *commented_ply < ply);
+            self.move_times.retain(|timed_ply, _| *timed_ply < ply);
+        }
+        self.move_times
+            .insert(self.position.move_history().len(), seconds);
+
+        // Only follow the game to its latest move, and leave a replayed past move alone.
+        if self.settings.follow_game && self.future_moves.is_empty() {
+            // Scroll after update.
+            let history_bottom_ref = self.history_bottom_ref.clone();
+            Timeout::new(0, move || {
+                if let Some(history_bottom) = history_bottom_ref.cast::<Element>() {
+                    let _ = history_bottom.scroll_into_view();
+                }
+            })
+            .forget();
+        }
+
+        // A wrong puzzle answer has already been taken back, so the host never sees it.
+        if !self.check_puzzle_answer(next_move) {
+            return;
+        }
+
+        let sfen = self.position.to_sfen();
+        host_events::dispatch(
+            "shogi-move",
+            &[("usi", &move_to_usi(next_move)), ("sfen", &sfen)],
+        );
+        self.report_game_over(self.status());
+    }
+
+    fn play_move_sound(&self, kind: MoveSoundKind) {
+        if self.settings.mute {
+            return;
+        }
+        let audio_ref = match kind {
+            MoveSoundKind::Quiet => &self.move_audio_ref,
+            MoveSoundKind::Capture => &self.capture_audio_ref,
+            MoveSoundKind::MajorCapture => &self.major_capture_audio_ref,
+        };
+        if let Some(audio) = audio_ref.cast::<HtmlAudioElement>() {
+            let _ = audio.play();
+        }
+    }
+
+    fn play_tick_sound(&self) {
+        if self.settings.mute {
+            return;
+        }
+        if let Some(audio) = self.tick_audio_ref.cast::<HtmlAudioElement>() {
+            let _ = audio.play();
+        }
+    }
+
+    /// Ticks through the last seconds of the side to move's byoyomi, which counts from
+    /// when the position was shown. Falls silent once the move is made or the time is up.
+    fn byoyomi_tick(&self) {
+        let byoyomi_seconds = match self.settings.byoyomi_seconds {
+            Some(byoyomi_seconds) => byoyomi_seconds,
+            None => return,
+        };
+        if self.editing || self.status().is_over() {
+            return;
+        }
+        let elapsed = ((Date::now() - self.last_move_instant) / 1000.0).max(0.0) as u32;
+        let remaining = byoyomi_seconds.saturating_sub(elapsed);
+        if remaining > 0 && remaining <= self.settings.byoyomi_tick_seconds {
+            self.play_tick_sound();
+        }
+    }
+}
+
+impl Component for Model {
+    type Message = Msg;
+    type Properties = ();
+
+    fn create(_props: Self::Properties, link: ComponentLink<Self>) -> Self {
+        BBFactory::init();
+        let link_clone = link.clone();
+        let link_for_escape = link.clone();
+        let mut model = Self {
+            link,
+            position: Position::new(),
+            future_moves: Vec::new(),
+            comments: HashMap::new(),
+            meta: GameMeta::default(),
+            move_times: HashMap::new(),
+            last_move_instant: Date::now(),
+            move_intent: MoveIntentBuilder::NoIntent,
+            settings: Settings {
+                piece_styles: PieceStyles {
+                    black: stored_piece_style(BLACK_PIECE_STYLE_STORAGE_KEY),
+                    white: stored_piece_style(WHITE_PIECE_STYLE_STORAGE_KEY),
+                },
+                show_dots: LocalStorage::get(SHOW_DOTS_STORAGE_KEY).unwrap_or(true),
+                high_contrast: LocalStorage::get(HIGH_CONTRAST_STORAGE_KEY).unwrap_or(false),
+                strict_url_positions: LocalStorage::get(STRICT_URL_POSITIONS_STORAGE_KEY)
+                    .unwrap_or(false),
+                lang: LocalStorage::get(LANG_STORAGE_KEY)
+                    .ok()
+                    .and_then(|name: String| Lang::from_name(&name))
+                    .unwrap_or_default(),
+                cpu_color: LocalStorage::get(CPU_COLOR_STORAGE_KEY)
+                    .ok()
+                    .and_then(|name: String| color_from_name(&name)),
+                ..Default::default()
+            },
+            replay: None,
+            byoyomi_ticker: None,
+            replay_speed_ms: 1000,
+            move_audio_ref: Default::default(),
+            capture_audio_ref: Default::default(),
+            major_capture_audio_ref: Default::default(),
+            tick_audio_ref: Default::default(),
+            history_bottom_ref: Default::default(),
+            history_scroll_top: 0,
+            history_height: 400,
+            history_width: LocalStorage::get(HISTORY_WIDTH_STORAGE_KEY)
+                .map(|width: f64| width.clamp(MIN_HISTORY_WIDTH_PX, MAX_HISTORY_WIDTH_PX))
+                .unwrap_or(DEFAULT_HISTORY_WIDTH_PX),
+            history_resize: None,
+            destination_input_ref: Default::default(),
+            usi_input_ref: Default::default(),
+            usi_input_flash: None,
+            should_focus_destination_input: false,
+            destination_input_shake: None,
+            sfen_input_ref: Default::default(),
+            sfen_input_flash: None,
+            rejected_sfen: None,
+            refused_url_violations: Vec::new(),
+            reveal_pieces: None,
+            just_gained: None,
+            changed_squares: None,
+            mobility_preview: None,
+            uchifuzume_feedback: None,
+            restart_armed: None,
+            cpu_move: None,
+            cpu_rng: None,
+            random_position_plies: 40,
+            lesson: None,
+            lesson_start_ply: 0,
+            lesson_mistake: false,
+            lesson_reply: None,
+            tsume_reply: None,
+            tsume_result: None,
+            mate_search: None,
+            puzzle: None,
+            highlighted_line: None,
+            editing: false,
+            opponent_view: false,
+            palette_piece: None,
+            hovered_square: None,
+            _hash_change_listener: EventListener::new(&window(), "hashchange", move |_| {
+                link_clone.send_message(Msg::LoadFromUrl);
+            }),
+            _escape_listener: EventListener::new(&window(), "keydown", move |event| {
+                if event
+                    .dyn_ref::<KeyboardEvent>()
+                    .map_or(false, |event| event.key() == "Escape")
+                    && !is_typing_into(event)
+                {
+                    link_for_escape.send_message(Msg::CancelMove);
+                }
+            }),
+            file_reader: None,
+            synced_url_hash: String::new(),
+            presets: load_presets(),
+            reference_position: None,
+            declared_impasse: None,
+            start_sfen: STARTING_SFEN.to_owned(),
+            undo_check: None,
+            analysed_game: String::new(),
+            move_disambiguations: Vec::new(),
+            material_balances: Vec::new(),
+            export: None,
+        };
+        model.load_initial_position();
+        model.refresh_game_analysis();
+        model.sync_url_hash();
+        model.schedule_cpu_move();
+        model
     }
 
     fn update(&mut self, msg: Self::Message) -> ShouldRender {
         match msg {
+            Msg::ClickSquare(square) if self.editing => {
+                if let Some(piece) = self.palette_piece {
+                    self.stamp_square(square, Some(piece));
+                }
+            }
+            Msg::ClickSquare(_) | Msg::ClickHeldPiece(..)
+                if self.status().is_over()
+                    || self.cpu_move.is_some()
+                    || self.lesson_reply.is_some()
+                    || self.tsume_reply.is_some() => {}
+            Msg::ClickSquare(_) | Msg::ClickHeldPiece(..) if !self.move_intent.accepts_clicks() => {
+            }
             Msg::ClickSquare(square) => match self.move_intent {
                 MoveIntentBuilder::NoIntent => {
                     if let Some(piece) = self.position.piece_at(square) {
@@ -408,13 +2708,23 @@ impl Component for Model {
                     if self.move_intent.can_move_to(square, &self.position) {
                         self.choose_destination(square);
                     } else {
+                        if self.settings.explain_uchifuzume
+                            && self.move_intent.move_origin_hand_piece_type()
+                                == Some(PieceType::Pawn)
+                            && self.is_uchifuzume(square)
+                        {
+                            let link = self.link.clone();
+                            self.uchifuzume_feedback =
+                                Some(Timeout::new(UCHIFUZUME_FEEDBACK_MS, move || {
+                                    link.send_message(Msg::StopUchifuzumeFeedback);
+                                }));
+                        }
                         self.clear_choice();
                     }
                 }
-                MoveIntentBuilder::WithDestination { .. } => {
-                    self.clear_choice();
-                }
+                MoveIntentBuilder::WithDestination { .. } => {}
             },
+            Msg::ClickHeldPiece(..) if self.editing => {}
             Msg::ClickHeldPiece(piece_type, color) => match self.move_intent {
                 MoveIntentBuilder::NoIntent => {
                     if color == self.position.side_to_move()
@@ -426,18 +2736,334 @@ impl Component for Model {
                     }
                 }
                 MoveIntentBuilder::WithOrigin { .. } => self.clear_choice(),
-                MoveIntentBuilder::WithDestination { .. } => self.clear_choice(),
+                MoveIntentBuilder::WithDestination { .. } => {}
             },
             Msg::ChoosePromote(promote) => {
                 self.choose_promote(promote);
             }
-            Msg::Restart => self.reset(),
+            Msg::ToggleEditing => {
+                self.editing = !self.editing;
+                self.clear_choice();
+            }
+            Msg::SelectPalettePiece(piece) => {
+                self.palette_piece = if self.palette_piece == Some(piece) {
+                    None
+                } else {
+                    Some(piece)
+                };
+            }
+            Msg::ClearSquare(square) => self.stamp_square(square, None),
+            Msg::CancelMove => self.clear_choice(),
+            Msg::ImportJson => {
+                let before = self.pieces();
+                self.import_json();
+                self.flash_changed_squares(before);
+            }
+            Msg::Restart => {
+                let has_game =
+                    !self.position.move_history().is_empty() || !self.future_moves.is_empty();
+                if self.settings.confirm_restart && has_game && self.restart_armed.is_none() {
+                    let link = self.link.clone();
+                    self.restart_armed = Some(Timeout::new(3000, move || {
+                        link.send_message(Msg::DisarmRestart);
+                    }));
+                } else {
+                    self.restart_armed = None;
+                    self.reset();
+                    self.schedule_cpu_move();
+                }
+            }
+            Msg::DisarmRestart => {
+                self.restart_armed = None;
+            }
+            Msg::ToggleConfirmOverwrite => {
+                self.settings.confirm_overwrite = !self.settings.confirm_overwrite;
+            }
+            Msg::ToggleConfirmRestart => {
+                self.settings.confirm_restart = !self.settings.confirm_restart;
+                self.restart_armed = None;
+            }
+            Msg::StartPreset(index) => {
+                if let Some(preset) = self.presets.get(index).cloned() {
+                    self.start_preset(&preset);
+                }
+            }
+            Msg::SavePreset => self.save_preset(),
+            Msg::RandomPosition => self.load_random_position(),
+            Msg::SetRandomPositionPlies(plies) => self.random_position_plies = plies,
             Msg::Undo => self.undo(),
+            Msg::UndoMyMove => self.undo_my_move(),
+            Msg::LessonReply => self.play_lesson_reply(),
+            Msg::TsumeReply => self.play_tsume_reply(),
+            Msg::ToggleTsumeDefence => {
+                self.settings.tsume_defence = !self.settings.tsume_defence;
+                self.tsume_reply = None;
+                self.tsume_result = None;
+            }
+            Msg::SetCpuColor(cpu_color) => {
+                self.settings.cpu_color = cpu_color;
+                store_cpu_color(cpu_color);
+                self.cpu_move = None;
+                self.schedule_cpu_move();
+            }
+            Msg::SetCpuThinkMs(think_ms) => self.settings.cpu_think_ms = think_ms,
+            Msg::SetCpuSeed(cpu_seed) => {
+                self.settings.cpu_seed = cpu_seed;
+                self.reseed_cpu();
+            }
+            Msg::SetByoyomi(byoyomi_seconds) => {
+                self.settings.byoyomi_seconds = byoyomi_seconds;
+                self.byoyomi_ticker = byoyomi_seconds.map(|_| {
+                    let link = self.link.clone();
+                    Interval::new(BYOYOMI_TICK_INTERVAL_MS, move || {
+                        link.send_message(Msg::ByoyomiTick);
+                    })
+                });
+            }
+            Msg::SetByoyomiTickSeconds(tick_seconds) => {
+                self.settings.byoyomi_tick_seconds = tick_seconds;
+            }
+            Msg::ByoyomiTick => {
+                self.byoyomi_tick();
+                return false;
+            }
+            Msg::SetShareScope(share_scope) => self.settings.share_scope = share_scope,
+            Msg::Export(format) => self.export = Some(self.build_export(format)),
+            Msg::SetDiagramArrows(diagram_arrows) => {
+                self.settings.diagram_arrows = diagram_arrows;
+            }
+            Msg::CpuMove => self.play_cpu_move(),
+            Msg::Redo => self.redo(),
             Msg::LoadFromUrl => {
+                let before = self.pieces();
                 let _ = self.try_load_from_url();
+                self.flash_changed_squares(before);
+            }
+            Msg::StopChangedSquares => self.changed_squares = None,
+            Msg::StopMobilityPreview => self.mobility_preview = None,
+            Msg::ToggleMobilityPreview => {
+                self.settings.preview_mobility = !self.settings.preview_mobility;
+                self.mobility_preview = None;
+            }
+            Msg::StopUchifuzumeFeedback => self.uchifuzume_feedback = None,
+            Msg::ToggleExplainUchifuzume => {
+                self.settings.explain_uchifuzume = !self.settings.explain_uchifuzume;
+            }
+            Msg::ToggleHeatmap => {
+                self.settings.show_heatmap = !self.settings.show_heatmap;
+            }
+            Msg::ToggleHints => {
+                self.settings.show_hints = !self.settings.show_hints;
+            }
+            Msg::ToggleMute => {
+                self.settings.mute = !self.settings.mute;
+            }
+            Msg::SetNotation(notation) => {
+                self.settings.notation = notation;
+            }
+            Msg::SetDropStyle(drop_style) => {
+                self.settings.drop_style = drop_style;
+            }
+            Msg::SetLang(lang) => {
+                self.settings.lang = lang;
+                let _ = LocalStorage::set(LANG_STORAGE_KEY, lang.name());
+            }
+            Msg::SetPieceStyle(color, style) => {
+                let key = match color {
+                    Color::Black => {
+                        self.settings.piece_styles.black = style;
+                        BLACK_PIECE_STYLE_STORAGE_KEY
+                    }
+                    Color::White => {
+                        self.settings.piece_styles.white = style;
+                        WHITE_PIECE_STYLE_STORAGE_KEY
+                    }
+                };
+                let _ = LocalStorage::set(key, style.name());
+            }
+            Msg::SetAutoPromote(auto_promote) => {
+                self.settings.auto_promote = auto_promote;
+            }
+            Msg::SetCandidateStrategy(candidate_strategy) => {
+                self.settings.candidate_strategy = candidate_strategy;
+            }
+            Msg::ToggleTryRule => self.settings.try_rule = !self.settings.try_rule,
+            Msg::SetImpasseRule(impasse_rule) => {
+                self.settings.impasse_rule = impasse_rule;
+            }
+            Msg::DeclareImpasse => self.declare_impasse(),
+            Msg::ToggleBlindfold => {
+                self.settings.blindfold = !self.settings.blindfold;
+            }
+            Msg::OpponentView => self.toggle_opponent_view(),
+            Msg::ToggleFlip => {
+                self.settings.flipped = !self.settings.flipped;
+            }
+            Msg::ToggleCoordinates => {
+                self.settings.show_coordinates = !self.settings.show_coordinates;
+                self.highlighted_line = None;
+            }
+            Msg::TogglePins => self.settings.show_pins = !self.settings.show_pins,
+            Msg::ToggleDanger => self.settings.show_danger = !self.settings.show_danger,
+            Msg::ToggleOpponentMoves => {
+                self.settings.show_opponent_moves = !self.settings.show_opponent_moves;
+            }
+            Msg::ToggleDropCounts => {
+                self.settings.show_drop_counts = !self.settings.show_drop_counts;
+            }
+            Msg::ToggleFocusSide => self.settings.focus_side = !self.settings.focus_side,
+            Msg::ToggleFollowGame => self.settings.follow_game = !self.settings.follow_game,
+            Msg::TogglePromotionZones => {
+                self.settings.show_promotion_zones = !self.settings.show_promotion_zones;
+            }
+            Msg::StopJustGained => self.just_gained = None,
+            Msg::ToggleStrictUrlPositions => {
+                self.settings.strict_url_positions = !self.settings.strict_url_positions;
+                let _ = LocalStorage::set(
+                    STRICT_URL_POSITIONS_STORAGE_KEY,
+                    self.settings.strict_url_positions,
+                );
+            }
+            Msg::ToggleAutoCopyLink => {
+                self.settings.auto_copy_link = !self.settings.auto_copy_link;
+            }
+            Msg::ToggleHighContrast => {
+                self.settings.high_contrast = !self.settings.high_contrast;
+                let _ = LocalStorage::set(HIGH_CONTRAST_STORAGE_KEY, self.settings.high_contrast);
+            }
+            Msg::ToggleDots => {
+                self.settings.show_dots = !self.settings.show_dots;
+                let _ = LocalStorage::set(SHOW_DOTS_STORAGE_KEY, self.settings.show_dots);
+            }
+            Msg::ToggleCaptureLog => {
+                self.settings.show_capture_log = !self.settings.show_capture_log;
+            }
+            Msg::ToggleHandBalance => {
+                self.settings.show_hand_balance = !self.settings.show_hand_balance;
+            }
+            Msg::CheckUndoSymmetry => {
+                self.undo_check = Some(check_undo_symmetry(
+                    &self.position,
+                    UNDO_CHECK_PLIES,
+                    &mut rand::thread_rng(),
+                ));
+            }
+            Msg::ToggleDebug => {
+                self.settings.show_debug = !self.settings.show_debug;
+            }
+            Msg::SetSquareBorder(border_px) => self.settings.square_border_px = border_px,
+            Msg::SetPieceInset(inset_px) => self.settings.piece_inset_px = inset_px,
+            Msg::ToggleSquareIndices => {
+                self.settings.show_square_indices = !self.settings.show_square_indices;
+            }
+            Msg::HoverSquare(square) => {
+                self.hovered_square = square;
+                // Only the ghost arrow and hover-checked candidates depend on the hovered
+                // square.
+                return matches!(self.move_intent, MoveIntentBuilder::WithOrigin { .. });
+            }
+            Msg::HighlightFile(file) => {
+                self.toggle_highlighted_line(BoardLine::File(file));
+            }
+            Msg::HighlightRank(rank) => {
+                self.toggle_highlighted_line(BoardLine::Rank(rank));
+            }
+            Msg::ToggleBlindfoldRevealOnHover => {
+                self.settings.blindfold_reveal_on_hover = !self.settings.blindfold_reveal_on_hover;
+            }
+            Msg::RevealPieces => {
+                let link = self.link.clone();
+                self.reveal_pieces = Some(Timeout::new(2000, move || {
+                    link.send_message(Msg::HidePieces);
+                }));
+            }
+            Msg::HidePieces => {
+                self.reveal_pieces = None;
+            }
+            Msg::SearchMate => {
+                self.mate_search =
+                    Some((self.position.to_sfen(), self.mate_in(MATE_SEARCH_MAX_PLIES)));
+            }
+            Msg::TypeDestination(text) => self.type_destination(&text),
+            Msg::SubmitUsi(text) => self.submit_usi(&text),
+            Msg::StopUsiInputFlash => {
+                self.usi_input_flash = None;
+            }
+            Msg::StopDestinationInputShake => {
+                self.destination_input_shake = None;
+            }
+            Msg::GotoPly(ply) => self.goto_ply(ply),
+            Msg::ToggleReplay => self.toggle_replay(),
+            Msg::ReplayStep => self.replay_step(),
+            Msg::SetReplaySpeed(speed_ms) => {
+                self.replay_speed_ms = speed_ms;
+                if self.replay.is_some() {
+                    self.start_replay();
+                }
+            }
+            Msg::SetTrailLength(trail_length) => {
+                self.settings.trail_length = trail_length;
+            }
+            Msg::SetMoveNumbering(move_numbering) => {
+                self.settings.move_numbering = move_numbering;
+            }
+            Msg::SetHistoryColumns(history_columns) => {
+                self.settings.history_columns = history_columns;
+            }
+            Msg::SetSfen(sfen) => {
+                let before = self.pieces();
+                self.set_sfen(&sfen);
+                self.flash_changed_squares(before);
+            }
+            Msg::SetReferenceSfen(sfen) => self.set_reference_sfen(&sfen),
+            Msg::ClearReference => self.reference_position = None,
+            Msg::DropFile(file) => self.read_dropped_file(file),
+            Msg::LoadDroppedText(text) => {
+                let before = self.pieces();
+                self.load_dropped_text(&text);
+                self.flash_changed_squares(before);
+            }
+            // Comments and the players are written into the exports, so exports made before
+            // are out of date.
+            Msg::SetComment(comment) => {
+                self.set_comment(comment);
+                self.export = None;
+            }
+            Msg::SetMetaField(field, value) => {
+                *self.meta.field_mut(field) = value;
+                self.export = None;
+            }
+            Msg::ScrollHistory { scroll_top, height } => {
+                let row_count = self.history_row_count();
+                let previous_window = self.history_window(row_count);
+                self.history_scroll_top = scroll_top;
+                self.history_height = height;
+                return self.history_window(row_count) != previous_window;
+            }
+            Msg::StartHistoryResize(x) => self.start_history_resize(x),
+            Msg::ResizeHistory(x) => self.resize_history(x),
+            Msg::StopHistoryResize => self.stop_history_resize(),
+            Msg::SetSideToMove(color) => {
+                self.set_side_to_move(color);
+            }
+            Msg::StopSfenInputFlash => {
+                self.sfen_input_flash = None;
             }
         }
 
+        // Whatever changed the position, a piece chosen before it may not be there now.
+        if self.move_intent.is_stale(&self.position) {
+            self.clear_choice();
+        }
+        self.refresh_game_analysis();
+        if self
+            .export
+            .as_ref()
+            .map_or(false, |export| export.source != self.export_source())
+        {
+            self.export = None;
+        }
+        self.sync_url_hash();
         true
     }
 
@@ -445,50 +3071,105 @@ impl Component for Model {
         false
     }
 
-    fn view(&self) -> Html {
-        if let Ok(history) = window().history() {
-            let new_url = format!("#{}", encode(self.position.to_sfen()));
-            let _ = history.replace_state_with_url(&JsValue::NULL, "", Some(&new_url));
+    fn rendered(&mut self, first_render: bool) {
+        if first_render {
+            // There's only one sound asset, so captures play it slower and lower and the
+            // byoyomi ticks faster and higher.
+            for (audio_ref, playback_rate) in [
+                (&self.capture_audio_ref, 0.85),
+                (&self.major_capture_audio_ref, 0.7),
+                (&self.tick_audio_ref, 1.6),
+            ] {
+                if let Some(audio) = audio_ref.cast::<HtmlAudioElement>() {
+                    audio.set_playback_rate(playback_rate);
+                    let _ = Reflect::set(
+                        &audio,
+                        &JsValue::from_str("preservesPitch"),
+                        &JsValue::FALSE,
+                    );
+                }
+            }
         }
+        if self.should_focus_destination_input {
+            self.should_focus_destination_input = false;
+            if let Some(input) = self.destination_input_ref.cast::<HtmlInputElement>() {
+                let _ = input.focus();
+            }
+        }
+    }
 
-        let white_hand_pieces: Vec<HandPiece> = PieceType::iter()
-            .filter(|piece_type| piece_type.is_hand_piece())
-            .map(|piece_type| HandPiece {
-                piece_type,
-                count: self.position.hand(Piece {
-                    piece_type,
-                    color: Color::White,
-                }),
-            })
-            .collect();
-
-        let black_hand_pieces: Vec<HandPiece> = PieceType::iter()
-            .filter(|piece_type| piece_type.is_hand_piece())
-            .map(|piece_type| HandPiece {
-                piece_type,
-                count: self.position.hand(Piece {
-                    piece_type,
-                    color: Color::Black,
-                }),
-            })
-            .collect();
-
-        let white_hand_selection = if self.position.side_to_move() == Color::White {
-            self.move_intent.move_origin_hand_piece_type()
+    fn view(&self) -> Html {
+        let lang = self.settings.lang;
+        // Each candidate computation tries moves on a copy of the position, so work them
+        // out once per render.
+        let show_hints = self.show_hints();
+        // How many squares were tried as destinations, for the debug panel.
+        let (move_origin_candidates, (move_destination_candidates, squares_checked)) = if show_hints
+        {
+            (
+                self.move_intent.move_origin_candidates(&self.position),
+                self.move_intent.rendered_destination_candidates(
+                    &self.position,
+                    self.settings.candidate_strategy,
+                    self.hovered_square,
+                ),
+            )
         } else {
-            None
+            Default::default()
+        };
+        // A lesson only lights up the move it expects.
+        let (move_origin_candidates, move_destination_candidates) =
+            match self.expected_lesson_move() {
+                Some(Move::Normal { from, to, .. }) => (
+                    move_origin_candidates
+                        .into_iter()
+                        .filter(|square| *square == from)
+                        .collect(),
+                    move_destination_candidates
+                        .into_iter()
+                        .filter(|square| *square == to)
+                        .collect(),
+                ),
+                Some(Move::Drop { to, .. }) => (
+                    HashSet::new(),
+                    move_destination_candidates
+                        .into_iter()
+                        .filter(|square| *square == to)
+                        .collect(),
+                ),
+                None => (move_origin_candidates, move_destination_candidates),
+            };
+        // Every legal move escapes a check, but beginners benefit from seeing it spelt out.
+        let check_escapes = if self.position.in_check(self.position.side_to_move()) {
+            move_destination_candidates.clone()
+        } else {
+            HashSet::new()
         };
 
-        let black_hand_selection = if self.position.side_to_move() == Color::Black {
-            self.move_intent.move_origin_hand_piece_type()
+        let pinned_squares = if self.settings.show_pins {
+            pinned_squares(&self.position)
         } else {
-            None
+            HashSet::new()
+        };
+
+        let danger_squares = match self.move_intent.move_origin_square() {
+            Some(from) if self.settings.show_danger => king_danger_squares(&self.position, from),
+            _ => HashSet::new(),
+        };
+
+        let opponent_destinations: HashSet<Square> = if self.settings.show_opponent_moves {
+            self.attack_counts(self.position.side_to_move().flip())
+                .into_keys()
+                .collect()
+        } else {
+            HashSet::new()
         };
 
-        let white_hand_can_select = self.position.side_to_move() == Color::White
-            && matches!(self.move_intent, MoveIntentBuilder::NoIntent);
-        let black_hand_can_select = self.position.side_to_move() == Color::Black
-            && matches!(self.move_intent, MoveIntentBuilder::NoIntent);
+        let top_hand_color = if self.settings.flipped {
+            Color::Black
+        } else {
+            Color::White
+        };
 
         let previous_move_origin = self
             .position
@@ -508,12 +3189,40 @@ impl Component for Model {
                     MoveRecord::Drop { to, .. } => *to,
                 });
 
+        let attack_counts = if self.settings.show_heatmap {
+            self.attack_counts(self.position.side_to_move())
+        } else {
+            Default::default()
+        };
+
+        let game_records = self.game_records();
+        let history_layout = HistoryLayout::for_numbering(self.move_numbering());
+        let history_row_count = self.history_row_count();
+        let history_window = self.history_window(history_row_count);
+        let rule_violations = diagnose(&self.position);
+        let status = self.status();
+        let mut sfen_input_classes = classes!();
+        if self.sfen_input_flash.is_some() {
+            sfen_input_classes.push("invalid");
+        }
+        let viewed_ply = self.position.move_history().len();
+        let first_mover = self.first_mover();
+        let first_move_number = self.first_move_number();
+        // In two-column rows Black's moves go on the left, even when White moved first.
+        let first_slot = if first_mover == Color::White { 1 } else { 0 };
+
         html! {
             <>
-                <audio preload="auto" ref=self.move_audio_ref.clone()>
-                    <source src="./assets/sounds/Move.ogg" type="audio/ogg" />
-                    <source src="./assets/sounds/Move.mp3" type="audio/mpeg" />
-                </audio>
+                {
+                    for [&self.move_audio_ref, &self.capture_audio_ref, &self.major_capture_audio_ref, &self.tick_audio_ref]
+                        .iter()
+                        .map(|audio_ref| html! {
+                            <audio preload="auto" ref=(*audio_ref).clone()>
+                                <source src="./assets/sounds/Move.ogg" type="audio/ogg" />
+                                <source src="./assets/sounds/Move.mp3" type="audio/mpeg" />
+                            </audio>
+                        })
+                }
                 <h1>
                     {"I tried learning yew+rust but got transported to another world and reincarnated as a shogi board. "}
                     <a href="https://yew.rs/">
@@ -532,36 +3241,484 @@ impl Component for Model {
                         {"source code"}
                     </a>
                 </h1>
-                <div class=classes!("game")>
+                <StatusBar
+                    lang=lang
+                    side_to_move=self.position.side_to_move()
+                    move_number=self.position.ply() as usize
+                    material_balance=Some(material_balance(&self.position))
+                    status=status
+                />
+                <div
+                    class=classes!("game")
+                    ondragover=Callback::from(|event: DragEvent| event.prevent_default())
+                    ondrop=self.link.batch_callback(|event: DragEvent| {
+                        event.prevent_default();
+                        event
+                            .data_transfer()
+                            .and_then(|data_transfer| data_transfer.files())
+                            .and_then(|files| files.get(0))
+                            .map(Msg::DropFile)
+                    })
+                >
                     <div class="left">
-                        <Hand
-                            color=Color::White
-                            pieces=white_hand_pieces
-                            selection=white_hand_selection
-                            can_select=white_hand_can_select
-                            on_piece_click=self.link.callback(|piece_type|Msg::ClickHeldPiece(piece_type, Color::White))
-                        />
+                        { self.view_hand(top_hand_color) }
+                        {
+                            match &self.puzzle {
+                                Some(puzzle) => html! {
+                                    <div class="puzzle">
+                                        {
+                                            match puzzle.status {
+                                                PuzzleStatus::Unsolved => t("Find the best move.", lang).to_owned(),
+                                                PuzzleStatus::Solved => t("Well done, that's the move!", lang).to_owned(),
+                                                PuzzleStatus::Missed => t_fill("Not quite. {}", lang, puzzle.hint(lang)),
+                                            }
+                                        }
+                                    </div>
+                                },
+                                None => html! {},
+                            }
+                        }
+                        {
+                            if self.lesson.is_some() {
+                                html! {
+                                    <div class="puzzle">
+                                        {
+                                            if self.is_lesson_complete() {
+                                                t("Lesson complete!", lang)
+                                            } else if self.lesson_mistake {
+                                                t("That's not the move for this lesson. Try again.", lang)
+                                            } else if self.expected_lesson_move().is_some() {
+                                                t("Play the lesson's next move.", lang)
+                                            } else {
+                                                ""
+                                            }
+                                        }
+                                    </div>
+                                }
+                            } else {
+                                html! {}
+                            }
+                        }
+                        {
+                            match self.tsume_result {
+                                Some(result) => html! {
+                                    <div class="puzzle">
+                                        {
+                                            match result {
+                                                TsumeResult::Mated => t("Mate! The defence couldn't hold out.", lang).to_owned(),
+                                                TsumeResult::NotCheck => t("Missed: every move of a tsume must give check.", lang).to_owned(),
+                                                TsumeResult::NoMateFound => t_fill("Missed: no mate found within {} plies.", lang, MATE_SEARCH_MAX_PLIES),
+                                            }
+                                        }
+                                    </div>
+                                },
+                                None => html! {},
+                            }
+                        }
+                        {
+                            if self.uchifuzume_feedback.is_some() {
+                                html! {
+                                    <div class="puzzle" role="alert">
+                                        { t("打ち歩詰めは反則: a pawn drop may not give checkmate.", lang) }
+                                    </div>
+                                }
+                            } else {
+                                html! {}
+                            }
+                        }
+                        {
+                            if rule_violations.is_empty() {
+                                html! {}
+                            } else {
+                                let repaired_sfen = repair_sfen(&self.position.to_sfen());
+                                html! {
+                                    <div class="rule-violations">
+                                        <ul>
+                                            { for rule_violations.iter().map(|violation| html! { <li>{ describe_diagnosis(violation, lang) }</li> }) }
+                                        </ul>
+                                        {
+                                            match repaired_sfen {
+                                                Some(repaired_sfen) => html! {
+                                                    <button onclick=self.link.callback(move |_| Msg::SetSfen(repaired_sfen.clone()))>
+                                                        { t("Repair", lang) }
+                                                    </button>
+                                                },
+                                                None => html! {},
+                                            }
+                                        }
+                                    </div>
+                                }
+                            }
+                        }
                         <div class="fill" />
+                        {
+                            if matches!(self.move_intent, MoveIntentBuilder::WithDestination { .. }) {
+                                html! {
+                                    <button onclick=self.link.callback(|_| Msg::CancelMove)>
+                                        { t("Cancel move", lang) }
+                                    </button>
+                                }
+                            } else {
+                                html! {}
+                            }
+                        }
+                        {
+                            if show_hints {
+                                self.view_intent_preview(&move_destination_candidates)
+                            } else {
+                                html! {}
+                            }
+                        }
+                        {
+                            if matches!(self.move_intent, MoveIntentBuilder::WithOrigin { .. }) {
+                                let mut destination_input_classes = classes!("destination-input");
+                                if self.destination_input_shake.is_some() {
+                                    destination_input_classes.push("shake");
+                                }
+                                html! {
+                                    <input
+                                        class=destination_input_classes
+                                        type="text"
+                                        placeholder=t("Destination, e.g. 7f", lang)
+                                        ref=self.destination_input_ref.clone()
+                                        onkeydown=self.link.batch_callback(|event: KeyboardEvent| {
+                                            // The page-wide Escape leaves text boxes alone, but this
+                                            // one only exists to finish the move.
+                                            if event.key() == "Escape" {
+                                                return Some(Msg::CancelMove);
+                                            }
+                                            if event.key() != "Enter" {
+                                                return None;
+                                            }
+                                            event.target()
+                                                .and_then(|target| target.dyn_into::<HtmlInputElement>().ok())
+                                                .map(|input| Msg::TypeDestination(input.value()))
+                                        })
+                                    />
+                                }
+                            } else {
+                                html! {}
+                            }
+                        }
+                        <input
+                            class=classes!("usi-input", self.usi_input_flash.is_some().then(|| "invalid"))
+                            type="text"
+                            aria-label=t("Move in USI", lang)
+                            placeholder=t("Move, e.g. 7g7f", lang)
+                            ref=self.usi_input_ref.clone()
+                            onkeydown=self.link.batch_callback(|event: KeyboardEvent| {
+                                if event.key() != "Enter" {
+                                    return None;
+                                }
+                                event.target()
+                                    .and_then(|target| target.dyn_into::<HtmlInputElement>().ok())
+                                    .map(|input| Msg::SubmitUsi(input.value()))
+                            })
+                        />
                         <button
                             disabled=self.position.move_history().is_empty()
                             onclick=self.link.callback(|_| Msg::Undo)
                         >
-                            {"Undo"}
+                            { t("Undo", lang) }
+                        </button>
+                        <button
+                            disabled=self.future_moves.is_empty()
+                            onclick=self.link.callback(|_| Msg::Redo)
+                        >
+                            { t("Redo", lang) }
                         </button>
+                        {
+                            if self.settings.cpu_color.is_some() {
+                                html! {
+                                    <button
+                                        disabled=self.position.move_history().is_empty()
+                                        onclick=self.link.callback(|_| Msg::UndoMyMove)
+                                    >
+                                        { t("Undo my move", lang) }
+                                    </button>
+                                }
+                            } else {
+                                html! {}
+                            }
+                        }
+                        <select
+                            aria-label=t("Opponent", lang)
+                            onchange=self.link.batch_callback(|change: ChangeData| match change {
+                                ChangeData::Select(select) => match select.value().as_str() {
+                                    "human" => Some(Msg::SetCpuColor(None)),
+                                    "cpu-white" => Some(Msg::SetCpuColor(Some(Color::White))),
+                                    "cpu-black" => Some(Msg::SetCpuColor(Some(Color::Black))),
+                                    _ => None,
+                                },
+                                _ => None,
+                            })
+                        >
+                            <option value="human" selected=self.settings.cpu_color.is_none()>
+                                { t("Two players", lang) }
+                            </option>
+                            <option value="cpu-white" selected=self.settings.cpu_color == Some(Color::White)>
+                                { t("CPU plays White", lang) }
+                            </option>
+                            <option value="cpu-black" selected=self.settings.cpu_color == Some(Color::Black)>
+                                { t("CPU plays Black", lang) }
+                            </option>
+                        </select>
+                        {
+                            if self.settings.cpu_color.is_some() {
+                                html! {
+                                    <>
+                                        <select
+                                            aria-label=t("CPU thinking time", lang)
+                                            onchange=self.link.batch_callback(|change: ChangeData| match change {
+                                                ChangeData::Select(select) => select.value().parse().ok().map(Msg::SetCpuThinkMs),
+                                                _ => None,
+                                            })
+                                        >
+                                            {
+                                                for [0, 500, 1000, 2000].iter().map(|think_ms| html! {
+                                                    <option
+                                                        value=think_ms.to_string()
+                                                        selected=*think_ms == self.settings.cpu_think_ms
+                                                    >
+                                                        { t_fill("Thinks {}s", lang, *think_ms as f64 / 1000.0) }
+                                                    </option>
+                                                })
+                                            }
+                                        </select>
+                                        <input
+                                            class="cpu-seed"
+                                            type="text"
+                                            inputmode="numeric"
+                                            aria-label=t("CPU seed", lang)
+                                            placeholder=t("Random seed", lang)
+                                            value=self.settings.cpu_seed.map(|seed| seed.to_string()).unwrap_or_default()
+                                            onchange=self.link.batch_callback(|change: ChangeData| match change {
+                                                ChangeData::Value(value) if value.trim().is_empty() => Some(Msg::SetCpuSeed(None)),
+                                                ChangeData::Value(value) => value.trim().parse().ok().map(|seed| Msg::SetCpuSeed(Some(seed))),
+                                                _ => None,
+                                            })
+                                        />
+                                        {
+                                            if self.cpu_move.is_some() {
+                                                html! { <span class="cpu-thinking" aria-live="polite">{ t("Thinking…", lang) }</span> }
+                                            } else {
+                                                html! {}
+                                            }
+                                        }
+                                    </>
+                                }
+                            } else {
+                                html! {}
+                            }
+                        }
                         <button
+                            class=classes!(self.restart_armed.is_some().then(|| "armed"))
                             onclick=self.link.callback(|_| Msg::Restart)
                         >
-                            {"Restart"}
+                            { if self.restart_armed.is_some() { t("Confirm restart?", lang) } else { t("Restart", lang) } }
+                        </button>
+                        <select
+                            onchange=self.link.batch_callback(|change: ChangeData| match change {
+                                ChangeData::Select(select) => {
+                                    select.value().parse().ok().map(Msg::StartPreset)
+                                }
+                                _ => None,
+                            })
+                        >
+                            <option value="" selected=true>{ t("New game from preset…", lang) }</option>
+                            {
+                                for self.presets.iter().enumerate().map(|(index, preset)| html! {
+                                    <option value=index.to_string() selected=false>
+                                        { &preset.name }
+                                    </option>
+                                })
+                            }
+                        </select>
+                        <button onclick=self.link.callback(|_| Msg::SavePreset)>
+                            { t("Save current settings as preset", lang) }
+                        </button>
+                        <button onclick=self.link.callback(|_| Msg::RandomPosition)>
+                            { t("Random position", lang) }
                         </button>
+                        <input
+                            class="random-position-plies"
+                            type="number"
+                            min="0"
+                            max=MAX_RANDOM_POSITION_PLIES.to_string()
+                            aria-label=t("Random moves to play", lang)
+                            value=self.random_position_plies.to_string()
+                            onchange=self.link.batch_callback(|change: ChangeData| match change {
+                                ChangeData::Value(value) => value
+                                    .trim()
+                                    .parse()
+                                    .ok()
+                                    .map(|plies: usize| Msg::SetRandomPositionPlies(plies.min(MAX_RANDOM_POSITION_PLIES))),
+                                _ => None,
+                            })
+                        />
+                        <button onclick=self.link.callback(|_| Msg::OpponentView)>
+                            { if self.opponent_view { t("Back to my view", lang) } else { t("See it as opponent", lang) } }
+                        </button>
+                        <button onclick=self.link.callback(|_| Msg::ToggleEditing)>
+                            { if self.editing { t("Done editing", lang) } else { t("Edit position", lang) } }
+                        </button>
+                        {
+                            if self.editing {
+                                html! {
+                                    <Palette
+                                        selection=self.palette_piece
+                                        on_select=self.link.callback(Msg::SelectPalettePiece)
+                                        piece_styles=self.settings.piece_styles
+                                    />
+                                }
+                            } else {
+                                html! {}
+                            }
+                        }
+                        {
+                            if self.settings.blindfold {
+                                html! {
+                                    <button
+                                        disabled=self.reveal_pieces.is_some()
+                                        onclick=self.link.callback(|_| Msg::RevealPieces)
+                                    >
+                                        { t("Reveal", lang) }
+                                    </button>
+                                }
+                            } else {
+                                html! {}
+                            }
+                        }
+                        <div class="replay">
+                            <button
+                                disabled=self.position.move_history().is_empty() && self.future_moves.is_empty()
+                                onclick=self.link.callback(|_| Msg::ToggleReplay)
+                            >
+                                { if self.replay.is_some() { t("Pause", lang) } else { t("Play", lang) } }
+                            </button>
+                            <select
+                                onchange=self.link.batch_callback(|change: ChangeData| match change {
+                                    ChangeData::Select(select) => select.value().parse().ok().map(Msg::SetReplaySpeed),
+                                    _ => None,
+                                })
+                            >
+                                {
+                                    for [2000, 1000, 500, 250].iter().map(|speed_ms| html! {
+                                        <option
+                                            value=speed_ms.to_string()
+                                            selected=*speed_ms == self.replay_speed_ms
+                                        >
+                                            { format!("{}s", *speed_ms as f64 / 1000.0) }
+                                        </option>
+                                    })
+                                }
+                            </select>
+                        </div>
+                        <div class="mate-search">
+                            <button onclick=self.link.callback(|_| Msg::SearchMate)>
+                                { t("Find mate", lang) }
+                            </button>
+                            {
+                                match &self.mate_search {
+                                    Some((sfen, result)) if *sfen == self.position.to_sfen() => html! {
+                                        <span>
+                                            {
+                                                match result {
+                                                    Some(plies) => t_fill("Mate in {}", lang, plies),
+                                                    None => t_fill("No mate found within {} plies", lang, MATE_SEARCH_MAX_PLIES),
+                                                }
+                                            }
+                                        </span>
+                                    },
+                                    _ => html! {},
+                                }
+                            }
+                        </div>
+                        {
+                            let black_points = self.impasse_points(Color::Black);
+                            let white_points = self.impasse_points(Color::White);
+                            if black_points.is_some() || white_points.is_some() {
+                                let describe = |points: Option<u32>| {
+                                    points.map_or(t("king not entered", lang).to_owned(), |points| t_fill("{} points", lang, points))
+                                };
+                                html! {
+                                    <div class="impasse">
+                                        <div>{ "入玉" }</div>
+                                        <div>{ format!("{} {}", side_mark(Color::Black), describe(black_points)) }</div>
+                                        <div>{ format!("{} {}", side_mark(Color::White), describe(white_points)) }</div>
+                                        <select
+                                            onchange=self.link.batch_callback(|change: ChangeData| match change {
+                                                ChangeData::Select(select) => match select.value().as_str() {
+                                                    "24" => Some(Msg::SetImpasseRule(ImpasseRule::TwentyFourPoint)),
+                                                    "27" => Some(Msg::SetImpasseRule(ImpasseRule::TwentySevenPoint)),
+                                                    _ => None,
+                                                },
+                                                _ => None,
+                                            })
+                                        >
+                                            <option value="27" selected=self.settings.impasse_rule == ImpasseRule::TwentySevenPoint>
+                                                { t("27-point rule", lang) }
+                                            </option>
+                                            <option value="24" selected=self.settings.impasse_rule == ImpasseRule::TwentyFourPoint>
+                                                { t("24-point rule", lang) }
+                                            </option>
+                                        </select>
+                                        <button
+                                            disabled=status.is_over()
+                                            onclick=self.link.callback(|_| Msg::DeclareImpasse)
+                                        >
+                                            { t("Declare impasse", lang) }
+                                        </button>
+                                    </div>
+                                }
+                            } else {
+                                html! {}
+                            }
+                        }
+                        <select
+                            aria-label=t("Shareable link contents", lang)
+                            onchange=self.link.batch_callback(|change: ChangeData| match change {
+                                ChangeData::Select(select) => match select.value().as_str() {
+                                    "position" => Some(Msg::SetShareScope(ShareScope::Position)),
+                                    "game" => Some(Msg::SetShareScope(ShareScope::Game)),
+                                    _ => None,
+                                },
+                                _ => None,
+                            })
+                        >
+                            <option value="position" selected=self.settings.share_scope == ShareScope::Position>
+                                { t("Share position only", lang) }
+                            </option>
+                            <option value="game" selected=self.settings.share_scope == ShareScope::Game>
+                                { t("Share full game", lang) }
+                            </option>
+                        </select>
                         <ShareableLink
-                            link_to_share=window().location().href().unwrap_or_default()
+                            id="shareable-link"
+                            lang=lang
+                            label=t("Shareable link", lang)
+                            link_to_share=self.shareable_url()
+                            auto_copy=self.settings.auto_copy_link
                         />
                     </div>
                     <Board
                         pieces=self.pieces()
                         ghost_piece=self.move_intent.move_origin_piece(&self.position)
-                        move_origin_candidates=self.move_intent.move_origin_candidates(&self.position)
-                        move_destination_candidates=self.move_intent.move_destination_candidates(&self.position)
+                        move_origin_candidates=move_origin_candidates
+                        move_destination_candidates=move_destination_candidates
+                        check_escapes=check_escapes
+                        pinned_squares=pinned_squares
+                        opponent_destinations=opponent_destinations
+                        danger_squares=danger_squares
+                        mobility_preview=self.mobility_preview
+                            .as_ref()
+                            .map(|(mobility_preview, _)| mobility_preview.clone())
+                            .unwrap_or_default()
+                        changed_squares=self.changed_squares
+                            .as_ref()
+                            .map(|(changed_squares, _)| changed_squares.clone())
+                            .unwrap_or_default()
+                        focus_color=self.settings.focus_side.then(|| self.position.side_to_move())
                         move_origin=self.move_intent.move_origin_square()
                         move_destination=self.move_intent.move_destination()
                         previous_move_origin=previous_move_origin
@@ -570,97 +3727,946 @@ impl Component for Model {
                             .is_asking_promotion_with_piece(&self.position)
                         is_white_in_check=self.position.in_check(Color::White)
                         is_black_in_check=self.position.in_check(Color::Black)
+                        attack_counts=attack_counts
+                        trail_intensities=self.trail_intensities()
+                        blindfold=self.settings.blindfold && self.reveal_pieces.is_none()
+                        reveal_on_hover=self.settings.blindfold_reveal_on_hover
+                        flipped=self.settings.flipped
+                        piece_styles=self.settings.piece_styles
+                        show_coordinates=self.settings.show_coordinates
+                        show_dots=self.settings.show_dots
+                        show_promotion_zones=self.settings.show_promotion_zones
+                        high_contrast=self.settings.high_contrast
+                        show_square_indices=self.settings.show_debug && self.settings.show_square_indices
+                        border_px=self.settings.square_border_px
+                        inset_px=self.settings.piece_inset_px
+                        highlighted_squares=self.highlighted_squares()
+                        hovered_square=self.hovered_square
                         on_square_click=self.link.callback(|square| Msg::ClickSquare(square))
                         on_choose_promote=self.link.callback(|promote| Msg::ChoosePromote(promote))
+                        on_file_click=self.link.callback(Msg::HighlightFile)
+                        on_rank_click=self.link.callback(Msg::HighlightRank)
+                        on_square_hover=self.link.callback(Msg::HoverSquare)
+                        on_square_right_click=self.editing.then(|| self.link.callback(Msg::ClearSquare))
+                        lang=lang
+                    />
+                    <div
+                        class="history-divider"
+                        onmousedown=self.link.callback(|event: MouseEvent| {
+                            event.prevent_default();
+                            Msg::StartHistoryResize(event.client_x())
+                        })
                     />
                     <div class="right">
-                        <div class="history">
-                            <div class="history-preamble">{ "手合割：平手" }</div>
+                        <div
+                            class="history"
+                            style=format!("width: {}px;", self.history_width)
+                            onscroll=self.link.batch_callback(|event: Event| {
+                                event.target()
+                                    .and_then(|target| target.dyn_into::<Element>().ok())
+                                    .map(|history| Msg::ScrollHistory {
+                                        scroll_top: history.scroll_top(),
+                                        height: history.client_height(),
+                                    })
+                            })
+                        >
+                            <button
+                                class="history-preamble"
+                                aria-label=t("Starting position", lang)
+                                aria-current=if self.position.move_history().is_empty() { "step" } else { "false" }
+                                onclick=self.link.callback(|_| Msg::GotoPly(0))
+                            >
+                                { self.starting_position_label() }
+                            </button>
+                            <div
+                                class="history-spacer"
+                                style=format!("height: {}px;", history_window.start as i32 * HISTORY_ROW_HEIGHT_PX)
+                            />
                             {
-                                for self.position.move_history().iter().enumerate().map(|(turn, move_record)| {
-                                    let previous_move_destination = self.position.move_history().get(turn - 1).map(|previous_move| match previous_move {
-                                        MoveRecord::Normal { to, .. } => to,
-                                        MoveRecord::Drop { to, ..} => to,
-                                    });
-                                    let color = if turn % 2 == 0 {
-                                        Color::Black
-                                    } else {
-                                        Color::White
-                                    };
-                                    let side = match color {
-                                        //Color::Black => "▲",
-                                        Color::Black => "☗",
-                                        //Color::White => "△",
-                                        Color::White => "☖",
-                                    };
-                                    let destination_square = match move_record {
-                                        MoveRecord::Normal { to, .. } => to,
-                                        MoveRecord::Drop { to, ..} => to,
-                                    };
-                                    let destination = if previous_move_destination == Some(destination_square) {
-                                        "同　".to_owned()
-                                    } else {
-                                        let file = coord_index_to_full_width_latin(destination_square.file());
-                                        let rank = coord_index_to_japanese_numeral(destination_square.rank());
-                                        format!("{}{}", file, rank)
-                                    };
-                                    let piece_type = match move_record {
-                                        MoveRecord::Normal { placed, .. } => placed.piece_type,
-                                        MoveRecord::Drop { piece, .. } => piece.piece_type,
-                                    };
-                                    let piece = match piece_type {
-                                        PieceType::King => "玉　",
-                                        PieceType::Rook => "飛　",
-                                        PieceType::Bishop => "角　",
-                                        PieceType::Gold => "金　",
-                                        PieceType::Silver => "銀　",
-                                        PieceType::Knight => "桂　",
-                                        PieceType::Lance => "香　",
-                                        PieceType::Pawn => "歩　",
-                                        PieceType::ProRook => "龍　",
-                                        PieceType::ProBishop => "馬　",
-                                        PieceType::ProSilver => "成銀",
-                                        PieceType::ProKnight => "成桂",
-                                        PieceType::ProLance => "成香",
-                                        PieceType::ProPawn => "と　",
-                                    };
-                                    let movement = match move_record {
-                                        MoveRecord::Normal { from, .. } => {
-                                            // Pseudo KIF notation
-                                            let file = coord_index_to_full_width_latin(from.file());
-                                            let rank = coord_index_to_full_width_latin(from.rank());
-                                            format!("（{}{}）", file, rank)
-                                        },
-                                        MoveRecord::Drop { .. } => "　打".to_owned(),
-                                    };
-                                    let promotion = match move_record {
-                                        MoveRecord::Normal { promoted, .. } => {
-                                            if *promoted {
-                                                "成"
-                                            } else {
-                                                "　"
-                                            }
+                                match history_layout {
+                                    HistoryLayout::Vertical => {
+                                        let columns = self.history_columns();
+                                        html! {
+                                            for history_window.clone().map(|row| html! {
+                                                <div class="history-row vertical" key=row>
+                                                    {
+                                                        for (row * columns..((row + 1) * columns).min(game_records.len())).map(|turn| html! {
+                                                            <div class="history-cell">
+                                                                <span class="move-number">{ self.move_numbering().number(first_move_number, turn) }</span>
+                                                                { self.view_history_move(&game_records, turn, first_mover) }
+                                                            </div>
+                                                        })
+                                                    }
+                                                </div>
+                                            })
                                         }
-                                        MoveRecord::Drop { .. } => "　"
-                                    };
-                                    html! {
-                                        <div class="history-item" key=turn>
-                                            { format!("{}{}{}{}{}\n", side, destination, piece, promotion, movement) }
-                                        </div>
                                     }
-                                })
+                                    HistoryLayout::TwoColumn => html! {
+                                        for history_window.clone().map(|row| {
+                                            let black_slot = row * 2;
+                                            // Numbered as its White move, which is there
+                                            // even when White moves first.
+                                            let white_turn = black_slot + 1 - first_slot;
+                                            html! {
+                                                <div class="history-row two-column" key=row>
+                                                    <span class="move-number">{ self.move_numbering().number(first_move_number, white_turn) }</span>
+                                                    {
+                                                        for (black_slot..black_slot + 2).map(|slot| {
+                                                            match slot.checked_sub(first_slot).filter(|turn| *turn < game_records.len()) {
+                                                                Some(turn) => self.view_history_move(&game_records, turn, first_mover),
+                                                                None => html! { <div class="history-item empty" /> },
+                                                            }
+                                                        })
+                                                    }
+                                                </div>
+                                            }
+                                        })
+                                    },
+                                }
+                            }
+                            <div
+                                class="history-spacer"
+                                style=format!("height: {}px;", (history_row_count - history_window.end) as i32 * HISTORY_ROW_HEIGHT_PX)
+                            />
+                            {
+                                match (status, status.winner()) {
+                                    (GameStatus::Impasse { winner, black_points, white_points }, _) => html! {
+                                        <div class="history-result" key="result">
+                                            {
+                                                match winner {
+                                                    Some(Color::Black) => format!("まで{}手で先手の勝ち", viewed_ply),
+                                                    Some(Color::White) => format!("まで{}手で後手の勝ち", viewed_ply),
+                                                    None => format!("まで{}手で持将棋", viewed_ply),
+                                                }
+                                            }
+                                            <div class="impasse-points">
+                                                {
+                                                    format!(
+                                                        "{} {}点　{} {}点",
+                                                        side_mark(Color::Black),
+                                                        black_points,
+                                                        side_mark(Color::White),
+                                                        white_points
+                                                    )
+                                                }
+                                            </div>
+                                        </div>
+                                    },
+                                    (_, None) => html! {},
+                                    (_, Some(winner)) => html! {
+                                        <div class="history-result" key="result">
+                                            {
+                                                format!(
+                                                    "まで{}手で{}の勝ち",
+                                                    viewed_ply,
+                                                    match winner {
+                                                        Color::Black => "先手",
+                                                        Color::White => "後手",
+                                                    }
+                                                )
+                                            }
+                                        </div>
+                                    },
+                                }
                             }
                             <div class="bottom" ref=self.history_bottom_ref.clone() key="bottom" />
                         </div>
-                        <Hand
-                            color={Color::Black}
-                            pieces={black_hand_pieces}
-                            selection=black_hand_selection
-                            can_select=black_hand_can_select
-                            on_piece_click=self.link.callback(|piece_type|Msg::ClickHeldPiece(piece_type, Color::Black))
+                        {
+                            if self.future_moves.is_empty() {
+                                html! {}
+                            } else {
+                                html! {
+                                    <label class="history-deviation">
+                                        <input
+                                            type="checkbox"
+                                            checked=self.settings.confirm_overwrite
+                                            onclick=self.link.callback(|_| Msg::ToggleConfirmOverwrite)
+                                        />
+                                        {
+                                            t_fill(
+                                                "A different move here overwrites the {} later moves; ask first",
+                                                lang,
+                                                self.future_moves.len()
+                                            )
+                                        }
+                                    </label>
+                                }
+                            }
+                        }
+                        <textarea
+                            class="comment"
+                            style=format!("width: {}px;", self.history_width)
+                            placeholder=t("Comment on this position", lang)
+                            value=self.comments.get(&viewed_ply).cloned().unwrap_or_default()
+                            oninput=self.link.callback(|input: InputData| Msg::SetComment(input.value))
+                        />
+                        { self.view_material_sparkline(&self.material_balances) }
+                        {
+                            if self.settings.show_hand_balance {
+                                html! {
+                                    <HandBalance
+                                        black_pieces=self.hand_pieces(Color::Black)
+                                        white_pieces=self.hand_pieces(Color::White)
+                                        piece_styles=self.settings.piece_styles
+                                        lang=lang
+                                    />
+                                }
+                            } else {
+                                html! {}
+                            }
+                        }
+                        {
+                            if self.settings.show_capture_log {
+                                self.view_capture_log()
+                            } else {
+                                html! {}
+                            }
+                        }
+                        { self.view_hand(top_hand_color.flip()) }
+                    </div>
+                </div>
+                <div class="game-meta">
+                    { self.view_meta_input(MetaField::Black, "Black ", "先手") }
+                    { self.view_meta_input(MetaField::White, "White ", "後手") }
+                    { self.view_meta_input(MetaField::Date, "Date ", "2021/10/05 10:00:00") }
+                    { self.view_meta_input(MetaField::Event, "Event ", "") }
+                </div>
+                <div class="exports">
+                    <ShareableLink
+                        id="lishogi-link"
+                        lang=lang
+                        label=t("Lishogi analysis", lang)
+                        link_to_share=self.lishogi_url()
+                    />
+                    <button onclick=self.link.callback(|_| Msg::Export(ExportFormat::Kif))>
+                        { t(ExportFormat::Kif.label(), lang) }
+                    </button>
+                    <button onclick=self.link.callback(|_| Msg::Export(ExportFormat::Bod))>
+                        { t(ExportFormat::Bod.label(), lang) }
+                    </button>
+                    <select
+                        aria-label=t("Arrows in the SVG diagram", lang)
+                        onchange=self.link.batch_callback(|change: ChangeData| match change {
+                            ChangeData::Select(select) => match select.value().as_str() {
+                                "none" => Some(Msg::SetDiagramArrows(DiagramArrows::None)),
+                                "selected" => Some(Msg::SetDiagramArrows(DiagramArrows::SelectedPiece)),
+                                "all" => Some(Msg::SetDiagramArrows(DiagramArrows::AllPieces)),
+                                _ => None,
+                            },
+                            _ => None,
+                        })
+                    >
+                        <option value="none" selected=self.settings.diagram_arrows == DiagramArrows::None>
+                            { t("No arrows", lang) }
+                        </option>
+                        <option value="selected" selected=self.settings.diagram_arrows == DiagramArrows::SelectedPiece>
+                            { t("Arrows for the selected piece", lang) }
+                        </option>
+                        <option value="all" selected=self.settings.diagram_arrows == DiagramArrows::AllPieces>
+                            { t("Arrows for every piece", lang) }
+                        </option>
+                    </select>
+                    <button onclick=self.link.callback(|_| Msg::Export(ExportFormat::Svg))>
+                        { t(ExportFormat::Svg.label(), lang) }
+                    </button>
+                    <button onclick=self.link.callback(|_| Msg::Export(ExportFormat::Usi))>
+                        { t(ExportFormat::Usi.label(), lang) }
+                    </button>
+                    <button onclick=self.link.callback(|_| Msg::Export(ExportFormat::Json))>
+                        { t(ExportFormat::Json.label(), lang) }
+                    </button>
+                    <button onclick=self.link.callback(|_| Msg::ImportJson)>
+                        { t("Paste JSON", lang) }
+                    </button>
+                    {
+                        match &self.export {
+                            Some(export) => html! {
+                                <ShareableLink
+                                    id=export.format.id()
+                                    lang=lang
+                                    multiline=true
+                                    label=t(export.format.label(), lang)
+                                    link_to_share=export.text.clone()
+                                />
+                            },
+                            None => html! {},
+                        }
+                    }
+                    {
+                        match self.puzzle_url() {
+                            Some(puzzle_url) => html! {
+                                <ShareableLink
+                                    id="puzzle-link"
+                                    lang=lang
+                                    label=t("Puzzle of the position", lang)
+                                    link_to_share=puzzle_url
+                                />
+                            },
+                            None => html! {},
+                        }
+                    }
+                </div>
+                <div class="sfen-bar">
+                    <input
+                        class=sfen_input_classes
+                        type="text"
+                        aria-label="SFEN"
+                        ref=self.sfen_input_ref.clone()
+                        value=self.position.to_sfen()
+                        onkeydown=self.link.batch_callback(|event: KeyboardEvent| {
+                            if event.key() != "Enter" {
+                                return None;
+                            }
+                            event.target()
+                                .and_then(|target| target.dyn_into::<HtmlInputElement>().ok())
+                                .map(|input| Msg::SetSfen(input.value()))
+                        })
+                        onblur=self.link.batch_callback(|event: FocusEvent| {
+                            event.target()
+                                .and_then(|target| target.dyn_into::<HtmlInputElement>().ok())
+                                .map(|input| Msg::SetSfen(input.value()))
+                        })
+                    />
+                    <select
+                        aria-label=t("Side to move", lang)
+                        onchange=self.link.batch_callback(|change: ChangeData| match change {
+                            ChangeData::Select(select) => match select.value().as_str() {
+                                "black" => Some(Msg::SetSideToMove(Color::Black)),
+                                "white" => Some(Msg::SetSideToMove(Color::White)),
+                                _ => None,
+                            },
+                            _ => None,
+                        })
+                    >
+                        <option value="black" selected=self.position.side_to_move() == Color::Black>
+                            { t("Black to move", lang) }
+                        </option>
+                        <option value="white" selected=self.position.side_to_move() == Color::White>
+                            { t("White to move", lang) }
+                        </option>
+                    </select>
+                </div>
+                {
+                    match &self.rejected_sfen {
+                        Some(rejected_sfen) => html! {
+                            <ul class="sfen-diagnoses">
+                                {
+                                    for diagnose_sfen(rejected_sfen).iter().map(|diagnosis| html! {
+                                        <li>{ describe_diagnosis(diagnosis, lang) }</li>
+                                    })
+                                }
+                            </ul>
+                        },
+                        None => html! {},
+                    }
+                }
+                {
+                    if self.refused_url_violations.is_empty() {
+                        html! {}
+                    } else {
+                        html! {
+                            <div class="url-violations">
+                                { t("The position in the link was not loaded because it breaks the rules:", lang) }
+                                <ul class="sfen-diagnoses">
+                                    {
+                                        for self.refused_url_violations.iter().map(|violation| html! {
+                                            <li>{ describe_diagnosis(violation, lang) }</li>
+                                        })
+                                    }
+                                </ul>
+                            </div>
+                        }
+                    }
+                }
+                <div class="reference">
+                    <div class="reference-bar">
+                        <input
+                            type="text"
+                            aria-label=t("Reference SFEN", lang)
+                            placeholder=t("Compare with an SFEN or shared link", lang)
+                            value=self.reference_position
+                                .as_ref()
+                                .map(|position| position.to_sfen())
+                                .unwrap_or_default()
+                            onkeydown=self.link.batch_callback(|event: KeyboardEvent| {
+                                if event.key() != "Enter" {
+                                    return None;
+                                }
+                                event.target()
+                                    .and_then(|target| target.dyn_into::<HtmlInputElement>().ok())
+                                    .map(|input| Msg::SetReferenceSfen(input.value()))
+                            })
                         />
+                        <button
+                            disabled=self.reference_position.is_none()
+                            onclick=self.link.callback(|_| Msg::ClearReference)
+                        >
+                            { t("Clear", lang) }
+                        </button>
                     </div>
+                    {
+                        match &self.reference_position {
+                            Some(reference) => html! {
+                                <Board
+                                    pieces=pieces_of(reference)
+                                    ghost_piece=None
+                                    move_origin_candidates=HashSet::new()
+                                    move_destination_candidates=HashSet::new()
+                                    check_escapes=HashSet::new()
+                                    pinned_squares=HashSet::new()
+                                    opponent_destinations=HashSet::new()
+                                    danger_squares=HashSet::new()
+                                    changed_squares=HashSet::new()
+                                    mobility_preview=HashSet::new()
+                                    focus_color=None
+                                    move_origin=None
+                                    move_destination=None
+                                    previous_move_origin=None
+                                    previous_move_destination=None
+                                    is_asking_promotion_with_piece=None
+                                    is_white_in_check=false
+                                    is_black_in_check=false
+                                    attack_counts=HashMap::new()
+                                    trail_intensities=HashMap::new()
+                                    blindfold=false
+                                    reveal_on_hover=false
+                                    flipped=self.settings.flipped
+                                    piece_styles=self.settings.piece_styles
+                                    show_coordinates=self.settings.show_coordinates
+                                    show_dots=self.settings.show_dots
+                                    show_promotion_zones=self.settings.show_promotion_zones
+                                    high_contrast=self.settings.high_contrast
+                                    show_square_indices=false
+                                    border_px=self.settings.square_border_px
+                                    inset_px=self.settings.piece_inset_px
+                                    highlighted_squares=reference_differences(&self.position, reference)
+                                    hovered_square=None
+                                    on_square_click=Callback::noop()
+                                    on_choose_promote=Callback::noop()
+                                    on_file_click=Callback::noop()
+                                    on_rank_click=Callback::noop()
+                                    on_square_hover=Callback::noop()
+                                    on_square_right_click=None
+                                    lang=lang
+                                />
+                            },
+                            None => html! {},
+                        }
+                    }
+                </div>
+                <div class="settings">
+                    <label>
+                        <input
+                            type="checkbox"
+                            checked=self.settings.show_heatmap
+                            onclick=self.link.callback(|_| Msg::ToggleHeatmap)
+                        />
+                        { t("Show threats", lang) }
+                    </label>
+                    <label>
+                        <input
+                            type="checkbox"
+                            checked=self.settings.show_hints
+                            onclick=self.link.callback(|_| Msg::ToggleHints)
+                        />
+                        { t("Show move hints", lang) }
+                    </label>
+                    <label>
+                        <input
+                            type="checkbox"
+                            checked=self.settings.mute
+                            onclick=self.link.callback(|_| Msg::ToggleMute)
+                        />
+                        { t("Mute", lang) }
+                    </label>
+                    <label>
+                        { t("Byoyomi ", lang) }
+                        <select
+                            onchange=self.link.batch_callback(|change: ChangeData| match change {
+                                ChangeData::Select(select) if select.value() == "off" => Some(Msg::SetByoyomi(None)),
+                                ChangeData::Select(select) => select.value().parse().ok().map(|seconds| Msg::SetByoyomi(Some(seconds))),
+                                _ => None,
+                            })
+                        >
+                            <option value="off" selected=self.settings.byoyomi_seconds.is_none()>
+                                { t("Off", lang) }
+                            </option>
+                            {
+                                for [10, 30, 60].iter().map(|seconds| html! {
+                                    <option
+                                        value=seconds.to_string()
+                                        selected=self.settings.byoyomi_seconds == Some(*seconds)
+                                    >
+                                        { format!("{}s", seconds) }
+                                    </option>
+                                })
+                            }
+                        </select>
+                    </label>
+                    {
+                        if self.settings.byoyomi_seconds.is_some() {
+                            html! {
+                                <label>
+                                    { t("Tick for the last ", lang) }
+                                    <select
+                                        onchange=self.link.batch_callback(|change: ChangeData| match change {
+                                            ChangeData::Select(select) => select.value().parse().ok().map(Msg::SetByoyomiTickSeconds),
+                                            _ => None,
+                                        })
+                                    >
+                                        {
+                                            for [5, 10, 20].iter().map(|seconds| html! {
+                                                <option
+                                                    value=seconds.to_string()
+                                                    selected=*seconds == self.settings.byoyomi_tick_seconds
+                                                >
+                                                    { format!("{}s", seconds) }
+                                                </option>
+                                            })
+                                        }
+                                    </select>
+                                </label>
+                            }
+                        } else {
+                            html! {}
+                        }
+                    }
+                    <label>
+                        <input
+                            type="checkbox"
+                            checked=self.settings.flipped
+                            onclick=self.link.callback(|_| Msg::ToggleFlip)
+                        />
+                        { t("Flip board", lang) }
+                    </label>
+                    <label>
+                        <input
+                            type="checkbox"
+                            checked=self.settings.show_coordinates
+                            onclick=self.link.callback(|_| Msg::ToggleCoordinates)
+                        />
+                        { t("Coordinates", lang) }
+                    </label>
+                    <label>
+                        <input
+                            type="checkbox"
+                            checked=self.settings.show_dots
+                            onclick=self.link.callback(|_| Msg::ToggleDots)
+                        />
+                        { t("Board dots", lang) }
+                    </label>
+                    <label>
+                        <input
+                            type="checkbox"
+                            checked=self.settings.show_promotion_zones
+                            onclick=self.link.callback(|_| Msg::TogglePromotionZones)
+                        />
+                        { t("Promotion zones", lang) }
+                    </label>
+                    <label>
+                        <input
+                            type="checkbox"
+                            checked=self.settings.show_pins
+                            onclick=self.link.callback(|_| Msg::TogglePins)
+                        />
+                        { t("Show pinned pieces", lang) }
+                    </label>
+                    <label>
+                        <input
+                            type="checkbox"
+                            checked=self.settings.show_opponent_moves
+                            onclick=self.link.callback(|_| Msg::ToggleOpponentMoves)
+                        />
+                        { t("Show the opponent's moves", lang) }
+                    </label>
+                    <label>
+                        <input
+                            type="checkbox"
+                            checked=self.settings.show_danger
+                            onclick=self.link.callback(|_| Msg::ToggleDanger)
+                        />
+                        { t("Mark squares a king can't step to", lang) }
+                    </label>
+                    <label>
+                        <input
+                            type="checkbox"
+                            checked=self.settings.preview_mobility
+                            onclick=self.link.callback(|_| Msg::ToggleMobilityPreview)
+                        />
+                        { t("Flash where a moved piece can go next", lang) }
+                    </label>
+                    <label>
+                        <input
+                            type="checkbox"
+                            checked=self.settings.show_drop_counts
+                            onclick=self.link.callback(|_| Msg::ToggleDropCounts)
+                        />
+                        { t("Count legal drops", lang) }
+                    </label>
+                    <label>
+                        <input
+                            type="checkbox"
+                            checked=self.settings.focus_side
+                            onclick=self.link.callback(|_| Msg::ToggleFocusSide)
+                        />
+                        { t("Dim the opponent's pieces", lang) }
+                    </label>
+                    <label>
+                        <input
+                            type="checkbox"
+                            checked=self.settings.follow_game
+                            onclick=self.link.callback(|_| Msg::ToggleFollowGame)
+                        />
+                        { t("Scroll the history to new moves", lang) }
+                    </label>
+                    <label>
+                        <input
+                            type="checkbox"
+                            checked=self.settings.high_contrast
+                            onclick=self.link.callback(|_| Msg::ToggleHighContrast)
+                        />
+                        { t("High contrast highlights", lang) }
+                    </label>
+                    <label>
+                        <input
+                            type="checkbox"
+                            checked=self.settings.try_rule
+                            onclick=self.link.callback(|_| Msg::ToggleTryRule)
+                        />
+                        { t("Try rule", lang) }
+                    </label>
+                    <label>
+                        <input
+                            type="checkbox"
+                            checked=self.settings.explain_uchifuzume
+                            onclick=self.link.callback(|_| Msg::ToggleExplainUchifuzume)
+                        />
+                        { t("Explain refused pawn-drop mates", lang) }
+                    </label>
+                    <label>
+                        <input
+                            type="checkbox"
+                            checked=self.settings.tsume_defence
+                            onclick=self.link.callback(|_| Msg::ToggleTsumeDefence)
+                        />
+                        { t("Tsume practice against the best defence", lang) }
+                    </label>
+                    <label>
+                        <input
+                            type="checkbox"
+                            checked=self.settings.strict_url_positions
+                            onclick=self.link.callback(|_| Msg::ToggleStrictUrlPositions)
+                        />
+                        { t("Only load playable positions from links", lang) }
+                    </label>
+                    <label>
+                        <input
+                            type="checkbox"
+                            checked=self.settings.auto_copy_link
+                            onclick=self.link.callback(|_| Msg::ToggleAutoCopyLink)
+                        />
+                        { t("Copy the link after every move", lang) }
+                    </label>
+                    <label>
+                        <input
+                            type="checkbox"
+                            checked=self.settings.confirm_restart
+                            onclick=self.link.callback(|_| Msg::ToggleConfirmRestart)
+                        />
+                        { t("Confirm restart", lang) }
+                    </label>
+                    <label>
+                        <input
+                            type="checkbox"
+                            checked=self.settings.show_debug
+                            onclick=self.link.callback(|_| Msg::ToggleDebug)
+                        />
+                        { t("Debug", lang) }
+                    </label>
+                    <label>
+                        <input
+                            type="checkbox"
+                            checked=self.settings.show_hand_balance
+                            onclick=self.link.callback(|_| Msg::ToggleHandBalance)
+                        />
+                        { t("Hand balance", lang) }
+                    </label>
+                    <label>
+                        <input
+                            type="checkbox"
+                            checked=self.settings.show_capture_log
+                            onclick=self.link.callback(|_| Msg::ToggleCaptureLog)
+                        />
+                        { t("Capture log", lang) }
+                    </label>
+                    <label>
+                        <input
+                            type="checkbox"
+                            checked=self.settings.blindfold
+                            onclick=self.link.callback(|_| Msg::ToggleBlindfold)
+                        />
+                        { t("Blindfold", lang) }
+                    </label>
+                    <label>
+                        <input
+                            type="checkbox"
+                            checked=self.settings.blindfold_reveal_on_hover
+                            disabled=!self.settings.blindfold
+                            onclick=self.link.callback(|_| Msg::ToggleBlindfoldRevealOnHover)
+                        />
+                        { t("Reveal on hover", lang) }
+                    </label>
+                    <label>
+                        { t("Promotion ", lang) }
+                        <select
+                            onchange=self.link.batch_callback(|change: ChangeData| match change {
+                                ChangeData::Select(select) => match select.value().as_str() {
+                                    "ask" => Some(Msg::SetAutoPromote(AutoPromotePolicy::Ask)),
+                                    "always" => Some(Msg::SetAutoPromote(AutoPromotePolicy::AlwaysWhenOptional)),
+                                    "never" => Some(Msg::SetAutoPromote(AutoPromotePolicy::NeverWhenOptional)),
+                                    _ => None,
+                                },
+                                _ => None,
+                            })
+                        >
+                            <option value="ask" selected=self.settings.auto_promote == AutoPromotePolicy::Ask>
+                                { t("Ask", lang) }
+                            </option>
+                            <option value="always" selected=self.settings.auto_promote == AutoPromotePolicy::AlwaysWhenOptional>
+                                { t("Always promote", lang) }
+                            </option>
+                            <option value="never" selected=self.settings.auto_promote == AutoPromotePolicy::NeverWhenOptional>
+                                { t("Never promote", lang) }
+                            </option>
+                        </select>
+                    </label>
+                    <label>
+                        { t("Destinations ", lang) }
+                        <select
+                            onchange=self.link.batch_callback(|change: ChangeData| match change {
+                                ChangeData::Select(select) => match select.value().as_str() {
+                                    "eager" => Some(Msg::SetCandidateStrategy(CandidateStrategy::Eager)),
+                                    "on-hover" => Some(Msg::SetCandidateStrategy(CandidateStrategy::OnHover)),
+                                    _ => None,
+                                },
+                                _ => None,
+                            })
+                        >
+                            <option value="eager" selected=self.settings.candidate_strategy == CandidateStrategy::Eager>
+                                { t("Show all", lang) }
+                            </option>
+                            <option value="on-hover" selected=self.settings.candidate_strategy == CandidateStrategy::OnHover>
+                                { t("Check hovered square only", lang) }
+                            </option>
+                        </select>
+                    </label>
+                    <label>
+                        { t("Trail ", lang) }
+                        <select
+                            onchange=self.link.batch_callback(|change: ChangeData| match change {
+                                ChangeData::Select(select) => select.value().parse().ok().map(Msg::SetTrailLength),
+                                _ => None,
+                            })
+                        >
+                            {
+                                for [0, 3, 5, 8].iter().map(|trail_length| html! {
+                                    <option
+                                        value=trail_length.to_string()
+                                        selected=*trail_length == self.settings.trail_length
+                                    >
+                                        { if *trail_length == 0 { t("Off", lang).to_owned() } else { t_fill("{} moves", lang, trail_length) } }
+                                    </option>
+                                })
+                            }
+                        </select>
+                    </label>
+                    <label>
+                        { t("History columns ", lang) }
+                        <select
+                            onchange=self.link.batch_callback(|change: ChangeData| match change {
+                                ChangeData::Select(select) => match select.value().as_str() {
+                                    "auto" => Some(Msg::SetHistoryColumns(None)),
+                                    value => value.parse().ok().map(|columns| Msg::SetHistoryColumns(Some(columns))),
+                                },
+                                _ => None,
+                            })
+                        >
+                            <option value="auto" selected=self.settings.history_columns.is_none()>
+                                { t("Fit to width", lang) }
+                            </option>
+                            {
+                                for [1, 2, 3].iter().map(|columns| html! {
+                                    <option
+                                        value=columns.to_string()
+                                        selected=self.settings.history_columns == Some(*columns)
+                                    >
+                                        { columns }
+                                    </option>
+                                })
+                            }
+                        </select>
+                    </label>
+                    <label>
+                        { t("Notation ", lang) }
+                        <select
+                            onchange=self.link.batch_callback(|change: ChangeData| match change {
+                                ChangeData::Select(select) => match select.value().as_str() {
+                                    "japanese" => Some(Msg::SetNotation(Notation::Japanese)),
+                                    "western" => Some(Msg::SetNotation(Notation::Western)),
+                                    _ => None,
+                                },
+                                _ => None,
+                            })
+                        >
+                            <option value="japanese" selected=self.settings.notation == Notation::Japanese>
+                                { t("Japanese", lang) }
+                            </option>
+                            <option value="western" selected=self.settings.notation == Notation::Western>
+                                { t("Western", lang) }
+                            </option>
+                        </select>
+                    </label>
+                    <label>
+                        { t("Move numbers ", lang) }
+                        <select
+                            onchange=self.link.batch_callback(|change: ChangeData| match change {
+                                ChangeData::Select(select) => match select.value().as_str() {
+                                    "notation" => Some(Msg::SetMoveNumbering(None)),
+                                    "ply" => Some(Msg::SetMoveNumbering(Some(MoveNumbering::PerPly))),
+                                    "paired" => Some(Msg::SetMoveNumbering(Some(MoveNumbering::Paired))),
+                                    _ => None,
+                                },
+                                _ => None,
+                            })
+                        >
+                            <option value="notation" selected=self.settings.move_numbering.is_none()>
+                                { t("As the notation counts them", lang) }
+                            </option>
+                            <option value="ply" selected=self.settings.move_numbering == Some(MoveNumbering::PerPly)>
+                                { t("Every ply (KIF)", lang) }
+                            </option>
+                            <option value="paired" selected=self.settings.move_numbering == Some(MoveNumbering::Paired)>
+                                { t("Move pairs (Western)", lang) }
+                            </option>
+                        </select>
+                    </label>
+                    <label>
+                        { t("Drops ", lang) }
+                        <select
+                            disabled=self.settings.notation != Notation::Japanese
+                            onchange=self.link.batch_callback(|change: ChangeData| match change {
+                                ChangeData::Select(select) => match select.value().as_str() {
+                                    "spaced" => Some(Msg::SetDropStyle(DropStyle::Spaced)),
+                                    "compact" => Some(Msg::SetDropStyle(DropStyle::Compact)),
+                                    _ => None,
+                                },
+                                _ => None,
+                            })
+                        >
+                            <option value="spaced" selected=self.settings.drop_style == DropStyle::Spaced>
+                                {"５五角　　　打"}
+                            </option>
+                            <option value="compact" selected=self.settings.drop_style == DropStyle::Compact>
+                                {"５五角打"}
+                            </option>
+                        </select>
+                    </label>
+                    { self.view_piece_style_select(Color::Black) }
+                    { self.view_piece_style_select(Color::White) }
+                    <label>
+                        { t("Language ", lang) }
+                        <select
+                            onchange=self.link.batch_callback(|change: ChangeData| match change {
+                                ChangeData::Select(select) => Lang::from_name(&select.value()).map(Msg::SetLang),
+                                _ => None,
+                            })
+                        >
+                            {
+                                for [Lang::English, Lang::Japanese].iter().map(|&option| html! {
+                                    <option value=option.name() selected=option == lang>
+                                        { option.native_name() }
+                                    </option>
+                                })
+                            }
+                        </select>
+                    </label>
                 </div>
+                {
+                    if self.settings.show_debug {
+                        let legal_moves = self.legal_moves_usi();
+                        html! {
+                            <div class="debug">
+                                <label>
+                                    <input
+                                        type="checkbox"
+                                        checked=self.settings.show_square_indices
+                                        onclick=self.link.callback(|_| Msg::ToggleSquareIndices)
+                                    />
+                                    { t("Square indices", lang) }
+                                </label>
+                                <label>
+                                    { t("Grid lines ", lang) }
+                                    <input
+                                        type="number"
+                                        min="0"
+                                        max="4"
+                                        value=self.settings.square_border_px.to_string()
+                                        onchange=self.link.batch_callback(|change: ChangeData| match change {
+                                            ChangeData::Value(value) => value.trim().parse().ok().map(|border_px: u32| Msg::SetSquareBorder(border_px.min(4))),
+                                            _ => None,
+                                        })
+                                    />
+                                </label>
+                                <label>
+                                    { t("Piece inset ", lang) }
+                                    <input
+                                        type="number"
+                                        min="0"
+                                        max="10"
+                                        value=self.settings.piece_inset_px.to_string()
+                                        onchange=self.link.batch_callback(|change: ChangeData| match change {
+                                            ChangeData::Value(value) => value.trim().parse().ok().map(|inset_px: u32| Msg::SetPieceInset(inset_px.min(10))),
+                                            _ => None,
+                                        })
+                                    />
+                                </label>
+                                <div>{ t_fill("{} legal moves", lang, legal_moves.len()) }</div>
+                                {
+                                    if let MoveIntentBuilder::WithOrigin { .. } = self.move_intent {
+                                        html! {
+                                            <div>
+                                                {
+                                                    t_fill(
+                                                        "{} destination squares checked this render",
+                                                        lang,
+                                                        squares_checked
+                                                    )
+                                                }
+                                            </div>
+                                        }
+                                    } else {
+                                        html! {}
+                                    }
+                                }
+                                <div class="legal-moves">{ legal_moves.join(" ") }</div>
+                                <div>
+                                    <button onclick=self.link.callback(|_| Msg::CheckUndoSymmetry)>
+                                        { t("Check undo symmetry", lang) }
+                                    </button>
+                                    {
+                                        match &self.undo_check {
+                                            Some(Ok(plies)) => t_fill(" {} random moves undone cleanly", lang, plies),
+                                            Some(Err(error)) => format!(" {}", error),
+                                            None => String::new(),
+                                        }
+                                    }
+                                </div>
+                            </div>
+                        }
+                    } else {
+                        html! {}
+                    }
+                }
             </>
         }
     }
@@ -669,3 +4675,235 @@ impl Component for Model {
 fn main() {
     yew::start_app::<Model>();
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn position_from(sfen: &str) -> Position {
+        BBFactory::init();
+        let mut position = Position::new();
+        position.set_sfen(sfen).unwrap();
+        position
+    }
+
+    #[test]
+    fn unchanged_position_gives_equal_pieces() {
+        let mut position = position_from(STARTING_SFEN);
+        let before = pieces_of(&position);
+        assert_eq!(before, pieces_of(&position));
+
+        position.make_move(parse_move("7g7f").unwrap()).unwrap();
+        assert_ne!(before, pieces_of(&position));
+        position.unmake_move().unwrap();
+        assert_eq!(before, pieces_of(&position));
+    }
+
+    #[test]
+    fn first_history_row_has_no_previous_move() {
+        assert_eq!(previous_move_destination(&[], 0), None);
+
+        let mut position = position_from(STARTING_SFEN);
+        position.make_move(parse_move("7g7f").unwrap()).unwrap();
+        let game_records = position.move_history();
+        assert_eq!(previous_move_destination(game_records, 0), None);
+        assert_eq!(
+            previous_move_destination(game_records, 1),
+            parse_square("7f").as_ref()
+        );
+    }
+
+    fn piece_type_at(position: &Position, square: &str) -> Option<PieceType> {
+        (*position.piece_at(parse_square(square).unwrap())).map(|piece| piece.piece_type)
+    }
+
+    #[test]
+    fn redo_keeps_a_forced_promotion() {
+        let mut position = position_from("4k4/P8/9/9/9/9/9/9/4K4 b - 1");
+        position.make_move(parse_move("9b9a+").unwrap()).unwrap();
+        let mut future_moves = Vec::new();
+        assert!(undo_into(&mut position, &mut future_moves));
+        assert_eq!(piece_type_at(&position, "9b"), Some(PieceType::Pawn));
+
+        assert!(redo_from(&mut position, &mut future_moves));
+        assert_eq!(piece_type_at(&position, "9a"), Some(PieceType::ProPawn));
+        assert!(future_moves.is_empty());
+    }
+
+    #[test]
+    fn redo_keeps_a_declined_promotion() {
+        let mut position = position_from("4k4/9/9/4S4/9/9/9/9/4K4 b - 1");
+        position.make_move(parse_move("5d4c").unwrap()).unwrap();
+        let mut future_moves = Vec::new();
+        assert!(undo_into(&mut position, &mut future_moves));
+        assert!(redo_from(&mut position, &mut future_moves));
+        assert_eq!(piece_type_at(&position, "4c"), Some(PieceType::Silver));
+        assert!(matches!(
+            position.move_history().last(),
+            Some(MoveRecord::Normal {
+                promoted: false,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn nothing_to_undo_or_redo() {
+        let mut position = position_from(STARTING_SFEN);
+        let mut future_moves = Vec::new();
+        assert!(!undo_into(&mut position, &mut future_moves));
+        assert!(!redo_from(&mut position, &mut future_moves));
+    }
+
+    const LARGE_HANDS_SFEN: &str = "4k4/9/9/9/9/9/9/9/4K4 b 2R2B4G4S4N4L10P8p 1";
+
+    #[test]
+    fn large_hands_survive_the_link_hash() {
+        let decoded = decode_hash_sfen(&encode(LARGE_HANDS_SFEN)).unwrap();
+        assert_eq!(decoded, LARGE_HANDS_SFEN);
+
+        let position = position_from(&decoded);
+        let count = |piece_type, color| position.hand(Piece { piece_type, color });
+        assert_eq!(count(PieceType::Pawn, Color::Black), 10);
+        assert_eq!(count(PieceType::Pawn, Color::White), 8);
+        assert_eq!(count(PieceType::Lance, Color::Black), 4);
+    }
+
+    #[test]
+    fn mangled_base64_still_decodes() {
+        let encoded = encode(LARGE_HANDS_SFEN);
+        let escaped = encoded
+            .replace('+', "%2B")
+            .replace('/', "%2F")
+            .replace('=', "%3D");
+        assert_eq!(decode_hash_sfen(&escaped).unwrap(), LARGE_HANDS_SFEN);
+        let spaced = encoded.replace('+', " ");
+        assert_eq!(decode_hash_sfen(&spaced).unwrap(), LARGE_HANDS_SFEN);
+    }
+
+    #[test]
+    fn query_loads_moves_from_a_start() {
+        BBFactory::init();
+        let game = parse_query_game("?usi=7g7f%203c3d%208h2b%2B&ply=2").unwrap();
+        assert_eq!(game.start_sfen, STARTING_SFEN);
+        assert_eq!(game.moves.len(), 3);
+        assert!(matches!(
+            game.moves[2],
+            MoveRecord::Normal { promoted: true, .. }
+        ));
+        assert_eq!(game.ply, 2);
+
+        let sfen = "4k4/9/4G4/9/9/9/9/9/4K4 b G 1";
+        let game = parse_query_game(&format!("?sfen={}", percent_encode(sfen))).unwrap();
+        assert_eq!(game.start_sfen, sfen);
+        assert!(game.moves.is_empty());
+    }
+
+    #[test]
+    fn query_refuses_an_unescaped_plus() {
+        BBFactory::init();
+        assert!(parse_query_game("?usi=7g7f%203c3d%208h2b+").is_err());
+        assert!(parse_query_game("?usi=7g7f%203c3d%208h2b%2B").is_ok());
+        assert!(parse_query_game("?utm_source=feed").is_err());
+    }
+
+    #[test]
+    fn hash_gives_the_sfen_and_what_follows_it() {
+        let sfen = "4k4/9/4G4/9/9/9/9/9/4K4 b G 1";
+        let hash = format!("#{}&solution=G*5b", encode(sfen));
+        let (decoded, parts) = split_url_hash(&hash).unwrap();
+        assert_eq!(decoded, sfen);
+        assert_eq!(parts, vec!["solution=G*5b"]);
+        assert!(split_url_hash("").is_err());
+    }
+
+    #[test]
+    fn stored_sfen_loads_the_position_it_was_saved_from() {
+        let mut position = position_from(STARTING_SFEN);
+        position.make_move(parse_move("7g7f").unwrap()).unwrap();
+        let stored = position.to_sfen();
+        assert_eq!(parse_sfen(&stored).unwrap().to_sfen(), stored);
+        assert!(parse_sfen("not a position").is_err());
+    }
+
+    #[test]
+    fn every_ending_is_reported_with_a_winner_or_a_draw() {
+        assert!(game_over_detail(GameStatus::Ongoing).is_none());
+        assert_eq!(
+            game_over_detail(GameStatus::Checkmate {
+                winner: Color::White
+            }),
+            Some(("checkmate", "white"))
+        );
+        let drawn_impasse = GameStatus::Impasse {
+            winner: None,
+            black_points: 24,
+            white_points: 24,
+        };
+        assert_eq!(game_over_detail(drawn_impasse), Some(("impasse", "draw")));
+    }
+
+    #[test]
+    fn broken_rules_are_explained_in_the_chosen_language() {
+        let position = position_from("4k4/9/9/9/9/9/4P4/4P4/4K4 b - 1");
+        let diagnoses = diagnose(&position);
+        assert_eq!(diagnoses.len(), 1);
+        assert_eq!(
+            describe_diagnosis(&diagnoses[0], Lang::English),
+            "Black has 2 pawns on file 5 (nifu)"
+        );
+        assert_eq!(
+            describe_diagnosis(&diagnoses[0], Lang::Japanese),
+            "先手の歩が2枚、5筋にあります（二歩）"
+        );
+    }
+
+    #[test]
+    fn every_piece_name_is_translated() {
+        for piece_type in PieceType::iter() {
+            let name = piece_type_name(piece_type);
+            assert_ne!(t(name, Lang::Japanese), name);
+        }
+    }
+
+    #[test]
+    fn kif_from_another_start_reads_back_the_same_game() {
+        let start_sfen = "4k4/9/4G4/9/9/9/9/4+S4/4K4 w 2G10Pr 1";
+        let mut position = position_from(start_sfen);
+        position.make_move(parse_move("5a4a").unwrap()).unwrap();
+        let game_records = position.move_history();
+        let kif = format!(
+            "{}手数----指手---------消費時間--\n   1 {}\n",
+            kif_starting_position(start_sfen),
+            format_move(&game_records[0], None, "", DropStyle::Spaced)
+        );
+        assert_eq!(
+            kif::parse_start_sfen(&kif).unwrap().as_deref(),
+            Some(start_sfen)
+        );
+        assert!(kif::parse_moves(&kif).unwrap() == vec![parse_move("5a4a").unwrap()]);
+    }
+
+    #[test]
+    fn kif_names_a_handicap_start() {
+        let sfen = "lnsgkgsnl/1r7/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL w - 1";
+        let header = kif_starting_position(sfen);
+        assert_eq!(header, "手合割：角落ち\n");
+        assert_eq!(
+            kif::parse_start_sfen(&header).unwrap().as_deref(),
+            Some(sfen)
+        );
+    }
+
+    #[test]
+    fn kif_writes_other_starting_positions_as_bod() {
+        BBFactory::init();
+        assert_eq!(kif_starting_position(STARTING_SFEN), "手合割：平手\n");
+
+        let header = kif_starting_position("4k4/9/9/9/9/9/9/9/4K4 w G 1");
+        assert!(header.starts_with("後手の持駒：なし\n"));
+        assert!(header.contains("先手の持駒：金"));
+        assert!(header.ends_with("後手番\n"));
+        assert!(!header.contains("手合割"));
+    }
+}
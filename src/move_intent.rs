@@ -0,0 +1,478 @@
+use crate::rules::is_legal;
+
+use shogi::{square::Square, Move, Piece, PieceType, Position};
+use std::collections::HashSet;
+
+#[cfg(test)]
+thread_local! {
+    /// How many sandboxes this thread has made, so tests can count them per action.
+    static SANDBOXES_MADE: std::cell::Cell<usize> = std::cell::Cell::new(0);
+}
+
+/// Which squares are checked for being a legal destination of the selected piece.
+#[derive(Clone, Copy, PartialEq)]
+pub enum CandidateStrategy {
+    /// Check all 81 squares, lighting up every destination at once.
+    Eager,
+    /// Check only the hovered square, for devices too slow to try every move.
+    OnHover,
+}
+
+/// Where the piece being moved comes from.
+#[derive(Clone, Copy)]
+pub enum Origin {
+    SquarePiece(Square),
+    HeldPiece(PieceType),
+}
+
+impl Origin {
+    pub fn square(self) -> Option<Square> {
+        match self {
+            Self::SquarePiece(square) => Some(square),
+            Self::HeldPiece(..) => None,
+        }
+    }
+
+    pub fn hand_piece_type(self) -> Option<PieceType> {
+        match self {
+            Self::SquarePiece(..) => None,
+            Self::HeldPiece(piece_type) => Some(piece_type),
+        }
+    }
+
+    pub fn piece(self, position: &Position) -> Option<Piece> {
+        match self {
+            Origin::SquarePiece(from_square) => *position.piece_at(from_square),
+            Origin::HeldPiece(piece_type) => Some(Piece {
+                piece_type,
+                color: position.side_to_move(),
+            }),
+        }
+    }
+}
+
+/// The move being put together from the player's clicks, checked for legality against
+/// a copy of the position at each step.
+#[derive(Clone, Copy)]
+pub enum MoveIntentBuilder {
+    NoIntent,
+    WithOrigin { from: Origin },
+    WithDestination { from: Origin, to: Square },
+}
+
+impl MoveIntentBuilder {
+    fn create_sandbox(position: &Position) -> Position {
+        #[cfg(test)]
+        SANDBOXES_MADE.with(|made| made.set(made.get() + 1));
+        let mut sandbox_position = Position::new();
+        sandbox_position.set_sfen(&position.to_sfen()).unwrap();
+        sandbox_position
+    }
+
+    pub fn can_move_to(self, square: Square, position: &Position) -> bool {
+        self.can_move_to_in_sandbox(square, &mut Self::create_sandbox(&position))
+    }
+
+    /// Like `can_move_to`, but reuses a sandbox copy of the position, which is left
+    /// unchanged. Copying the position is the slow part, so checking many squares
+    /// should share one sandbox.
+    fn can_move_to_in_sandbox(self, square: Square, sandbox_position: &mut Position) -> bool {
+        match self {
+            MoveIntentBuilder::WithOrigin { from } => {
+                let try_moves = match from {
+                    Origin::SquarePiece(from_square) => {
+                        vec![
+                            Move::Normal {
+                                from: from_square,
+                                to: square,
+                                promote: true,
+                            },
+                            Move::Normal {
+                                from: from_square,
+                                to: square,
+                                promote: false,
+                            },
+                        ]
+                    }
+                    Origin::HeldPiece(piece_type) => {
+                        vec![Move::Drop {
+                            piece_type,
+                            to: square,
+                        }]
+                    }
+                };
+                try_moves
+                    .into_iter()
+                    .any(|try_move| is_legal(sandbox_position, try_move))
+            }
+            _ => panic!(),
+        }
+    }
+
+    /// Gives up the move being put together, whatever stage it has reached.
+    pub fn cancel(&mut self) {
+        *self = Self::NoIntent;
+    }
+
+    /// Whether clicks on the board or the hands may change the move. Only the prompt's
+    /// options or cancelling resolve a pending promotion, so a stray click can't throw
+    /// the move away.
+    pub fn accepts_clicks(self) -> bool {
+        !matches!(self, Self::WithDestination { .. })
+    }
+
+    pub fn must_promote(self, position: &Position) -> bool {
+        match self {
+            MoveIntentBuilder::WithDestination {
+                from: Origin::SquarePiece(from),
+                to,
+            } => {
+                let mut sandbox_position = Self::create_sandbox(&position);
+                sandbox_position
+                    .make_move(Move::Normal {
+                        from,
+                        to,
+                        promote: false,
+                    })
+                    .is_err()
+            }
+            MoveIntentBuilder::WithDestination {
+                from: Origin::HeldPiece(_),
+                to: _,
+            } => false,
+            _ => panic!(),
+        }
+    }
+
+    pub fn cant_promote(self, position: &Position) -> bool {
+        match self {
+            MoveIntentBuilder::WithDestination {
+                from: Origin::SquarePiece(from),
+                to,
+            } => {
+                let mut sandbox_position = Self::create_sandbox(&position);
+                sandbox_position
+                    .make_move(Move::Normal {
+                        from,
+                        to,
+                        promote: true,
+                    })
+                    .is_err()
+            }
+            MoveIntentBuilder::WithDestination {
+                from: Origin::HeldPiece(_),
+                to: _,
+            } => true,
+            _ => panic!(),
+        }
+    }
+
+    pub fn move_origin_candidates(self, position: &Position) -> HashSet<Square> {
+        match self {
+            Self::NoIntent => Square::iter()
+                .filter(|square| {
+                    position
+                        .piece_at(*square)
+                        .filter(|piece| piece.color == position.side_to_move())
+                        .is_some()
+                })
+                .collect(),
+            Self::WithOrigin { .. } => Default::default(),
+            Self::WithDestination { .. } => Default::default(),
+        }
+    }
+
+    pub fn move_destination_candidates(self, position: &Position) -> HashSet<Square> {
+        self.move_destination_candidates_among(position, Square::iter())
+    }
+
+    /// Like `move_destination_candidates`, but only checks the given squares. No sandbox
+    /// is made when there are no squares to check.
+    pub fn move_destination_candidates_among(
+        self,
+        position: &Position,
+        squares: impl IntoIterator<Item = Square>,
+    ) -> HashSet<Square> {
+        match self {
+            Self::NoIntent => Default::default(),
+            Self::WithOrigin { .. } => {
+                let mut squares = squares.into_iter().peekable();
+                if squares.peek().is_none() {
+                    return Default::default();
+                }
+                let mut sandbox_position = Self::create_sandbox(&position);
+                squares
+                    .filter(|square| self.can_move_to_in_sandbox(*square, &mut sandbox_position))
+                    .collect()
+            }
+            Self::WithDestination { .. } => Default::default(),
+        }
+    }
+
+    /// The destinations one render of the board lights up under `strategy`, along with
+    /// how many squares were tried to find them.
+    pub fn rendered_destination_candidates(
+        self,
+        position: &Position,
+        strategy: CandidateStrategy,
+        hovered_square: Option<Square>,
+    ) -> (HashSet<Square>, usize) {
+        let squares: Vec<Square> = match strategy {
+            CandidateStrategy::Eager => Square::iter().collect(),
+            CandidateStrategy::OnHover => hovered_square.into_iter().collect(),
+        };
+        let squares_checked = match self {
+            Self::WithOrigin { .. } => squares.len(),
+            _ => 0,
+        };
+        (
+            self.move_destination_candidates_among(position, squares),
+            squares_checked,
+        )
+    }
+
+    pub fn move_origin_square(self) -> Option<Square> {
+        match self {
+            Self::NoIntent => None,
+            Self::WithOrigin { from } => from.square(),
+            Self::WithDestination { from, .. } => from.square(),
+        }
+    }
+
+    pub fn move_origin_hand_piece_type(self) -> Option<PieceType> {
+        match self {
+            Self::NoIntent => None,
+            Self::WithOrigin { from } => from.hand_piece_type(),
+            Self::WithDestination { from, .. } => from.hand_piece_type(),
+        }
+    }
+
+    pub fn move_origin_piece(self, position: &Position) -> Option<Piece> {
+        match self {
+            Self::NoIntent => None,
+            Self::WithOrigin { from } => from.piece(position),
+            Self::WithDestination { from, .. } => from.piece(position),
+        }
+    }
+
+    /// Whether the chosen piece is no longer there for the side to move, as happens when
+    /// the position changes underneath a half-made move.
+    pub fn is_stale(self, position: &Position) -> bool {
+        let from = match self {
+            Self::NoIntent => return false,
+            Self::WithOrigin { from } | Self::WithDestination { from, .. } => from,
+        };
+        let color = position.side_to_move();
+        match from {
+            Origin::SquarePiece(square) => !position
+                .piece_at(square)
+                .map_or(false, |piece| piece.color == color),
+            Origin::HeldPiece(piece_type) => position.hand(Piece { piece_type, color }) == 0,
+        }
+    }
+
+    pub fn move_destination(self) -> Option<Square> {
+        match self {
+            Self::NoIntent => None,
+            Self::WithOrigin { .. } => None,
+            Self::WithDestination { to, .. } => Some(to),
+        }
+    }
+
+    pub fn is_asking_promotion_with_piece(self, position: &Position) -> Option<Piece> {
+        match self {
+            Self::NoIntent => None,
+            Self::WithOrigin { .. } => None,
+            Self::WithDestination { from, .. } => from.piece(position),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::usi::{parse_move, parse_square};
+    use shogi::bitboard::Factory as BBFactory;
+
+    const STARTING_SFEN: &str = "lnsgkgsnl/1r5b1/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL b - 1";
+
+    fn position_from(sfen: &str) -> Position {
+        BBFactory::init();
+        let mut position = Position::new();
+        position.set_sfen(sfen).unwrap();
+        position
+    }
+
+    fn square(name: &str) -> Square {
+        parse_square(name).unwrap()
+    }
+
+    fn squares(names: &[&str]) -> HashSet<Square> {
+        names.iter().map(|name| square(name)).collect()
+    }
+
+    fn from_square(name: &str) -> MoveIntentBuilder {
+        MoveIntentBuilder::WithOrigin {
+            from: Origin::SquarePiece(square(name)),
+        }
+    }
+
+    #[test]
+    fn pawn_steps_forward() {
+        let position = position_from(STARTING_SFEN);
+        assert_eq!(
+            from_square("7g").move_destination_candidates(&position),
+            squares(&["7f"])
+        );
+    }
+
+    #[test]
+    fn blocked_knight_has_nowhere_to_go() {
+        let position = position_from(STARTING_SFEN);
+        assert!(from_square("8i")
+            .move_destination_candidates(&position)
+            .is_empty());
+    }
+
+    #[test]
+    fn rook_slides_until_blocked() {
+        let position = position_from(STARTING_SFEN);
+        assert_eq!(
+            from_square("2h").move_destination_candidates(&position),
+            squares(&["1h", "3h", "4h", "5h", "6h", "7h"])
+        );
+    }
+
+    #[test]
+    fn pawn_drops_avoid_its_file_and_the_last_rank() {
+        let position = position_from("4k4/9/9/9/9/9/4P4/9/4K4 b P 1");
+        let candidates = MoveIntentBuilder::WithOrigin {
+            from: Origin::HeldPiece(PieceType::Pawn),
+        }
+        .move_destination_candidates(&position);
+        assert!(candidates.contains(&square("4e")));
+        assert!(!candidates.contains(&square("5e")));
+        assert!(!candidates.contains(&square("4a")));
+        assert!(!candidates.contains(&square("5i")));
+    }
+
+    #[test]
+    fn pending_promotion_ignores_clicks() {
+        let position = position_from("4k4/9/9/4S4/9/9/9/9/4K4 b - 1");
+        let intent = from_square("5d");
+        assert!(intent.accepts_clicks());
+        assert!(intent.can_move_to(square("4c"), &position));
+
+        let asking = MoveIntentBuilder::WithDestination {
+            from: Origin::SquarePiece(square("5d")),
+            to: square("4c"),
+        };
+        assert!(!asking.accepts_clicks());
+        assert!(asking.is_asking_promotion_with_piece(&position).is_some());
+        assert_eq!(asking.move_destination(), Some(square("4c")));
+        assert_eq!(asking.move_origin_square(), Some(square("5d")));
+    }
+
+    fn sandboxes_made_by(action: impl FnOnce()) -> usize {
+        let before = SANDBOXES_MADE.with(|made| made.get());
+        action();
+        SANDBOXES_MADE.with(|made| made.get()) - before
+    }
+
+    #[test]
+    fn one_render_makes_one_sandbox() {
+        let position = position_from(STARTING_SFEN);
+        let intent = from_square("7g");
+        let mut rendered = Default::default();
+        let made = sandboxes_made_by(|| {
+            rendered =
+                intent.rendered_destination_candidates(&position, CandidateStrategy::Eager, None);
+        });
+        assert_eq!(made, 1);
+        assert_eq!(rendered, (squares(&["7f"]), 81));
+    }
+
+    #[test]
+    fn hovering_checks_only_the_hovered_square() {
+        let position = position_from(STARTING_SFEN);
+        let intent = from_square("7g");
+        let mut rendered = Default::default();
+        let made = sandboxes_made_by(|| {
+            rendered = intent.rendered_destination_candidates(
+                &position,
+                CandidateStrategy::OnHover,
+                Some(square("7f")),
+            );
+        });
+        assert_eq!(made, 1);
+        assert_eq!(rendered, (squares(&["7f"]), 1));
+
+        // Moving off the board, or having nothing selected, tries no moves at all.
+        let made = sandboxes_made_by(|| {
+            intent.rendered_destination_candidates(&position, CandidateStrategy::OnHover, None);
+            MoveIntentBuilder::NoIntent.rendered_destination_candidates(
+                &position,
+                CandidateStrategy::Eager,
+                None,
+            );
+        });
+        assert_eq!(made, 0);
+    }
+
+    #[test]
+    fn undo_leaves_a_chosen_piece_stale() {
+        let mut position = position_from(STARTING_SFEN);
+        position.make_move(parse_move("7g7f").unwrap()).unwrap();
+        let intent = from_square("3c");
+        assert!(!intent.is_stale(&position));
+        position.unmake_move().unwrap();
+        // The piece now belongs to the side not to move, so clicking 3d mustn't move it.
+        assert!(intent.is_stale(&position));
+        assert!(!MoveIntentBuilder::NoIntent.is_stale(&position));
+    }
+
+    #[test]
+    fn undo_leaves_a_chosen_held_piece_stale() {
+        let mut position = position_from("4k4/9/9/9/9/4p4/4P4/9/4K4 b - 1");
+        position.make_move(parse_move("5g5f").unwrap()).unwrap();
+        position.make_move(parse_move("5a4a").unwrap()).unwrap();
+        let intent = MoveIntentBuilder::WithOrigin {
+            from: Origin::HeldPiece(PieceType::Pawn),
+        };
+        assert!(!intent.is_stale(&position));
+        position.unmake_move().unwrap();
+        position.unmake_move().unwrap();
+        assert!(intent.is_stale(&position));
+    }
+
+    #[test]
+    fn cancelling_starts_over_from_any_stage() {
+        let position = position_from("4k4/9/9/4S4/9/9/9/9/4K4 b - 1");
+        let stages = [
+            MoveIntentBuilder::NoIntent,
+            from_square("5d"),
+            MoveIntentBuilder::WithOrigin {
+                from: Origin::HeldPiece(PieceType::Pawn),
+            },
+            MoveIntentBuilder::WithDestination {
+                from: Origin::SquarePiece(square("5d")),
+                to: square("4c"),
+            },
+        ];
+        for mut intent in stages {
+            intent.cancel();
+            assert!(matches!(intent, MoveIntentBuilder::NoIntent));
+            assert!(intent.accepts_clicks());
+            assert_eq!(intent.move_destination(), None);
+            assert!(intent.move_destination_candidates(&position).is_empty());
+        }
+    }
+
+    #[test]
+    fn no_destinations_without_an_origin() {
+        let position = position_from(STARTING_SFEN);
+        assert!(MoveIntentBuilder::NoIntent
+            .move_destination_candidates(&position)
+            .is_empty());
+    }
+}
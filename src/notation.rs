@@ -0,0 +1,581 @@
+use crate::settings::DropStyle;
+use shogi::{square::Square, Color, MoveRecord, Piece, PieceType, Position};
+use shogi_board::kanji::{bod_piece_kanji, piece_kanji, side_mark};
+use shogi_board::rules::can_move_between;
+use shogi_board::usi::square_to_usi;
+
+/// Pads kanji out to the fixed-width columns of a KIF move.
+pub const FULL_WIDTH_SPACE: &str = "　";
+pub const SAME_SQUARE: &str = "同";
+pub const PROMOTE: &str = "成";
+pub const DROP: &str = "打";
+pub const RIGHT: &str = "右";
+pub const LEFT: &str = "左";
+pub const STRAIGHT: &str = "直";
+pub const UPWARD: &str = "上";
+pub const DOWNWARD: &str = "引";
+pub const SIDEWAYS: &str = "寄";
+
+pub fn coord_index_to_full_width_latin(index: u8) -> &'static str {
+    match index {
+        0 => "１",
+        1 => "２",
+        2 => "３",
+        3 => "４",
+        4 => "５",
+        5 => "６",
+        6 => "７",
+        7 => "８",
+        8 => "９",
+        _ => unreachable!(),
+    }
+}
+
+pub fn coord_index_to_japanese_numeral(index: u8) -> &'static str {
+    match index {
+        0 => "一",
+        1 => "二",
+        2 => "三",
+        3 => "四",
+        4 => "五",
+        5 => "六",
+        6 => "七",
+        7 => "八",
+        8 => "九",
+        _ => unreachable!(),
+    }
+}
+
+/// Pads `text` with full-width spaces until it is `width` characters wide.
+fn pad(text: &str, width: usize) -> String {
+    let padding = width.saturating_sub(text.chars().count());
+    format!("{}{}", text, FULL_WIDTH_SPACE.repeat(padding))
+}
+
+/// Formats a move in KIF style. `disambiguation` goes after the piece, as worked out by
+/// `relative_movement`, and can be left empty since the origin is written anyway.
+pub fn format_move(
+    move_record: &MoveRecord,
+    previous_move_destination: Option<&Square>,
+    disambiguation: &str,
+    drop_style: DropStyle,
+) -> String {
+    let destination_square = match move_record {
+        MoveRecord::Normal { to, .. } => to,
+        MoveRecord::Drop { to, .. } => to,
+    };
+    let destination = if previous_move_destination == Some(destination_square) {
+        pad(SAME_SQUARE, 2)
+    } else {
+        let file = coord_index_to_full_width_latin(destination_square.file());
+        let rank = coord_index_to_japanese_numeral(destination_square.rank());
+        format!("{}{}", file, rank)
+    };
+    match move_record {
+        MoveRecord::Normal {
+            from,
+            placed,
+            promoted,
+            ..
+        } => {
+            // KIF writes the origin in ASCII digits, e.g. "(77)".
+            let file = from.file() + 1;
+            let rank = from.rank() + 1;
+            // A promoting move is written with the piece it was before, followed by 成.
+            let moved = if *promoted {
+                placed.piece_type.unpromote().unwrap_or(placed.piece_type)
+            } else {
+                placed.piece_type
+            };
+            let promotion = if *promoted { PROMOTE } else { FULL_WIDTH_SPACE };
+            format!(
+                "{}{}{}{}({}{})",
+                destination,
+                pad(piece_kanji(moved), 2),
+                disambiguation,
+                promotion,
+                file,
+                rank
+            )
+        }
+        MoveRecord::Drop { piece, .. } => match drop_style {
+            DropStyle::Spaced => format!(
+                "{}{}{}{}{}",
+                destination,
+                pad(piece_kanji(piece.piece_type), 2),
+                FULL_WIDTH_SPACE,
+                FULL_WIDTH_SPACE,
+                DROP
+            ),
+            DropStyle::Compact => {
+                format!("{}{}{}", destination, piece_kanji(piece.piece_type), DROP)
+            }
+        },
+    }
+}
+
+pub fn western_piece_letter(piece_type: PieceType) -> &'static str {
+    match piece_type {
+        PieceType::King => "K",
+        PieceType::Rook => "R",
+        PieceType::Bishop => "B",
+        PieceType::Gold => "G",
+        PieceType::Silver => "S",
+        PieceType::Knight => "N",
+        PieceType::Lance => "L",
+        PieceType::Pawn => "P",
+        PieceType::ProRook => "+R",
+        PieceType::ProBishop => "+B",
+        PieceType::ProSilver => "+S",
+        PieceType::ProKnight => "+N",
+        PieceType::ProLance => "+L",
+        PieceType::ProPawn => "+P",
+    }
+}
+
+fn is_in_promotion_zone(square: Square, color: Color) -> bool {
+    match color {
+        Color::Black => square.rank() < 3,
+        Color::White => square.rank() > 5,
+    }
+}
+
+/// Formats a move in Western (Hodges) notation, e.g. "P-7f", "Bx2b+" or "S*5e".
+pub fn format_move_western(move_record: &MoveRecord) -> String {
+    match move_record {
+        MoveRecord::Normal {
+            from,
+            to,
+            placed,
+            captured,
+            promoted,
+        } => {
+            let moved = if *promoted {
+                placed.piece_type.unpromote().unwrap_or(placed.piece_type)
+            } else {
+                placed.piece_type
+            };
+            let separator = if captured.is_some() { "x" } else { "-" };
+            let promotion = if *promoted {
+                "+"
+            } else if moved.promote().is_some()
+                && (is_in_promotion_zone(*from, placed.color)
+                    || is_in_promotion_zone(*to, placed.color))
+            {
+                "="
+            } else {
+                ""
+            };
+            format!(
+                "{}{}{}{}",
+                western_piece_letter(moved),
+                separator,
+                square_to_usi(*to),
+                promotion
+            )
+        }
+        MoveRecord::Drop { to, piece } => format!(
+            "{}*{}",
+            western_piece_letter(piece.piece_type),
+            square_to_usi(*to)
+        ),
+    }
+}
+
+pub fn piece_type_name(piece_type: PieceType) -> &'static str {
+    match piece_type {
+        PieceType::King => "king",
+        PieceType::Rook => "rook",
+        PieceType::Bishop => "bishop",
+        PieceType::Gold => "gold",
+        PieceType::Silver => "silver",
+        PieceType::Knight => "knight",
+        PieceType::Lance => "lance",
+        PieceType::Pawn => "pawn",
+        PieceType::ProRook => "promoted rook",
+        PieceType::ProBishop => "promoted bishop",
+        PieceType::ProSilver => "promoted silver",
+        PieceType::ProKnight => "promoted knight",
+        PieceType::ProLance => "promoted lance",
+        PieceType::ProPawn => "promoted pawn",
+    }
+}
+
+/// Reads a move out for assistive technology, e.g. "Black silver 7g" or "White pawn drop 5e".
+pub fn spoken_move(move_record: &MoveRecord) -> String {
+    match move_record {
+        MoveRecord::Normal {
+            to,
+            placed,
+            promoted,
+            ..
+        } => {
+            let moved = if *promoted {
+                placed.piece_type.unpromote().unwrap_or(placed.piece_type)
+            } else {
+                placed.piece_type
+            };
+            format!(
+                "{} {} {}{}",
+                color_name(placed.color),
+                piece_type_name(moved),
+                square_to_usi(*to),
+                if *promoted { ", promotes" } else { "" }
+            )
+        }
+        MoveRecord::Drop { to, piece } => format!(
+            "{} {} drop {}",
+            color_name(piece.color),
+            piece_type_name(piece.piece_type),
+            square_to_usi(*to)
+        ),
+    }
+}
+
+fn color_name(color: Color) -> &'static str {
+    match color {
+        Color::Black => "Black",
+        Color::White => "White",
+    }
+}
+
+/// The file and rank of `square` as seen by `color`: lower files are further to its
+/// right and lower ranks further forward.
+fn relative_coordinates(square: Square, color: Color) -> (u8, u8) {
+    match color {
+        Color::Black => (square.file(), square.rank()),
+        Color::White => (8 - square.file(), 8 - square.rank()),
+    }
+}
+
+fn vertical_movement(from: Square, to: Square, color: Color) -> &'static str {
+    let (_, from_rank) = relative_coordinates(from, color);
+    let (_, to_rank) = relative_coordinates(to, color);
+    match to_rank.cmp(&from_rank) {
+        std::cmp::Ordering::Less => UPWARD,
+        std::cmp::Ordering::Greater => DOWNWARD,
+        std::cmp::Ordering::Equal => SIDEWAYS,
+    }
+}
+
+/// 右, 左 or 直 if that singles `from` out among `origins`, which include it.
+fn horizontal_movement(
+    from: Square,
+    to: Square,
+    color: Color,
+    piece_type: PieceType,
+    origins: &[Square],
+) -> Option<&'static str> {
+    let is_ranging = matches!(
+        piece_type,
+        PieceType::Rook | PieceType::Bishop | PieceType::ProRook | PieceType::ProBishop
+    );
+    // Only one origin can be right behind the destination for the stepping pieces.
+    if !is_ranging && from.file() == to.file() && vertical_movement(from, to, color) == UPWARD {
+        return Some(STRAIGHT);
+    }
+    let file = |square: Square| relative_coordinates(square, color).0;
+    let others = origins.iter().filter(|origin| **origin != from);
+    if others.clone().all(|origin| file(*origin) > file(from)) {
+        Some(RIGHT)
+    } else if others.clone().all(|origin| file(*origin) < file(from)) {
+        Some(LEFT)
+    } else {
+        None
+    }
+}
+
+/// Which of several identical pieces made the move, e.g. "右" or "左上", or nothing if
+/// no other could have reached the destination. `position` must be the position the
+/// move was played from; it is left unchanged.
+pub fn relative_movement(position: &mut Position, move_record: &MoveRecord) -> String {
+    let (from, to, placed, promoted) = match move_record {
+        MoveRecord::Normal {
+            from,
+            to,
+            placed,
+            promoted,
+            ..
+        } => (*from, *to, *placed, *promoted),
+        // Drops are always marked with 打.
+        MoveRecord::Drop { .. } => return String::new(),
+    };
+    let moved = Piece {
+        piece_type: if promoted {
+            placed.piece_type.unpromote().unwrap_or(placed.piece_type)
+        } else {
+            placed.piece_type
+        },
+        color: placed.color,
+    };
+    let origins: Vec<Square> = Square::iter()
+        .filter(|square| *position.piece_at(*square) == Some(moved))
+        .filter(|square| *square == from || can_move_between(position, *square, to))
+        .collect();
+    if origins.len() < 2 {
+        return String::new();
+    }
+    let color = moved.color;
+    let vertical = vertical_movement(from, to, color);
+    let same_vertical: Vec<Square> = origins
+        .iter()
+        .copied()
+        .filter(|origin| vertical_movement(*origin, to, color) == vertical)
+        .collect();
+    if same_vertical.len() == 1 {
+        return vertical.to_owned();
+    }
+    if let Some(horizontal) = horizontal_movement(from, to, color, moved.piece_type, &origins) {
+        return horizontal.to_owned();
+    }
+    match horizontal_movement(from, to, color, moved.piece_type, &same_vertical) {
+        Some(horizontal) => format!("{}{}", horizontal, vertical),
+        None => String::new(),
+    }
+}
+
+/// Writes a hand count as BOD does: nothing for one, otherwise a kanji numeral.
+fn bod_count(count: u8) -> String {
+    match count {
+        0 | 1 => String::new(),
+        10 => "十".to_owned(),
+        2..=9 => coord_index_to_japanese_numeral(count - 1).to_owned(),
+        _ => format!("十{}", coord_index_to_japanese_numeral(count - 11)),
+    }
+}
+
+fn bod_hand(position: &Position, color: Color) -> String {
+    let pieces: Vec<String> = [
+        PieceType::Rook,
+        PieceType::Bishop,
+        PieceType::Gold,
+        PieceType::Silver,
+        PieceType::Knight,
+        PieceType::Lance,
+        PieceType::Pawn,
+    ]
+    .iter()
+    .filter_map(|piece_type| {
+        let count = position.hand(Piece {
+            piece_type: *piece_type,
+            color,
+        });
+        (count > 0).then(|| format!("{}{}", piece_kanji(*piece_type), bod_count(count)))
+    })
+    .collect();
+    let owner = match color {
+        Color::Black => "先手",
+        Color::White => "後手",
+    };
+    if pieces.is_empty() {
+        format!("{}の持駒：なし", owner)
+    } else {
+        format!("{}の持駒：{}　", owner, pieces.join("　"))
+    }
+}
+
+/// Draws the position as a BOD diagram, the text board used on Japanese shogi forums.
+pub fn position_to_bod(position: &Position) -> String {
+    let mut bod = format!("{}\n", bod_hand(position, Color::White));
+    bod.push_str("  ９ ８ ７ ６ ５ ４ ３ ２ １\n");
+    bod.push_str("+---------------------------+\n");
+    for rank in 0..9 {
+        bod.push('|');
+        for file in (0..9).rev() {
+            let piece = Square::new(file, rank).and_then(|square| *position.piece_at(square));
+            match piece {
+                Some(piece) => {
+                    bod.push(if piece.color == Color::White {
+                        'v'
+                    } else {
+                        ' '
+                    });
+                    bod.push_str(bod_piece_kanji(piece.piece_type));
+                }
+                None => bod.push_str(" ・"),
+            }
+        }
+        bod.push('|');
+        bod.push_str(coord_index_to_japanese_numeral(rank));
+        bod.push('\n');
+    }
+    bod.push_str("+---------------------------+\n");
+    bod.push_str(&format!("{}\n", bod_hand(position, Color::Black)));
+    if position.side_to_move() == Color::White {
+        bod.push_str("後手番\n");
+    }
+    bod
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use shogi::bitboard::Factory as BBFactory;
+    use shogi_board::usi::{parse_move, parse_square};
+
+    const STARTING_SFEN: &str = "lnsgkgsnl/1r5b1/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL b - 1";
+
+    fn position_from(sfen: &str) -> Position {
+        BBFactory::init();
+        let mut position = Position::new();
+        position.set_sfen(sfen).unwrap();
+        position
+    }
+
+    /// The records of `moves`, written in USI, played from `sfen`.
+    fn play(sfen: &str, moves: &[&str]) -> Vec<MoveRecord> {
+        let mut position = position_from(sfen);
+        for next_move in moves {
+            position.make_move(parse_move(next_move).unwrap()).unwrap();
+        }
+        position.move_history().to_vec()
+    }
+
+    #[test]
+    fn formats_a_plain_move() {
+        let records = play(STARTING_SFEN, &["7g7f"]);
+        assert_eq!(
+            format_move(&records[0], None, "", DropStyle::Spaced),
+            "７六歩　　(77)"
+        );
+    }
+
+    #[test]
+    fn writes_origins_as_kif_digits() {
+        let records = play(STARTING_SFEN, &["1i1h", "9a9b", "5i4h"]);
+        let origins: Vec<String> = records
+            .iter()
+            .map(|record| format_move(record, None, "", DropStyle::Spaced))
+            .collect();
+        assert_eq!(
+            origins,
+            ["１八香　　(19)", "９二香　　(91)", "４八玉　　(59)"]
+        );
+    }
+
+    #[test]
+    fn formats_a_promotion_and_a_recapture() {
+        let records = play(STARTING_SFEN, &["7g7f", "3c3d", "8h2b+", "3a2b"]);
+        assert_eq!(
+            format_move(&records[2], None, "", DropStyle::Spaced),
+            "２二角　成(88)"
+        );
+        assert_eq!(
+            format_move(
+                &records[3],
+                parse_square("2b").as_ref(),
+                "",
+                DropStyle::Spaced
+            ),
+            "同　銀　　(31)"
+        );
+    }
+
+    #[test]
+    fn formats_drops_in_either_style() {
+        let records = play("4k4/9/9/9/9/9/9/9/4K4 b P 1", &["P*5e"]);
+        assert_eq!(
+            format_move(&records[0], None, "", DropStyle::Spaced),
+            "５五歩　　　打"
+        );
+        assert_eq!(
+            format_move(&records[0], None, "", DropStyle::Compact),
+            "５五歩打"
+        );
+    }
+
+    /// How `relative_movement` tells apart `next_move`, written in USI, played from `sfen`.
+    fn movement(sfen: &str, next_move: &str) -> String {
+        let mut position = position_from(sfen);
+        position.make_move(parse_move(next_move).unwrap()).unwrap();
+        let move_record = position.move_history().last().cloned().unwrap();
+        position.unmake_move().unwrap();
+        relative_movement(&mut position, &move_record)
+    }
+
+    #[test]
+    fn marks_right_and_left() {
+        let sfen = "4k4/9/9/9/9/9/9/9/3GKG3 b - 1";
+        assert_eq!(movement(sfen, "4i5h"), "右");
+        assert_eq!(movement(sfen, "6i5h"), "左");
+    }
+
+    #[test]
+    fn marks_straight_up() {
+        let sfen = "4k4/9/9/9/9/9/9/9/K3GG3 b - 1";
+        assert_eq!(movement(sfen, "5i5h"), "直");
+        assert_eq!(movement(sfen, "4i5h"), "右");
+    }
+
+    #[test]
+    fn marks_upward_and_downward() {
+        let sfen = "4k4/9/9/9/9/5S3/9/3S5/4K4 b - 1";
+        assert_eq!(movement(sfen, "6h5g"), "上");
+        assert_eq!(movement(sfen, "4f5g"), "引");
+    }
+
+    #[test]
+    fn marks_sideways() {
+        let sfen = "4k4/9/9/9/9/9/5G3/4G4/K8 b - 1";
+        assert_eq!(movement(sfen, "4g5g"), "寄");
+        assert_eq!(movement(sfen, "5h5g"), "上");
+    }
+
+    #[test]
+    fn leaves_a_lone_piece_unmarked() {
+        assert_eq!(movement(STARTING_SFEN, "7g7f"), "");
+    }
+
+    #[test]
+    fn draws_the_starting_position_as_bod() {
+        assert_eq!(
+            position_to_bod(&position_from(STARTING_SFEN)),
+            "後手の持駒：なし
+  ９ ８ ７ ６ ５ ４ ３ ２ １
++---------------------------+
+|v香v桂v銀v金v玉v金v銀v桂v香|一
+| ・v飛 ・ ・ ・ ・ ・v角 ・|二
+|v歩v歩v歩v歩v歩v歩v歩v歩v歩|三
+| ・ ・ ・ ・ ・ ・ ・ ・ ・|四
+| ・ ・ ・ ・ ・ ・ ・ ・ ・|五
+| ・ ・ ・ ・ ・ ・ ・ ・ ・|六
+| 歩 歩 歩 歩 歩 歩 歩 歩 歩|七
+| ・ 角 ・ ・ ・ ・ ・ 飛 ・|八
+| 香 桂 銀 金 玉 金 銀 桂 香|九
++---------------------------+
+先手の持駒：なし
+"
+        );
+    }
+
+    #[test]
+    fn draws_hands_and_promoted_pieces_as_bod() {
+        assert_eq!(
+            position_to_bod(&position_from("4k4/9/4G4/9/9/9/9/4+S4/4K4 w 2G10Pr 1")),
+            "後手の持駒：飛　
+  ９ ８ ７ ６ ５ ４ ３ ２ １
++---------------------------+
+| ・ ・ ・ ・v玉 ・ ・ ・ ・|一
+| ・ ・ ・ ・ ・ ・ ・ ・ ・|二
+| ・ ・ ・ ・ 金 ・ ・ ・ ・|三
+| ・ ・ ・ ・ ・ ・ ・ ・ ・|四
+| ・ ・ ・ ・ ・ ・ ・ ・ ・|五
+| ・ ・ ・ ・ ・ ・ ・ ・ ・|六
+| ・ ・ ・ ・ ・ ・ ・ ・ ・|七
+| ・ ・ ・ ・ 全 ・ ・ ・ ・|八
+| ・ ・ ・ ・ 玉 ・ ・ ・ ・|九
++---------------------------+
+先手の持駒：金二　歩十　
+後手番
+"
+        );
+    }
+
+    #[test]
+    fn counts_hands_in_kanji() {
+        assert_eq!(bod_count(1), "");
+        assert_eq!(bod_count(9), "九");
+        assert_eq!(bod_count(10), "十");
+        assert_eq!(bod_count(18), "十八");
+    }
+}
@@ -0,0 +1,66 @@
+use crate::piece::PieceView;
+use crate::settings::PieceStyles;
+
+use shogi::{Color, Piece, PieceType};
+use yew::prelude::*;
+
+/// Every piece that can be placed on the board in edit mode, without the limits of
+/// a real set of pieces.
+pub struct Palette {
+    props: PaletteProps,
+}
+
+#[derive(Properties, Clone, PartialEq)]
+pub struct PaletteProps {
+    pub selection: Option<Piece>,
+    pub on_select: Callback<Piece>,
+    pub piece_styles: PieceStyles,
+}
+
+impl Component for Palette {
+    type Message = ();
+    type Properties = PaletteProps;
+
+    fn create(props: Self::Properties, _link: ComponentLink<Self>) -> Self {
+        Self { props }
+    }
+
+    fn update(&mut self, _msg: Self::Message) -> ShouldRender {
+        false
+    }
+
+    fn change(&mut self, props: Self::Properties) -> ShouldRender {
+        let changed = self.props != props;
+        self.props = props;
+        changed
+    }
+
+    fn view(&self) -> Html {
+        html! {
+            <div class="palette">
+                {
+                    for [Color::Black, Color::White].iter().flat_map(|color| {
+                        PieceType::iter().map(move |piece_type| Piece {
+                            piece_type,
+                            color: *color,
+                        })
+                    })
+                    .map(|piece| {
+                        let mut palette_piece_classes = classes!("palette-piece");
+                        if self.props.selection == Some(piece) {
+                            palette_piece_classes.push("selected");
+                        }
+                        html! {
+                            <div
+                                class=palette_piece_classes
+                                onclick=self.props.on_select.reform(move |_| piece)
+                            >
+                                <PieceView piece=Some(piece) piece_styles=self.props.piece_styles />
+                            </div>
+                        }
+                    })
+                }
+            </div>
+        }
+    }
+}
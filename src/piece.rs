@@ -1,3 +1,6 @@
+use crate::notation::western_piece_letter;
+use crate::settings::{PieceStyle, PieceStyles};
+
 use shogi::{Color, Piece, PieceType};
 use yew::prelude::*;
 
@@ -8,6 +11,14 @@ pub struct PieceView {
 #[derive(Properties, Clone, PartialEq)]
 pub struct PieceProps {
     pub piece: Option<Piece>,
+    /// Keep the piece's place on the board but don't draw its glyph.
+    #[prop_or_default]
+    pub blindfold: bool,
+    /// Turn the glyph upside down for a viewer sitting on White's side.
+    #[prop_or_default]
+    pub flipped: bool,
+    #[prop_or_default]
+    pub piece_styles: PieceStyles,
 }
 
 impl Component for PieceView {
@@ -50,9 +61,37 @@ impl Component for PieceView {
                 Color::White => "white",
                 Color::Black => "black",
             };
-            html! {
-                <div class=classes!("piece", piece_type_classname, color_name)>
-                </div>
+            let style = match piece.color {
+                Color::Black => self.props.piece_styles.black,
+                Color::White => self.props.piece_styles.white,
+            };
+            let mut piece_classes = classes!("piece", color_name);
+            if self.props.blindfold {
+                piece_classes.push("blindfold");
+            }
+            if self.props.flipped {
+                piece_classes.push("flipped");
+            }
+            match style {
+                PieceStyle::Kanji => {
+                    // The piece type picks out the kanji image.
+                    piece_classes.push(piece_type_classname);
+                    html! {
+                        <div class=piece_classes>
+                        </div>
+                    }
+                }
+                PieceStyle::Letters => {
+                    piece_classes.push("letters");
+                    if piece.piece_type.unpromote().is_some() {
+                        piece_classes.push("promoted");
+                    }
+                    html! {
+                        <div class=piece_classes>
+                            { western_piece_letter(piece.piece_type) }
+                        </div>
+                    }
+                }
             }
         } else {
             html! {}
@@ -0,0 +1,76 @@
+use crate::settings::{AutoPromotePolicy, DropStyle, Notation, Settings};
+
+use gloo::storage::{LocalStorage, Storage};
+use serde::{Deserialize, Serialize};
+use shogi::Color;
+
+const PRESETS_STORAGE_KEY: &str = "presets";
+
+/// A named bundle of game settings for starting a new game in one go.
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct Preset {
+    pub name: String,
+    /// Where the game starts, which is how handicaps are set up.
+    pub starting_sfen: String,
+    /// Whether the board is seen from White's side, i.e. which side the player takes.
+    pub flipped: bool,
+    pub notation: Notation,
+    pub drop_style: DropStyle,
+    pub auto_promote: AutoPromotePolicy,
+    /// The side the computer plays, or `None` for two players. Presets saved before
+    /// this was kept have no opponent.
+    #[serde(default, with = "cpu_color_name")]
+    pub cpu_color: Option<Color>,
+}
+
+/// Stores the computer's side by name, since `Color` has no serde support of its own.
+mod cpu_color_name {
+    use crate::settings::{color_from_name, color_name};
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use shogi::Color;
+
+    pub fn serialize<S: Serializer>(
+        cpu_color: &Option<Color>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        cpu_color.map(color_name).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<Color>, D::Error> {
+        let name: Option<String> = Option::deserialize(deserializer)?;
+        Ok(name.as_deref().and_then(color_from_name))
+    }
+}
+
+impl Preset {
+    pub fn from_settings(name: String, starting_sfen: String, settings: &Settings) -> Self {
+        Self {
+            name,
+            starting_sfen,
+            flipped: settings.flipped,
+            notation: settings.notation,
+            drop_style: settings.drop_style,
+            auto_promote: settings.auto_promote,
+            cpu_color: settings.cpu_color,
+        }
+    }
+
+    pub fn apply_to(&self, settings: &mut Settings) {
+        settings.flipped = self.flipped;
+        settings.notation = self.notation;
+        settings.drop_style = self.drop_style;
+        settings.auto_promote = self.auto_promote;
+        settings.cpu_color = self.cpu_color;
+    }
+}
+
+/// The saved presets, or none if they are missing or can't be read.
+pub fn load_presets() -> Vec<Preset> {
+    LocalStorage::get(PRESETS_STORAGE_KEY).unwrap_or_default()
+}
+
+pub fn save_presets(presets: &[Preset]) {
+    let _ = LocalStorage::set(PRESETS_STORAGE_KEY, presets);
+}
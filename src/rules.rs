@@ -0,0 +1,723 @@
+use crate::usi::move_to_usi;
+use rand::seq::SliceRandom;
+use rand::Rng;
+use shogi::{square::Square, Color, Move, Piece, PieceType, Position};
+use std::collections::HashSet;
+
+/// Where the game stands for the side to move.
+#[derive(Clone, Copy, PartialEq)]
+pub enum GameStatus {
+    Ongoing,
+    Checkmate {
+        winner: Color,
+    },
+    /// The side to move isn't in check but can't move either. Unlike chess this isn't a
+    /// draw: the side to move loses.
+    NoLegalMoves {
+        winner: Color,
+    },
+    /// An impasse (持将棋) was declared and settled by points; no winner is a draw.
+    Impasse {
+        winner: Option<Color>,
+        black_points: u32,
+        white_points: u32,
+    },
+    /// Under the try rule (トライルール), the winner's king reached the square the
+    /// opponent's king starts on.
+    TryWin {
+        winner: Color,
+    },
+}
+
+impl GameStatus {
+    pub fn winner(self) -> Option<Color> {
+        match self {
+            Self::Ongoing => None,
+            Self::Checkmate { winner }
+            | Self::NoLegalMoves { winner }
+            | Self::TryWin { winner } => Some(winner),
+            Self::Impasse { winner, .. } => winner,
+        }
+    }
+
+    pub fn is_over(self) -> bool {
+        self != Self::Ongoing
+    }
+
+    /// How the game ended, as reported to the host page.
+    pub fn result_name(self) -> Option<&'static str> {
+        match self {
+            Self::Ongoing => None,
+            Self::Checkmate { .. } => Some("checkmate"),
+            Self::NoLegalMoves { .. } => Some("no-legal-moves"),
+            Self::Impasse { .. } => Some("impasse"),
+            Self::TryWin { .. } => Some("try"),
+        }
+    }
+}
+
+/// How a declared impasse is settled.
+#[derive(Clone, Copy, PartialEq)]
+pub enum ImpasseRule {
+    /// Both sides count their points; a side with fewer than 24 loses, and the game is
+    /// a draw when both or neither have 24.
+    TwentyFourPoint,
+    /// The side to move declares, and wins with 28 points as Black or 27 as White, at
+    /// least ten pieces besides the king in the opponent's camp, and its king not in
+    /// check. Otherwise the declaration loses.
+    TwentySevenPoint,
+}
+
+pub fn sfen_with_side_to_move(sfen: &str, color: Color) -> String {
+    let side = match color {
+        Color::Black => "b",
+        Color::White => "w",
+    };
+    sfen.split(' ')
+        .enumerate()
+        .map(|(index, field)| if index == 1 { side } else { field })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Whether the move is legal for the side to move. The position is left unchanged.
+pub fn is_legal(position: &mut Position, next_move: Move) -> bool {
+    if position.make_move(next_move).is_ok() {
+        position.unmake_move().unwrap();
+        true
+    } else {
+        false
+    }
+}
+
+/// Whether the piece at `from` can legally move to `to`, with or without promotion.
+/// The sandbox is left unchanged.
+pub fn can_move_between(sandbox_position: &mut Position, from: Square, to: Square) -> bool {
+    [false, true].iter().any(|promote| {
+        is_legal(
+            sandbox_position,
+            Move::Normal {
+                from,
+                to,
+                promote: *promote,
+            },
+        )
+    })
+}
+
+fn can_drop_to(sandbox_position: &mut Position, piece_type: PieceType, to: Square) -> bool {
+    is_legal(sandbox_position, Move::Drop { piece_type, to })
+}
+
+/// How many squares the side to move could legally drop a piece of this type on. The
+/// sandbox is left unchanged.
+pub fn legal_drop_count(sandbox_position: &mut Position, piece_type: PieceType) -> usize {
+    Square::iter()
+        .filter(|to| can_drop_to(sandbox_position, piece_type, *to))
+        .count()
+}
+
+fn own_squares(position: &Position) -> Vec<Square> {
+    let color = position.side_to_move();
+    Square::iter()
+        .filter(|square| {
+            position
+                .piece_at(*square)
+                .filter(|piece| piece.color == color)
+                .is_some()
+        })
+        .collect()
+}
+
+fn held_piece_types(position: &Position) -> Vec<PieceType> {
+    let color = position.side_to_move();
+    PieceType::iter()
+        .filter(|piece_type| piece_type.is_hand_piece())
+        .filter(|piece_type| {
+            position.hand(Piece {
+                piece_type: *piece_type,
+                color,
+            }) > 0
+        })
+        .collect()
+}
+
+/// Every legal move for the side to move, listing both promotion choices where each is
+/// legal. The position is left unchanged.
+pub fn legal_moves(position: &mut Position) -> Vec<Move> {
+    let mut moves = Vec::new();
+    for from in own_squares(position) {
+        for to in Square::iter() {
+            for promote in [false, true] {
+                let next_move = Move::Normal { from, to, promote };
+                if is_legal(position, next_move) {
+                    moves.push(next_move);
+                }
+            }
+        }
+    }
+    for piece_type in held_piece_types(position) {
+        for to in Square::iter() {
+            let next_move = Move::Drop { piece_type, to };
+            if is_legal(position, next_move) {
+                moves.push(next_move);
+            }
+        }
+    }
+    moves
+}
+
+/// Plays up to `plies` random legal moves from `position`, then unmakes them one by one,
+/// checking that each undo gives back exactly the SFEN from before that move. Returns
+/// how many moves were played, or a description of the first undo that went wrong.
+pub fn check_undo_symmetry(
+    position: &Position,
+    plies: usize,
+    rng: &mut impl Rng,
+) -> Result<usize, String> {
+    let mut sandbox_position = Position::new();
+    sandbox_position
+        .set_sfen(&position.to_sfen())
+        .map_err(|err| err.to_string())?;
+    let mut sfens_before = Vec::with_capacity(plies);
+    for _ in 0..plies {
+        let next_move = match legal_moves(&mut sandbox_position).choose(rng) {
+            Some(next_move) => *next_move,
+            None => break,
+        };
+        sfens_before.push(sandbox_position.to_sfen());
+        sandbox_position.make_move(next_move).map_err(|err| {
+            format!(
+                "{} was legal but couldn't be played: {:?}",
+                move_to_usi(next_move),
+                err
+            )
+        })?;
+    }
+    let played = sfens_before.len();
+    while let Some(sfen_before) = sfens_before.pop() {
+        sandbox_position
+            .unmake_move()
+            .map_err(|err| format!("Couldn't undo move {}: {:?}", sfens_before.len() + 1, err))?;
+        let sfen_after = sandbox_position.to_sfen();
+        if sfen_after != sfen_before {
+            return Err(format!(
+                "Undoing move {} gave {} instead of {}",
+                sfens_before.len() + 1,
+                sfen_after,
+                sfen_before
+            ));
+        }
+    }
+    Ok(played)
+}
+
+/// The position after up to `plies` random legal moves from `start`, for practising from
+/// somewhere new. A move that would end the game is taken back and the walk stops there,
+/// so the side to move always has something to play.
+pub fn random_position(start: &Position, plies: usize, rng: &mut impl Rng) -> Position {
+    let mut position = Position::new();
+    position.set_sfen(&start.to_sfen()).unwrap();
+    for _ in 0..plies {
+        let next_move = match legal_moves(&mut position).choose(rng) {
+            Some(next_move) => *next_move,
+            None => break,
+        };
+        if position.make_move(next_move).is_err() {
+            break;
+        }
+        if !has_legal_move(&position) {
+            let _ = position.unmake_move();
+            break;
+        }
+    }
+    position
+}
+
+/// Whether the side to move has any legal move at all, stopping at the first one found.
+pub fn has_legal_move(position: &Position) -> bool {
+    let mut sandbox_position = Position::new();
+    sandbox_position.set_sfen(&position.to_sfen()).unwrap();
+    for from in own_squares(position) {
+        for to in Square::iter() {
+            if can_move_between(&mut sandbox_position, from, to) {
+                return true;
+            }
+        }
+    }
+    for piece_type in held_piece_types(position) {
+        for to in Square::iter() {
+            if can_drop_to(&mut sandbox_position, piece_type, to) {
+                return true;
+            }
+        }
+    }
+    false
+}
+
+pub fn game_status(position: &Position) -> GameStatus {
+    let winner = position.side_to_move().flip();
+    if has_legal_move(position) {
+        GameStatus::Ongoing
+    } else if position.in_check(position.side_to_move()) {
+        GameStatus::Checkmate { winner }
+    } else {
+        GameStatus::NoLegalMoves { winner }
+    }
+}
+
+/// Every file and rank step a line can take from a square.
+const DIRECTIONS: [(i8, i8); 8] = [
+    (-1, -1),
+    (-1, 0),
+    (-1, 1),
+    (0, -1),
+    (0, 1),
+    (1, -1),
+    (1, 0),
+    (1, 1),
+];
+
+/// Whether the piece slides any distance towards the square it attacks, taking steps of
+/// `(file_step, rank_step)`.
+fn slides_along(piece: Piece, file_step: i8, rank_step: i8) -> bool {
+    let is_orthogonal = file_step == 0 || rank_step == 0;
+    match piece.piece_type {
+        PieceType::Rook | PieceType::ProRook => is_orthogonal,
+        PieceType::Bishop | PieceType::ProBishop => !is_orthogonal,
+        // Black moves towards rank 0 and White away from it.
+        PieceType::Lance => {
+            file_step == 0
+                && rank_step
+                    == match piece.color {
+                        Color::Black => -1,
+                        Color::White => 1,
+                    }
+        }
+        _ => false,
+    }
+}
+
+/// The side to move's pieces that stand alone between their king and an enemy piece
+/// sliding towards it, so that moving off that line would expose the king.
+pub fn pinned_squares(position: &Position) -> HashSet<Square> {
+    let color = position.side_to_move();
+    let king = Piece {
+        piece_type: PieceType::King,
+        color,
+    };
+    let mut pinned = HashSet::new();
+    let king_square = match Square::iter().find(|square| position.piece_at(*square).contains(&king))
+    {
+        Some(king_square) => king_square,
+        None => return pinned,
+    };
+    for (file_step, rank_step) in DIRECTIONS {
+        let mut blocker = None;
+        let mut file = king_square.file() as i8;
+        let mut rank = king_square.rank() as i8;
+        loop {
+            file += file_step;
+            rank += rank_step;
+            // Stepping off the low edge wraps around to a large `u8`, also off the board.
+            let square = match Square::new(file as u8, rank as u8) {
+                Some(square) => square,
+                None => break,
+            };
+            let piece = match *position.piece_at(square) {
+                Some(piece) => piece,
+                None => continue,
+            };
+            match blocker {
+                None if piece.color == color => blocker = Some(square),
+                // The attacker moves back along the line, towards the king.
+                Some(blocker)
+                    if piece.color != color && slides_along(piece, -file_step, -rank_step) =>
+                {
+                    pinned.insert(blocker);
+                    break;
+                }
+                _ => break,
+            }
+        }
+    }
+    pinned
+}
+
+/// The squares beside the side to move's king on `from` that it could step to, if not
+/// for walking into check. Empty unless `from` holds that king.
+pub fn king_danger_squares(position: &Position, from: Square) -> HashSet<Square> {
+    let color = position.side_to_move();
+    let king = Piece {
+        piece_type: PieceType::King,
+        color,
+    };
+    if *position.piece_at(from) != Some(king) {
+        return HashSet::new();
+    }
+    let mut sandbox_position = Position::new();
+    sandbox_position.set_sfen(&position.to_sfen()).unwrap();
+    DIRECTIONS
+        .iter()
+        .filter_map(|(file_step, rank_step)| {
+            // Stepping off the low edge wraps around to a large `u8`, also off the board.
+            Square::new(
+                (from.file() as i8 + file_step) as u8,
+                (from.rank() as i8 + rank_step) as u8,
+            )
+        })
+        .filter(|to| {
+            position
+                .piece_at(*to)
+                .map_or(true, |piece| piece.color != color)
+        })
+        .filter(|to| !can_move_between(&mut sandbox_position, from, *to))
+        .collect()
+}
+
+/// The square a king of this color must reach to win by the try rule: the centre of
+/// the opponent's back rank, where the opponent's king starts.
+pub fn try_square(color: Color) -> Square {
+    match color {
+        Color::Black => Square::new(4, 0),
+        Color::White => Square::new(4, 8),
+    }
+    .expect("The centre of the back rank is on the board")
+}
+
+/// The win for the side that just moved, if its king has reached its try square. The
+/// king can't have moved into check, so reaching the square is enough.
+pub fn try_rule_status(position: &Position) -> Option<GameStatus> {
+    let mover = position.side_to_move().flip();
+    let king = Piece {
+        piece_type: PieceType::King,
+        color: mover,
+    };
+    position
+        .piece_at(try_square(mover))
+        .contains(&king)
+        .then(|| GameStatus::TryWin { winner: mover })
+}
+
+/// How many ranks, counted from the far side of the board, a piece of this
+/// type could never move out of again.
+fn dead_ranks(piece_type: PieceType) -> u8 {
+    match piece_type {
+        PieceType::Pawn | PieceType::Lance => 1,
+        PieceType::Knight => 2,
+        _ => 0,
+    }
+}
+
+fn relative_rank(square: Square, color: Color) -> u8 {
+    match color {
+        Color::Black => square.rank(),
+        Color::White => 8 - square.rank(),
+    }
+}
+
+fn impasse_value(piece_type: PieceType) -> u32 {
+    match piece_type {
+        PieceType::King => 0,
+        PieceType::Rook | PieceType::Bishop | PieceType::ProRook | PieceType::ProBishop => 5,
+        _ => 1,
+    }
+}
+
+/// Scores `color`'s pieces for the 27-point impasse (入玉) rule: five points for each
+/// rook or bishop and one for every other piece, counting pieces in hand and pieces in
+/// the opponent's camp. Only scored once `color`'s king has entered that camp itself.
+pub fn impasse_points(position: &Position, color: Color) -> Option<u32> {
+    let in_camp: Vec<Piece> = Square::iter()
+        .filter(|square| relative_rank(*square, color) < 3)
+        .filter_map(|square| *position.piece_at(square))
+        .filter(|piece| piece.color == color)
+        .collect();
+    if !in_camp
+        .iter()
+        .any(|piece| piece.piece_type == PieceType::King)
+    {
+        return None;
+    }
+    let board_points: u32 = in_camp
+        .iter()
+        .map(|piece| impasse_value(piece.piece_type))
+        .sum();
+    let hand_points: u32 = PieceType::iter()
+        .filter(|piece_type| piece_type.is_hand_piece())
+        .map(|piece_type| {
+            impasse_value(piece_type) * position.hand(Piece { piece_type, color }) as u32
+        })
+        .sum();
+    Some(board_points + hand_points)
+}
+
+/// A rough worth of each piece for comparing material, in pawns.
+fn material_value(piece_type: PieceType) -> i32 {
+    match piece_type {
+        PieceType::King => 0,
+        PieceType::Pawn => 1,
+        PieceType::Lance => 3,
+        PieceType::Knight => 4,
+        PieceType::Silver => 5,
+        PieceType::Gold
+        | PieceType::ProPawn
+        | PieceType::ProLance
+        | PieceType::ProKnight
+        | PieceType::ProSilver => 6,
+        PieceType::Bishop => 8,
+        PieceType::Rook => 10,
+        PieceType::ProBishop => 10,
+        PieceType::ProRook => 12,
+    }
+}
+
+/// Black's material minus White's, counting pieces on the board and in hand.
+pub fn material_balance(position: &Position) -> i32 {
+    let signed = |color: Color, value: i32| match color {
+        Color::Black => value,
+        Color::White => -value,
+    };
+    let board: i32 = Square::iter()
+        .filter_map(|square| *position.piece_at(square))
+        .map(|piece| signed(piece.color, material_value(piece.piece_type)))
+        .sum();
+    let hands: i32 = [Color::Black, Color::White]
+        .iter()
+        .flat_map(|color| {
+            PieceType::iter()
+                .filter(|piece_type| piece_type.is_hand_piece())
+                .map(move |piece_type| {
+                    let count = position.hand(Piece {
+                        piece_type,
+                        color: *color,
+                    }) as i32;
+                    signed(*color, material_value(piece_type) * count)
+                })
+        })
+        .sum();
+    board + hands
+}
+
+/// Settles an impasse declared by the side to move under `rule`.
+pub fn declare_impasse(position: &Position, rule: ImpasseRule) -> GameStatus {
+    let black_points = impasse_points(position, Color::Black).unwrap_or(0);
+    let white_points = impasse_points(position, Color::White).unwrap_or(0);
+    let winner = match rule {
+        ImpasseRule::TwentyFourPoint => match (black_points >= 24, white_points >= 24) {
+            (true, false) => Some(Color::Black),
+            (false, true) => Some(Color::White),
+            _ => None,
+        },
+        ImpasseRule::TwentySevenPoint => {
+            let declarer = position.side_to_move();
+            let (points, needed) = match declarer {
+                Color::Black => (black_points, 28),
+                Color::White => (white_points, 27),
+            };
+            let pieces_in_camp = Square::iter()
+                .filter(|square| relative_rank(*square, declarer) < 3)
+                .filter_map(|square| *position.piece_at(square))
+                .filter(|piece| piece.color == declarer && piece.piece_type != PieceType::King)
+                .count();
+            if impasse_points(position, declarer).is_some()
+                && points >= needed
+                && pieces_in_camp >= 10
+                && !position.in_check(declarer)
+            {
+                Some(declarer)
+            } else {
+                Some(declarer.flip())
+            }
+        }
+    };
+    GameStatus::Impasse {
+        winner,
+        black_points,
+        white_points,
+    }
+}
+
+/// Something about a position that stops it from being played from.
+#[derive(Clone, PartialEq)]
+pub enum Diagnosis {
+    /// The SFEN couldn't be read at all.
+    Unparseable(String),
+    /// A pawn, lance or knight on a rank it could never move out of.
+    DeadPiece {
+        piece: Piece,
+        square: Square,
+    },
+    /// Two or more unpromoted pawns of one side on the same file.
+    Nifu {
+        color: Color,
+        file: u8,
+        count: usize,
+    },
+    WrongKingCount {
+        color: Color,
+        count: usize,
+    },
+    /// The side that just moved was left in check.
+    WaitingSideInCheck {
+        color: Color,
+    },
+    /// Pieces in hand are always held unpromoted.
+    PromotedPieceInHand,
+}
+
+impl Diagnosis {
+    /// Whether `repair_sfen` knows how to fix this.
+    pub fn is_repairable(&self) -> bool {
+        matches!(self, Self::PromotedPieceInHand)
+    }
+}
+
+/// Finds the rules of play that the position breaks, which `Position::set_sfen`
+/// doesn't check for.
+pub fn diagnose(position: &Position) -> Vec<Diagnosis> {
+    let mut diagnoses = Vec::new();
+
+    for square in Square::iter() {
+        if let Some(piece) = *position.piece_at(square) {
+            if relative_rank(square, piece.color) < dead_ranks(piece.piece_type) {
+                diagnoses.push(Diagnosis::DeadPiece { piece, square });
+            }
+        }
+    }
+
+    for color in [Color::Black, Color::White] {
+        for file in 0..9 {
+            let count = (0..9)
+                .filter_map(|rank| Square::new(file, rank))
+                .filter(|square| {
+                    position.piece_at(*square).contains(&Piece {
+                        piece_type: PieceType::Pawn,
+                        color,
+                    })
+                })
+                .count();
+            if count > 1 {
+                diagnoses.push(Diagnosis::Nifu { color, file, count });
+            }
+        }
+
+        let count = Square::iter()
+            .filter(|square| {
+                position.piece_at(*square).contains(&Piece {
+                    piece_type: PieceType::King,
+                    color,
+                })
+            })
+            .count();
+        if count != 1 {
+            diagnoses.push(Diagnosis::WrongKingCount { color, count });
+        }
+    }
+
+    let waiting = position.side_to_move().flip();
+    if position.in_check(waiting) {
+        diagnoses.push(Diagnosis::WaitingSideInCheck { color: waiting });
+    }
+
+    let sfen = position.to_sfen();
+    let hand = sfen.split(' ').nth(2).unwrap_or("-");
+    if hand.contains('+') {
+        diagnoses.push(Diagnosis::PromotedPieceInHand);
+    }
+
+    diagnoses
+}
+
+pub fn diagnose_sfen(sfen: &str) -> Vec<Diagnosis> {
+    let mut position = Position::new();
+    match position.set_sfen(sfen) {
+        Ok(()) => diagnose(&position),
+        Err(error) => vec![Diagnosis::Unparseable(error.to_string())],
+    }
+}
+
+/// Makes a best-effort attempt at the nearest playable position, fixing only the
+/// problems that have an obvious fix. Returns `None` if nothing could be fixed.
+pub fn repair_sfen(sfen: &str) -> Option<String> {
+    let diagnoses = diagnose_sfen(sfen);
+    if !diagnoses.iter().any(Diagnosis::is_repairable) {
+        return None;
+    }
+    let repaired = sfen
+        .split(' ')
+        .enumerate()
+        .map(|(index, field)| {
+            if index == 2 {
+                field.replace('+', "")
+            } else {
+                field.to_owned()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ");
+    Some(repaired).filter(|repaired| diagnose_sfen(repaired).len() < diagnoses.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rng::XorShift64;
+    use shogi::bitboard::Factory as BBFactory;
+
+    const STARTING_SFEN: &str = "lnsgkgsnl/1r5b1/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL b - 1";
+
+    fn position_from(sfen: &str) -> Position {
+        BBFactory::init();
+        let mut position = Position::new();
+        position.set_sfen(sfen).unwrap();
+        position
+    }
+
+    #[test]
+    fn starting_position_has_thirty_moves() {
+        let mut position = position_from(STARTING_SFEN);
+        assert_eq!(legal_moves(&mut position).len(), 30);
+    }
+
+    #[test]
+    fn random_moves_undo_cleanly() {
+        let position = position_from(STARTING_SFEN);
+        for seed in 1..=3 {
+            let played = check_undo_symmetry(&position, 40, &mut XorShift64::new(seed)).unwrap();
+            assert!(played > 0);
+        }
+    }
+
+    /// The SFEN after each move of a random walk from `start`, last move first.
+    fn random_walk(start: &Position, seed: u64) -> Vec<String> {
+        let mut position = random_position(start, 20, &mut XorShift64::new(seed));
+        let mut sfens = vec![position.to_sfen()];
+        while position.unmake_move().is_ok() {
+            sfens.push(position.to_sfen());
+        }
+        sfens
+    }
+
+    #[test]
+    fn same_seed_plays_the_same_moves() {
+        let position = position_from(STARTING_SFEN);
+        let walk = random_walk(&position, 7);
+        assert_eq!(walk.len(), 21);
+        assert_eq!(walk, random_walk(&position, 7));
+    }
+
+    #[test]
+    fn bare_king_with_no_moves_loses() {
+        // The gold covers 2a and 2b and the pawn covers 1b, none of them checking 1a.
+        let position = position_from("8k/6G2/8P/9/9/9/9/9/4K4 w - 1");
+        assert!(!position.in_check(Color::White));
+        assert!(!has_legal_move(&position));
+        assert!(
+            game_status(&position)
+                == GameStatus::NoLegalMoves {
+                    winner: Color::Black
+                }
+        );
+    }
+}
@@ -0,0 +1,334 @@
+use crate::i18n::Lang;
+
+use serde::{Deserialize, Serialize};
+use shogi::Color;
+pub use shogi_board::move_intent::CandidateStrategy;
+use shogi_board::rules::ImpasseRule;
+
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Notation {
+    Japanese,
+    Western,
+}
+
+/// How drops are written in Japanese notation.
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum DropStyle {
+    /// Padded to the same width as other moves, e.g. "５五角　　　打".
+    Spaced,
+    /// Without padding, e.g. "５五角打".
+    Compact,
+}
+
+/// What to do when a move may promote but doesn't have to.
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum AutoPromotePolicy {
+    Ask,
+    AlwaysWhenOptional,
+    NeverWhenOptional,
+}
+
+/// What the shareable link carries.
+#[derive(Clone, Copy, PartialEq)]
+pub enum ShareScope {
+    /// Just the position being viewed, in the URL fragment.
+    Position,
+    /// The starting position and every move of the game, so the recipient can step
+    /// through it.
+    Game,
+}
+
+/// Which moves the exported SVG diagram draws arrows for.
+#[derive(Clone, Copy, PartialEq)]
+pub enum DiagramArrows {
+    None,
+    /// The moves or drops of the piece currently chosen to move.
+    SelectedPiece,
+    /// Every board move of the side to move. Drops are left out to keep it readable.
+    AllPieces,
+}
+
+/// How the pieces of one side are drawn.
+#[derive(Clone, Copy, PartialEq)]
+pub enum PieceStyle {
+    /// The traditional kanji pieces.
+    Kanji,
+    /// Western letters such as "P" and "+R", for players still learning the kanji.
+    Letters,
+}
+
+impl PieceStyle {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Kanji => "kanji",
+            Self::Letters => "letters",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "kanji" => Some(Self::Kanji),
+            "letters" => Some(Self::Letters),
+            _ => None,
+        }
+    }
+}
+
+/// Each side's piece style, chosen separately so the two sides are easy to tell apart.
+#[derive(Clone, Copy, PartialEq)]
+pub struct PieceStyles {
+    pub black: PieceStyle,
+    pub white: PieceStyle,
+}
+
+impl Default for PieceStyles {
+    fn default() -> Self {
+        Self {
+            black: PieceStyle::Kanji,
+            white: PieceStyle::Kanji,
+        }
+    }
+}
+
+/// How the history panel arranges its moves.
+#[derive(Clone, Copy, PartialEq)]
+pub enum HistoryLayout {
+    /// One move per row, read top to bottom like a kifu.
+    Vertical,
+    /// Black's and White's moves side by side, one numbered row per pair.
+    TwoColumn,
+}
+
+impl HistoryLayout {
+    pub fn for_numbering(numbering: MoveNumbering) -> Self {
+        match numbering {
+            MoveNumbering::PerPly => Self::Vertical,
+            MoveNumbering::Paired => Self::TwoColumn,
+        }
+    }
+}
+
+/// How moves are counted in the history. Exports always count per ply, since KIF numbers
+/// every ply and USI move lists aren't numbered at all.
+#[derive(Clone, Copy, PartialEq)]
+pub enum MoveNumbering {
+    /// Every ply has its own number, as in KIF.
+    PerPly,
+    /// Black's move and White's reply share a number, as in Western notation.
+    Paired,
+}
+
+impl MoveNumbering {
+    /// The convention that goes with each notation.
+    pub fn for_notation(notation: Notation) -> Self {
+        match notation {
+            Notation::Japanese => Self::PerPly,
+            Notation::Western => Self::Paired,
+        }
+    }
+
+    /// The number shown for the move `turn` plies into a history whose first move has
+    /// the SFEN move count `first_move_number`.
+    pub fn number(self, first_move_number: usize, turn: usize) -> usize {
+        match self {
+            Self::PerPly => first_move_number + turn,
+            Self::Paired => (first_move_number + turn + 1) / 2,
+        }
+    }
+}
+
+/// How a side is written when stored, e.g. for the side the computer plays.
+pub fn color_name(color: Color) -> &'static str {
+    match color {
+        Color::Black => "black",
+        Color::White => "white",
+    }
+}
+
+pub fn color_from_name(name: &str) -> Option<Color> {
+    match name {
+        "black" => Some(Color::Black),
+        "white" => Some(Color::White),
+        _ => None,
+    }
+}
+
+/// Display and gameplay preferences chosen by the user.
+#[derive(Clone, PartialEq)]
+pub struct Settings {
+    /// Tint each square by how many of the side-to-move's pieces can move there.
+    /// Off by default since it tries every move of every piece on each render.
+    pub show_heatmap: bool,
+    /// Highlight where the selected piece can go and which pieces can move.
+    pub show_hints: bool,
+    pub candidate_strategy: CandidateStrategy,
+    pub share_scope: ShareScope,
+    pub diagram_arrows: DiagramArrows,
+    pub mute: bool,
+    pub notation: Notation,
+    pub drop_style: DropStyle,
+    pub piece_styles: PieceStyles,
+    pub auto_promote: AutoPromotePolicy,
+    /// How a declared impasse is settled.
+    pub impasse_rule: ImpasseRule,
+    /// Play the try rule variant, where a king reaching the opponent king's starting
+    /// square wins.
+    pub try_rule: bool,
+    /// Practise tsume against a defender that always plays the reply holding out longest.
+    pub tsume_defence: bool,
+    /// Say why a pawn can't be dropped where it would give checkmate (打ち歩詰め), since
+    /// the square otherwise just isn't offered.
+    pub explain_uchifuzume: bool,
+    /// The side played by the computer, which picks random legal moves.
+    pub cpu_color: Option<Color>,
+    /// How long the computer pauses before replying, so its move can be followed.
+    pub cpu_think_ms: u32,
+    /// Seeds the computer's choices so the same seed plays the same game.
+    pub cpu_seed: Option<u64>,
+    /// Seconds each move may take, counted from when the position was shown, or `None`
+    /// for no limit. Running out only stops the ticking; nobody loses on time.
+    pub byoyomi_seconds: Option<u32>,
+    /// Tick once a second through this many final seconds of the byoyomi.
+    pub byoyomi_tick_seconds: u32,
+    /// View the board from White's side.
+    pub flipped: bool,
+    /// Label the files and ranks around the board, which can be clicked to highlight them.
+    pub show_coordinates: bool,
+    pub show_dots: bool,
+    /// Tint the three far ranks where each side's pieces may promote.
+    pub show_promotion_zones: bool,
+    /// Mark the side to move's pieces that are pinned to their king.
+    pub show_pins: bool,
+    /// Mark where the side not to move could go if it were their turn. Off by default
+    /// since it tries every move of every opposing piece on each render.
+    pub show_opponent_moves: bool,
+    /// Mark the squares a selected king can't step to because they are attacked.
+    pub show_danger: bool,
+    /// Briefly mark where each piece could go next from the square it just moved to.
+    pub preview_mobility: bool,
+    /// Show on each held piece how many squares it could be dropped on. Trying every
+    /// drop is slow, so this is off by default.
+    pub show_drop_counts: bool,
+    /// Dim the pieces of the side not to move, so beginners can focus on their own.
+    pub focus_side: bool,
+    /// Mark highlights with borders and patterns instead of relying on color alone.
+    pub high_contrast: bool,
+    /// Ask for a second click before Restart throws away a game in progress.
+    pub confirm_restart: bool,
+    /// Ask before a move played at a past ply throws away the moves that followed it.
+    /// There are no sidelines to branch into yet, so overwriting is the only option.
+    pub confirm_overwrite: bool,
+    /// Refuse positions from links that break the rules of play, rather than loading
+    /// anything the SFEN parser accepts.
+    pub strict_url_positions: bool,
+    /// Copy the shareable link to the clipboard after every move. Off by default since
+    /// writing to the clipboard unasked can get in the way.
+    pub auto_copy_link: bool,
+    /// How many moves before the last one leave a fading highlight on their destination.
+    pub trail_length: usize,
+    /// Scroll the history to each new move as it is played.
+    pub follow_game: bool,
+    /// How many moves each row of the vertical history holds, reading left to right,
+    /// or `None` to fit as many as the panel is wide enough for.
+    pub history_columns: Option<usize>,
+    /// How the history numbers moves, or `None` to follow the notation's convention.
+    pub move_numbering: Option<MoveNumbering>,
+    /// Show internals such as the list of legal moves, for debugging.
+    pub show_debug: bool,
+    /// While debugging, label each square with its index and file/rank.
+    pub show_square_indices: bool,
+    /// How thick the grid lines between squares are.
+    pub square_border_px: u32,
+    /// Space left around each piece inside its square, for piece sets whose glyphs
+    /// don't fill the square the way the lishogi ones do.
+    pub piece_inset_px: u32,
+    /// Summarise the difference between the two hands next to them.
+    pub show_hand_balance: bool,
+    /// List the captures of the game so far, apart from the full history.
+    pub show_capture_log: bool,
+    /// Hide the pieces on the board for visualisation practice.
+    pub blindfold: bool,
+    pub blindfold_reveal_on_hover: bool,
+    /// The language of the interface.
+    pub lang: Lang,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            show_heatmap: false,
+            show_hints: true,
+            candidate_strategy: CandidateStrategy::Eager,
+            share_scope: ShareScope::Position,
+            diagram_arrows: DiagramArrows::SelectedPiece,
+            mute: false,
+            notation: Notation::Japanese,
+            drop_style: DropStyle::Spaced,
+            piece_styles: PieceStyles::default(),
+            auto_promote: AutoPromotePolicy::Ask,
+            impasse_rule: ImpasseRule::TwentySevenPoint,
+            try_rule: false,
+            tsume_defence: false,
+            explain_uchifuzume: true,
+            cpu_color: None,
+            cpu_think_ms: 500,
+            cpu_seed: None,
+            byoyomi_seconds: None,
+            byoyomi_tick_seconds: 10,
+            flipped: false,
+            show_coordinates: false,
+            show_dots: true,
+            show_promotion_zones: false,
+            show_pins: false,
+            show_opponent_moves: false,
+            show_danger: false,
+            preview_mobility: false,
+            show_drop_counts: false,
+            focus_side: false,
+            high_contrast: false,
+            confirm_restart: true,
+            confirm_overwrite: true,
+            strict_url_positions: false,
+            auto_copy_link: false,
+            trail_length: 0,
+            follow_game: true,
+            history_columns: None,
+            move_numbering: None,
+            show_debug: false,
+            show_square_indices: false,
+            square_border_px: 1,
+            piece_inset_px: 0,
+            show_hand_balance: false,
+            show_capture_log: false,
+            blindfold: false,
+            blindfold_reveal_on_hover: false,
+            lang: Lang::English,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn per_ply_numbers_every_move() {
+        let numbers: Vec<usize> = (0..4)
+            .map(|turn| MoveNumbering::PerPly.number(1, turn))
+            .collect();
+        assert_eq!(numbers, vec![1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn paired_numbers_a_move_and_its_reply_together() {
+        let numbers: Vec<usize> = (0..4)
+            .map(|turn| MoveNumbering::Paired.number(1, turn))
+            .collect();
+        assert_eq!(numbers, vec![1, 1, 2, 2]);
+        // A game where White moves first pairs White's move with Black's next one.
+        let numbers: Vec<usize> = (0..3)
+            .map(|turn| MoveNumbering::Paired.number(2, turn))
+            .collect();
+        assert_eq!(numbers, vec![1, 2, 2]);
+    }
+}
@@ -1,8 +1,15 @@
+use crate::i18n::{t, Lang};
+
 use gloo::timers::callback::Timeout;
+use js_sys::Date;
 use wasm_bindgen::{prelude::Closure, JsCast, JsValue};
-use yew::web_sys::HtmlInputElement;
+use yew::web_sys::{HtmlInputElement, HtmlTextAreaElement};
 use yew::{prelude::*, utils::window};
 
+/// Copies made on their own show their message at most this often, so a run of quick
+/// moves doesn't keep flashing it.
+const AUTO_COPY_MESSAGE_INTERVAL_MS: f64 = 5000.0;
+
 enum UserMessage {
     CopySuccess,
     CopyFailure,
@@ -13,12 +20,17 @@ pub struct ShareableLink {
     link: ComponentLink<Self>,
     user_message_shown: Option<Timeout>,
     user_message: Option<UserMessage>,
+    /// Whether the copy in progress was made on its own rather than by the Copy button.
+    copying_automatically: bool,
+    /// When a message was last shown for an automatic copy.
+    last_auto_copy_message: Option<f64>,
     on_copy_success: Closure<dyn FnMut(JsValue)>,
     on_copy_failure: Closure<dyn FnMut(JsValue)>,
 }
 
 pub enum Msg {
     CopyLink,
+    AutoCopyLink,
     ShowSuccess,
     ShowFailure,
     HideMessage,
@@ -26,10 +38,63 @@ pub enum Msg {
 
 #[derive(Properties, Clone, PartialEq)]
 pub struct ShareableLinkProps {
+    pub id: String,
+    pub label: String,
     pub link_to_share: String,
+    #[prop_or_default]
+    pub lang: Lang,
+    /// Copy the link to the clipboard each time it changes.
+    #[prop_or_default]
+    pub auto_copy: bool,
+    /// Show the text in a box that keeps its line breaks, for exports rather than links.
+    #[prop_or_default]
+    pub multiline: bool,
+}
+
+impl ShareableLinkProps {
+    /// Whether a message about copying this link would be misleading next to `new`.
+    fn outdates_message(&self, new: &Self) -> bool {
+        self.link_to_share != new.link_to_share
+    }
+
+    /// The messages shown after copying succeeds and after it fails.
+    fn copy_messages(&self) -> (&'static str, &'static str) {
+        if self.multiline {
+            ("Copied!", "Sorry, that wasn’t copied")
+        } else {
+            ("Link copied!", "Sorry, link wasn’t copied")
+        }
+    }
 }
 
 impl ShareableLink {
+    fn copy_link(&self) {
+        if let Some(clipboard) = window().navigator().clipboard() {
+            let _ = clipboard
+                .write_text(&self.props.link_to_share)
+                .then(&self.on_copy_success)
+                .catch(&self.on_copy_failure);
+        }
+    }
+
+    fn show_message(&mut self, message: UserMessage) {
+        if self.copying_automatically {
+            let now = Date::now();
+            if self
+                .last_auto_copy_message
+                .map_or(false, |shown| now - shown < AUTO_COPY_MESSAGE_INTERVAL_MS)
+            {
+                return;
+            }
+            self.last_auto_copy_message = Some(now);
+        }
+        if let Some(existing_timeout) = self.user_message_shown.take() {
+            existing_timeout.cancel();
+        }
+        self.user_message = Some(message);
+        self.user_message_shown = Some(self.create_hide_message_timeout());
+    }
+
     fn create_hide_message_timeout(&self) -> Timeout {
         let link = self.link.clone();
         Timeout::new(1000, move || {
@@ -50,6 +115,8 @@ impl Component for ShareableLink {
             link,
             user_message: None,
             user_message_shown: None,
+            copying_automatically: false,
+            last_auto_copy_message: None,
             on_copy_success: Closure::wrap(Box::new(move |_| {
                 link_clone_for_copy_success.send_message(Msg::ShowSuccess);
             })),
@@ -62,27 +129,15 @@ impl Component for ShareableLink {
     fn update(&mut self, msg: Self::Message) -> ShouldRender {
         match msg {
             Msg::CopyLink => {
-                if let Some(clipboard) = window().navigator().clipboard() {
-                    let _ = clipboard
-                        .write_text(&self.props.link_to_share)
-                        .then(&self.on_copy_success)
-                        .catch(&self.on_copy_failure);
-                }
+                self.copying_automatically = false;
+                self.copy_link();
             }
-            Msg::ShowSuccess => {
-                if let Some(existing_timeout) = self.user_message_shown.take() {
-                    existing_timeout.cancel();
-                }
-                self.user_message = Some(UserMessage::CopySuccess);
-                self.user_message_shown = Some(self.create_hide_message_timeout());
-            }
-            Msg::ShowFailure => {
-                if let Some(existing_timeout) = self.user_message_shown.take() {
-                    existing_timeout.cancel();
-                }
-                self.user_message = Some(UserMessage::CopyFailure);
-                self.user_message_shown = Some(self.create_hide_message_timeout());
+            Msg::AutoCopyLink => {
+                self.copying_automatically = true;
+                self.copy_link();
             }
+            Msg::ShowSuccess => self.show_message(UserMessage::CopySuccess),
+            Msg::ShowFailure => self.show_message(UserMessage::CopyFailure),
             Msg::HideMessage => {
                 self.user_message_shown = None;
             }
@@ -93,6 +148,14 @@ impl Component for ShareableLink {
 
     fn change(&mut self, props: Self::Properties) -> ShouldRender {
         if self.props != props {
+            if self.props.outdates_message(&props) {
+                if let Some(existing_timeout) = self.user_message_shown.take() {
+                    existing_timeout.cancel();
+                }
+                if props.auto_copy {
+                    self.link.send_message(Msg::AutoCopyLink);
+                }
+            }
             self.props = props;
             true
         } else {
@@ -101,6 +164,7 @@ impl Component for ShareableLink {
     }
 
     fn view(&self) -> Html {
+        let (copy_success, copy_failure) = self.props.copy_messages();
         let (user_message_classes, user_message_text) = if let Some(message) = &self.user_message {
             let hidden_class = if self.user_message_shown.is_some() {
                 classes!()
@@ -108,19 +172,52 @@ impl Component for ShareableLink {
                 classes!("hidden")
             };
             match message {
-                UserMessage::CopySuccess => (classes!(hidden_class, "success"), "Link copied!"),
+                UserMessage::CopySuccess => (
+                    classes!(hidden_class, "success"),
+                    t(copy_success, self.props.lang),
+                ),
                 UserMessage::CopyFailure => (
                     classes!(hidden_class, "failure"),
-                    "Sorry, link wasn’t copied",
+                    t(copy_failure, self.props.lang),
                 ),
             }
         } else {
             (classes!("hidden"), "")
         };
+        let text = if self.props.multiline {
+            html! {
+                <textarea
+                    id=self.props.id.clone()
+                    readonly=true
+                    onclick=Callback::from(|event: MouseEvent| {
+                        event.target()
+                            .and_then(|target| target.dyn_into::<HtmlTextAreaElement>().ok())
+                            .map(|textarea| textarea.select());
+                    })
+                    value=self.props.link_to_share.clone()
+                />
+            }
+        } else {
+            html! {
+                <input
+                    id=self.props.id.clone()
+                    type="text"
+                    readonly=true
+                    onclick=Callback::from(|event: MouseEvent| {
+                        event.target()
+                            .and_then(|target| target.dyn_into::<HtmlInputElement>().ok())
+                            .map(|input| {
+                                let _ = input.set_selection_range(0, input.value().len() as u32);
+                            });
+                    })
+                    value=self.props.link_to_share.clone()
+                />
+            }
+        };
         html! {
             <div class="share">
-                <label for="shareable-link">
-                    {"Shareable link"}
+                <label for=self.props.id.clone()>
+                    {&self.props.label}
                     {
                         html!{
                             <span class=user_message_classes>{user_message_text}</span>
@@ -128,24 +225,53 @@ impl Component for ShareableLink {
                     }
                 </label>
                 <div>
-                    <input
-                        id="shareable-link"
-                        type="text"
-                        readonly=true
-                        onclick=Callback::from(|event: MouseEvent| {
-                            event.target()
-                                .and_then(|target| target.dyn_into::<HtmlInputElement>().ok())
-                                .map(|input| {
-                                    let _ = input.set_selection_range(0, input.value().len() as u32);
-                                });
-                        })
-                        value=self.props.link_to_share.clone()
-                    />
+                    { text }
                     <button onclick=self.link.callback(|_| Msg::CopyLink)>
-                        {"Copy"}
+                        { t("Copy", self.props.lang) }
                     </button>
                 </div>
             </div>
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn props(link_to_share: &str) -> ShareableLinkProps {
+        ShareableLinkProps {
+            id: "link".to_owned(),
+            label: "Shareable link".to_owned(),
+            link_to_share: link_to_share.to_owned(),
+            lang: Lang::English,
+            auto_copy: false,
+            multiline: false,
+        }
+    }
+
+    #[test]
+    fn new_link_hides_the_message() {
+        assert!(props("#a").outdates_message(&props("#b")));
+    }
+
+    #[test]
+    fn other_prop_changes_keep_the_message() {
+        let relabelled = ShareableLinkProps {
+            label: "共有リンク".to_owned(),
+            lang: Lang::Japanese,
+            ..props("#a")
+        };
+        assert!(!props("#a").outdates_message(&relabelled));
+    }
+
+    #[test]
+    fn exports_are_not_called_links_when_copied() {
+        let export = ShareableLinkProps {
+            multiline: true,
+            ..props("1 7g7f\n2 3c3d")
+        };
+        assert_eq!(export.copy_messages().0, "Copied!");
+        assert_eq!(props("#a").copy_messages().0, "Link copied!");
+    }
+}
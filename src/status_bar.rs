@@ -0,0 +1,99 @@
+use crate::i18n::{t, t_fill, Lang};
+
+use shogi::Color;
+use shogi_board::kanji::side_mark;
+use shogi_board::rules::GameStatus;
+use yew::prelude::*;
+
+/// A one-line summary of where the game stands, leaving out whatever isn't known.
+pub struct StatusBar {
+    props: StatusBarProps,
+}
+
+#[derive(Properties, Clone, PartialEq)]
+pub struct StatusBarProps {
+    pub side_to_move: Color,
+    /// The SFEN move count of the move about to be played.
+    pub move_number: usize,
+    /// Black's material minus White's, if it is worth showing.
+    #[prop_or_default]
+    pub material_balance: Option<i32>,
+    #[prop_or_default]
+    pub opening: Option<String>,
+    pub status: GameStatus,
+    #[prop_or_default]
+    pub lang: Lang,
+}
+
+impl StatusBar {
+    fn result_text(&self) -> Option<String> {
+        let reason = match self.props.status {
+            GameStatus::Ongoing => return None,
+            GameStatus::Checkmate { .. } => "Checkmate",
+            GameStatus::NoLegalMoves { .. } => "No legal moves",
+            GameStatus::Impasse { .. } => "Impasse",
+            GameStatus::TryWin { .. } => "Try",
+        };
+        let reason = t(reason, self.props.lang);
+        Some(match self.props.status.winner() {
+            Some(Color::Black) => t_fill("{}, Black wins", self.props.lang, reason),
+            Some(Color::White) => t_fill("{}, White wins", self.props.lang, reason),
+            None => t_fill("{}, draw", self.props.lang, reason),
+        })
+    }
+}
+
+impl Component for StatusBar {
+    type Message = ();
+    type Properties = StatusBarProps;
+
+    fn create(props: Self::Properties, _link: ComponentLink<Self>) -> Self {
+        Self { props }
+    }
+
+    fn update(&mut self, _msg: Self::Message) -> ShouldRender {
+        false
+    }
+
+    fn change(&mut self, props: Self::Properties) -> ShouldRender {
+        let changed = self.props != props;
+        self.props = props;
+        changed
+    }
+
+    fn view(&self) -> Html {
+        let lang = self.props.lang;
+        let side_to_move = match self.props.side_to_move {
+            Color::Black => t("Black to move", lang),
+            Color::White => t("White to move", lang),
+        };
+        html! {
+            <div class="status-bar" aria-live="polite">
+                <span>{ format!("{} {}", side_mark(self.props.side_to_move), side_to_move) }</span>
+                <span>{ t_fill("Move {}", lang, self.props.move_number) }</span>
+                {
+                    match self.props.material_balance {
+                        Some(balance) => html! {
+                            <span title=t("Black's material minus White's", lang)>
+                                { t_fill("Material {}", lang, format!("{:+}", balance)) }
+                            </span>
+                        },
+                        None => html! {},
+                    }
+                }
+                {
+                    match &self.props.opening {
+                        Some(opening) => html! { <span>{ opening }</span> },
+                        None => html! {},
+                    }
+                }
+                {
+                    match self.result_text() {
+                        Some(result) => html! { <span class="status-result">{ result }</span> },
+                        None => html! {},
+                    }
+                }
+            </div>
+        }
+    }
+}
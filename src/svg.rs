@@ -0,0 +1,203 @@
+use crate::kanji::{bod_piece_kanji, side_mark};
+
+use shogi::{square::Square, Color, Piece, PieceType, Position};
+
+/// The exported diagram's own scale, independent of the board on screen.
+const SQUARE_SIZE_PX: u32 = 40;
+const MARGIN_PX: u32 = 10;
+/// The strip above and below the board listing each side's pieces in hand.
+const HAND_HEIGHT_PX: u32 = 30;
+const ARROW_COLOR: &str = "#c0392b";
+
+/// The top-left corner of the square, drawn the way the board faces.
+fn square_origin(square: Square, flipped: bool) -> (u32, u32) {
+    let (column, row) = if flipped {
+        (square.file(), 8 - square.rank())
+    } else {
+        (8 - square.file(), square.rank())
+    };
+    (
+        MARGIN_PX + column as u32 * SQUARE_SIZE_PX,
+        HAND_HEIGHT_PX + MARGIN_PX + row as u32 * SQUARE_SIZE_PX,
+    )
+}
+
+fn square_center(square: Square, flipped: bool) -> (u32, u32) {
+    let (x, y) = square_origin(square, flipped);
+    (x + SQUARE_SIZE_PX / 2, y + SQUARE_SIZE_PX / 2)
+}
+
+/// The side's pieces in hand, e.g. "☗ 飛 歩3", or just the mark if it holds none.
+fn hand_text(position: &Position, color: Color) -> String {
+    let mut text = side_mark(color).to_owned();
+    for piece_type in [
+        PieceType::Rook,
+        PieceType::Bishop,
+        PieceType::Gold,
+        PieceType::Silver,
+        PieceType::Knight,
+        PieceType::Lance,
+        PieceType::Pawn,
+    ] {
+        let count = position.hand(Piece { piece_type, color });
+        if count > 0 {
+            text.push(' ');
+            text.push_str(bod_piece_kanji(piece_type));
+            if count > 1 {
+                text.push_str(&count.to_string());
+            }
+        }
+    }
+    text
+}
+
+/// Draws the position from Black's side as a standalone SVG, without arrows. Needs no
+/// running app, so any SFEN can be rendered, though the app itself draws its live
+/// position directly.
+pub fn sfen_to_svg(sfen: &str) -> Result<String, String> {
+    let mut position = Position::new();
+    position.set_sfen(sfen).map_err(|err| err.to_string())?;
+    Ok(position_to_svg(&position, false, &[], &[]))
+}
+
+/// Draws the board and both hands as a standalone SVG, with an arrow for each
+/// `(from, to)` in `arrows` and a dot on each square in `drops`.
+pub fn position_to_svg(
+    position: &Position,
+    flipped: bool,
+    arrows: &[(Square, Square)],
+    drops: &[Square],
+) -> String {
+    let width = SQUARE_SIZE_PX * 9 + MARGIN_PX * 2;
+    let height = width + HAND_HEIGHT_PX * 2;
+    let grid_top = HAND_HEIGHT_PX + MARGIN_PX;
+    let mut svg = format!(
+        concat!(
+            r##"<svg xmlns="http://www.w3.org/2000/svg" width="{width}" height="{height}" viewBox="0 0 {width} {height}">"##,
+            r##"<defs><marker id="arrow-head" markerWidth="4" markerHeight="4" refX="2" refY="2" orient="auto">"##,
+            r##"<path d="M0,0 L4,2 L0,4 z" fill="{color}" /></marker></defs>"##,
+            r##"<rect width="{width}" height="{height}" fill="#f3cd95" />"##,
+        ),
+        width = width,
+        height = height,
+        color = ARROW_COLOR,
+    );
+    for line in 0..=9 {
+        let offset = line * SQUARE_SIZE_PX;
+        svg.push_str(&format!(
+            r##"<line x1="{x}" y1="{start}" x2="{x}" y2="{end}" stroke="#523e1e" />"##,
+            x = MARGIN_PX + offset,
+            start = grid_top,
+            end = grid_top + SQUARE_SIZE_PX * 9,
+        ));
+        svg.push_str(&format!(
+            r##"<line x1="{start}" y1="{y}" x2="{end}" y2="{y}" stroke="#523e1e" />"##,
+            y = grid_top + offset,
+            start = MARGIN_PX,
+            end = MARGIN_PX + SQUARE_SIZE_PX * 9,
+        ));
+    }
+    // The far side's hand goes above the board and the near side's below it.
+    let (far_side, near_side) = if flipped {
+        (Color::Black, Color::White)
+    } else {
+        (Color::White, Color::Black)
+    };
+    for (color, y) in [
+        (far_side, HAND_HEIGHT_PX / 2),
+        (near_side, height - HAND_HEIGHT_PX / 2),
+    ] {
+        svg.push_str(&format!(
+            r##"<text x="{}" y="{}" font-size="16" dominant-baseline="central">{}</text>"##,
+            MARGIN_PX,
+            y,
+            hand_text(position, color)
+        ));
+    }
+    for square in Square::iter() {
+        if let Some(piece) = *position.piece_at(square) {
+            let (x, y) = square_center(square, flipped);
+            // Each side's pieces point away from it, so the far side's are upside down.
+            let transform = if piece.color == far_side {
+                format!(r##" transform="rotate(180 {} {})""##, x, y)
+            } else {
+                String::new()
+            };
+            svg.push_str(&format!(
+                r##"<text x="{}" y="{}" font-size="26" text-anchor="middle" dominant-baseline="central"{}>{}</text>"##,
+                x,
+                y,
+                transform,
+                bod_piece_kanji(piece.piece_type)
+            ));
+        }
+    }
+    for &(from, to) in arrows {
+        let (x1, y1) = square_center(from, flipped);
+        let (x2, y2) = square_center(to, flipped);
+        svg.push_str(&format!(
+            r##"<line x1="{}" y1="{}" x2="{}" y2="{}" stroke="{}" stroke-width="3" stroke-opacity=".7" marker-end="url(#arrow-head)" />"##,
+            x1, y1, x2, y2, ARROW_COLOR
+        ));
+    }
+    for &to in drops {
+        let (x, y) = square_center(to, flipped);
+        svg.push_str(&format!(
+            r##"<circle cx="{}" cy="{}" r="6" fill="{}" fill-opacity=".7" />"##,
+            x, y, ARROW_COLOR
+        ));
+    }
+    svg.push_str("</svg>");
+    svg
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use shogi::bitboard::Factory as BBFactory;
+
+    const STARTING_SFEN: &str = "lnsgkgsnl/1r5b1/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL b - 1";
+
+    fn piece_count(svg: &str) -> usize {
+        svg.matches(r#"font-size="26""#).count()
+    }
+
+    #[test]
+    fn draws_the_starting_position() {
+        BBFactory::init();
+        let svg = sfen_to_svg(STARTING_SFEN).unwrap();
+        assert!(svg.starts_with("<svg "));
+        assert!(svg.ends_with("</svg>"));
+        assert_eq!(piece_count(&svg), 40);
+        // White's twenty pieces are turned to face Black.
+        assert_eq!(svg.matches("rotate(180").count(), 20);
+        assert!(svg.contains(">☗</text>"));
+        assert!(svg.contains(">☖</text>"));
+        assert!(!svg.contains("marker-end"));
+    }
+
+    #[test]
+    fn draws_hands_and_arrows_mid_game() {
+        BBFactory::init();
+        // After 7g7f 3c3d 8h2b+ 3a2b, each side holds the other's bishop.
+        let sfen = "lnsgkg1nl/1r5s1/pppppp1pp/6p2/9/2P6/PP1PPPPPP/7R1/LNSGKGSNL b Bb 5";
+        let svg = sfen_to_svg(sfen).unwrap();
+        assert_eq!(piece_count(&svg), 38);
+        assert!(svg.contains(">☗ 角</text>"));
+        assert!(svg.contains(">☖ 角</text>"));
+
+        let mut position = Position::new();
+        position.set_sfen(sfen).unwrap();
+        let from = Square::new(1, 7).unwrap();
+        let to = Square::new(1, 2).unwrap();
+        let svg = position_to_svg(&position, false, &[(from, to)], &[to]);
+        assert_eq!(svg.matches("marker-end").count(), 1);
+        assert_eq!(svg.matches("<circle").count(), 1);
+    }
+
+    #[test]
+    fn refuses_an_unreadable_sfen() {
+        BBFactory::init();
+        assert!(sfen_to_svg("not a position").is_err());
+    }
+}
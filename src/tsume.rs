@@ -0,0 +1,138 @@
+use shogi::{Move, Position};
+use shogi_board::rules::legal_moves;
+
+/// How many positions one search may look at. The search runs on the main thread, so
+/// this keeps a deep or wide position from freezing the page; a search that runs out
+/// reports no mate rather than a wrong one.
+const NODE_BUDGET: usize = 20_000;
+
+/// A mate search that gives up once it has looked at its share of positions.
+struct Search {
+    nodes_left: usize,
+}
+
+impl Search {
+    fn new() -> Self {
+        Self {
+            nodes_left: NODE_BUDGET,
+        }
+    }
+
+    /// Counts one more position looked at, or returns `false` if the budget is spent.
+    fn visit(&mut self) -> bool {
+        match self.nodes_left.checked_sub(1) {
+            Some(nodes_left) => {
+                self.nodes_left = nodes_left;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Whether the side to move can force checkmate within `plies`, checking on every
+    /// move as tsume problems require.
+    fn attacker_mates(&mut self, position: &mut Position, plies: u8) -> bool {
+        if !self.visit() {
+            return false;
+        }
+        let attacker = position.side_to_move();
+        for attacking_move in legal_moves(position) {
+            position.make_move(attacking_move).unwrap();
+            let mates =
+                position.in_check(attacker.flip()) && self.defender_loses(position, plies - 1);
+            position.unmake_move().unwrap();
+            if mates {
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Whether every defence of the side to move runs into mate within `plies`.
+    fn defender_loses(&mut self, position: &mut Position, plies: u8) -> bool {
+        if !self.visit() {
+            return false;
+        }
+        let replies = legal_moves(position);
+        if replies.is_empty() {
+            return true;
+        }
+        if plies == 0 {
+            return false;
+        }
+        for reply in replies {
+            position.make_move(reply).unwrap();
+            let mates = self.attacker_mates(position, plies - 1);
+            position.unmake_move().unwrap();
+            if !mates {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+/// The reply for the side to move that holds out longest, along with how many plies the
+/// attacker then needs to mate, or `None` if no mate turns up for the reply within
+/// `max_plies`, counting the attacker's move just played and the reply. Replies with
+/// no mate found are preferred, then the longest mate. The replies share one node budget,
+/// so a wide position makes the search give up rather than stall, and a `None` may hide
+/// a mate that is there. Returns `None` with no legal reply.
+pub fn best_defence(position: &Position, max_plies: u8) -> Option<(Move, Option<u8>)> {
+    let mut sandbox_position = Position::new();
+    sandbox_position.set_sfen(&position.to_sfen()).unwrap();
+    let mut search = Search::new();
+    let mut best: Option<(Move, Option<u8>)> = None;
+    for reply in legal_moves(&mut sandbox_position) {
+        sandbox_position.make_move(reply).unwrap();
+        let mate = (1..max_plies)
+            .step_by(2)
+            .find(|plies| search.attacker_mates(&mut sandbox_position, *plies));
+        sandbox_position.unmake_move().unwrap();
+        if mate.is_none() {
+            return Some((reply, None));
+        }
+        if best.map_or(true, |(_, best_mate)| mate > best_mate) {
+            best = Some((reply, mate));
+        }
+    }
+    best
+}
+
+/// The length in plies of the shortest forced mate for the side to move, searching no
+/// deeper than `max_plies`. Gives `None` when no mate turns up within `max_plies` or
+/// within the node budget, so it may miss a mate that is there.
+pub fn mate_in(position: &Position, max_plies: u8) -> Option<u8> {
+    let mut sandbox_position = Position::new();
+    sandbox_position.set_sfen(&position.to_sfen()).unwrap();
+    let mut search = Search::new();
+    (1..=max_plies)
+        .step_by(2)
+        .find(|plies| search.attacker_mates(&mut sandbox_position, *plies))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use shogi::bitboard::Factory as BBFactory;
+
+    fn position_from(sfen: &str) -> Position {
+        BBFactory::init();
+        let mut position = Position::new();
+        position.set_sfen(sfen).unwrap();
+        position
+    }
+
+    #[test]
+    fn finds_a_mate_in_one() {
+        let position = position_from("4k4/9/4G4/9/9/9/9/9/4K4 b G 1");
+        assert_eq!(mate_in(&position, 7), Some(1));
+    }
+
+    #[test]
+    fn gives_up_on_the_starting_position() {
+        let position =
+            position_from("lnsgkgsnl/1r5b1/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL b - 1");
+        assert_eq!(mate_in(&position, 7), None);
+    }
+}
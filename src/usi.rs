@@ -0,0 +1,86 @@
+use shogi::{square::Square, Move, PieceType};
+
+/// Parses a square written as a file digit followed by a rank letter, e.g. "7f".
+pub fn parse_square(text: &str) -> Option<Square> {
+    let mut chars = text.trim().chars();
+    let file = chars.next()?.to_digit(10)?.checked_sub(1)?;
+    let rank = (chars.next()?.to_ascii_lowercase() as u32).checked_sub('a' as u32)?;
+    if chars.next().is_some() {
+        return None;
+    }
+    Square::new(file as u8, rank as u8)
+}
+
+pub fn square_to_usi(square: Square) -> String {
+    format!("{}{}", square.file() + 1, (b'a' + square.rank()) as char)
+}
+
+fn piece_type_letter(piece_type: PieceType) -> Option<char> {
+    match piece_type {
+        PieceType::Rook => Some('R'),
+        PieceType::Bishop => Some('B'),
+        PieceType::Gold => Some('G'),
+        PieceType::Silver => Some('S'),
+        PieceType::Knight => Some('N'),
+        PieceType::Lance => Some('L'),
+        PieceType::Pawn => Some('P'),
+        _ => None,
+    }
+}
+
+fn piece_type_from_letter(letter: char) -> Option<PieceType> {
+    match letter {
+        'R' => Some(PieceType::Rook),
+        'B' => Some(PieceType::Bishop),
+        'G' => Some(PieceType::Gold),
+        'S' => Some(PieceType::Silver),
+        'N' => Some(PieceType::Knight),
+        'L' => Some(PieceType::Lance),
+        'P' => Some(PieceType::Pawn),
+        _ => None,
+    }
+}
+
+/// Parses a move in USI notation, e.g. "7g7f", "8h2b+" or "P*5e".
+pub fn parse_move(text: &str) -> Option<Move> {
+    let text = text.trim();
+    if let Some((piece, to)) = text.split_once('*') {
+        let mut piece_chars = piece.chars();
+        let piece_type = piece_type_from_letter(piece_chars.next()?)?;
+        if piece_chars.next().is_some() {
+            return None;
+        }
+        return Some(Move::Drop {
+            piece_type,
+            to: parse_square(to)?,
+        });
+    }
+    let (squares, promote) = match text.strip_suffix('+') {
+        Some(squares) => (squares, true),
+        None => (text, false),
+    };
+    if squares.len() != 4 || !squares.is_char_boundary(2) {
+        return None;
+    }
+    Some(Move::Normal {
+        from: parse_square(&squares[..2])?,
+        to: parse_square(&squares[2..])?,
+        promote,
+    })
+}
+
+pub fn move_to_usi(next_move: Move) -> String {
+    match next_move {
+        Move::Normal { from, to, promote } => format!(
+            "{}{}{}",
+            square_to_usi(from),
+            square_to_usi(to),
+            if promote { "+" } else { "" }
+        ),
+        Move::Drop { to, piece_type } => format!(
+            "{}*{}",
+            piece_type_letter(piece_type).expect("Only hand pieces can be dropped"),
+            square_to_usi(to)
+        ),
+    }
+}